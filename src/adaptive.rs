@@ -0,0 +1,140 @@
+//! Adaptive sampling, which stops sampling pixels once their radiance is known well enough.
+use crate::color::Color;
+
+/// Mean luminance below which pixels are held to the precision of pixels that bright, so
+/// that noise too dark to be seen does not take all the samples.
+const DARK: f64 = 0.01;
+
+/// Stopping rule for the samples of a pixel.
+///
+/// A pixel is sampled until the 95% confidence interval of its mean luminance is narrower
+/// than `threshold` times the mean on either side, but at least `min_samples` times and
+/// at most as many times as the samples per pixel of the render.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: i32,
+    /// Half-width of the confidence interval relative to the mean.
+    pub threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub const DEFAULT_MIN_SAMPLES: i32 = 16;
+
+    /// Whether the pixel of `estimate` needs no more samples.
+    pub fn converged(&self, estimate: &Estimate) -> bool {
+        if estimate.count() < self.min_samples.max(2) as usize {
+            return false;
+        }
+        // The luminance of the standard deviations bounds that of the luminance.
+        let variance = estimate.variance_of_mean();
+        let deviation = Color::new(
+            variance.r().sqrt(),
+            variance.g().sqrt(),
+            variance.b().sqrt(),
+        );
+        1.96 * deviation.luminance() <= self.threshold * estimate.mean().luminance().max(DARK)
+    }
+}
+
+/// Running mean and variance of the samples of a pixel, by Welford's algorithm.
+#[derive(Copy, Clone, Debug)]
+pub struct Estimate {
+    count: usize,
+    mean: Color,
+    /// Sum of the squared differences of the samples from their mean.
+    squares: Color,
+}
+
+impl Default for Estimate {
+    fn default() -> Self {
+        let black = Color::new(0., 0., 0.);
+        Estimate {
+            count: 0,
+            mean: black,
+            squares: black,
+        }
+    }
+}
+
+impl Estimate {
+    pub fn add(&mut self, sample: Color) {
+        self.count += 1;
+        let before = sample + self.mean * -1.;
+        self.mean += before * (1. / self.count as f64);
+        self.squares += before * (sample + self.mean * -1.);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> Color {
+        self.mean
+    }
+
+    /// Unbiased variance of the samples, zero for fewer than two.
+    pub fn variance(&self) -> Color {
+        if self.count < 2 {
+            Color::new(0., 0., 0.)
+        } else {
+            self.squares * (1. / (self.count - 1) as f64)
+        }
+    }
+
+    /// Variance of the mean of the samples.
+    pub fn variance_of_mean(&self) -> Color {
+        self.variance() * (1. / self.count.max(1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    #[test]
+    fn tracks_mean_and_variance() {
+        let samples = [1., 3., 3., 5.];
+        let mut estimate = Estimate::default();
+        for &v in &samples {
+            estimate.add(Color::new(v, 2. * v, 0.));
+        }
+        assert_eq!(estimate.count(), 4);
+        assert_eq!(estimate.mean(), Color::new(3., 6., 0.));
+        let variance = estimate.variance();
+        assert!((variance.r() - 8. / 3.).abs() < 1e-12);
+        assert!((variance.g() - 32. / 3.).abs() < 1e-12);
+        assert!((estimate.variance_of_mean().r() - 2. / 3.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stops_once_precise() {
+        let adaptive = AdaptiveSampling {
+            min_samples: 8,
+            threshold: 0.05,
+        };
+        let samples_until_converged = |sample: &dyn Fn() -> f64| {
+            let mut estimate = Estimate::default();
+            for count in 1..=100_000 {
+                let v = sample();
+                estimate.add(Color::new(v, v, v));
+                if adaptive.converged(&estimate) {
+                    return count;
+                }
+            }
+            panic!("no convergence");
+        };
+        random::reseed(5, 0);
+        // Flat pixels stop as early as allowed, black ones too.
+        assert_eq!(samples_until_converged(&|| 0.5), 8);
+        assert_eq!(samples_until_converged(&|| 0.), 8);
+        // Noisier pixels take more samples, about (1.96 σ / 0.05 µ)² = 512 for σ = µ / √3.
+        let noisy = samples_until_converged(&|| 2. * random::random::<f64>());
+        assert!(noisy > 300 && noisy < 800, "{}", noisy);
+        let noisier = samples_until_converged(&|| 4. * random::random::<f64>().powi(3));
+        assert!(noisier > noisy, "{} {}", noisier, noisy);
+        // Pixels too dark to see stop early.
+        let dark = samples_until_converged(&|| 2e-4 * random::random::<f64>());
+        assert_eq!(dark, 8);
+    }
+}
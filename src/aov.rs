@@ -0,0 +1,240 @@
+//! Arbitrary output variables, passes rendered alongside the radiance for compositing.
+use crate::color::Color;
+use crate::spaces::{FreeVec3, Vec3};
+use std::iter::FromIterator;
+
+/// Passes that can be rendered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance from the camera to the nearest surface seen in the pixel.
+    Depth,
+    /// World-space shading normal of the first surface, facing the camera.
+    Normal,
+    /// Color of the material of the first surface.
+    Albedo,
+    /// Number of the object of the first surface, 0 for the background.
+    ObjectId,
+    /// Number of the material of the first surface, 0 for the background.
+    MaterialId,
+    /// Light reaching the camera after at most one scattering event, including the light
+    /// seen directly.
+    Direct,
+    /// Light reaching the camera after bouncing more than once.
+    Indirect,
+    /// Number of samples taken in the pixel, which varies with adaptive sampling.
+    Samples,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Samples,
+    ];
+    pub const NAMES: &'static str =
+        "depth, normal, albedo, object_id, material_id, direct, indirect or samples";
+
+    pub fn named(name: &str) -> Option<Self> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    /// Name of the pass, used for the files and layers it is written to.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Samples => "samples",
+        }
+    }
+
+    /// Names of the channels of the pass in OpenEXR images.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Samples => &["count"],
+            Aov::Albedo | Aov::Direct | Aov::Indirect => &["R", "G", "B"],
+        }
+    }
+
+    /// Whether the pass holds whole numbers rather than measurements.
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId | Aov::Samples)
+    }
+
+    /// Values of the channels of the pass in `record`.
+    pub fn values(&self, record: &Record) -> [f64; 3] {
+        let color = |c: &Color| [*c.r(), *c.g(), *c.b()];
+        match self {
+            Aov::Depth => [record.depth, 0., 0.],
+            Aov::Normal => [record.normal.x(), record.normal.y(), record.normal.z()],
+            Aov::Albedo => color(&record.albedo),
+            Aov::ObjectId => [record.object_id as f64, 0., 0.],
+            Aov::MaterialId => [record.material_id as f64, 0., 0.],
+            Aov::Direct => color(&record.direct),
+            Aov::Indirect => color(&record.indirect),
+            Aov::Samples => [record.samples as f64, 0., 0.],
+        }
+    }
+}
+
+/// Set of passes to render.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AovSet(u8);
+
+impl AovSet {
+    pub fn insert(&mut self, aov: Aov) {
+        self.0 |= 1 << aov as u8;
+    }
+
+    pub fn contains(&self, aov: Aov) -> bool {
+        self.0 & (1 << aov as u8) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Passes of the set in the order of `Aov::ALL`.
+    pub fn iter(&self) -> impl Iterator<Item = Aov> + '_ {
+        Aov::ALL
+            .iter()
+            .copied()
+            .filter(move |&aov| self.contains(aov))
+    }
+}
+
+impl FromIterator<Aov> for AovSet {
+    fn from_iter<I: IntoIterator<Item = Aov>>(aovs: I) -> Self {
+        let mut set = AovSet::default();
+        for aov in aovs {
+            set.insert(aov);
+        }
+        set
+    }
+}
+
+/// Passes of a camera path, or of a pixel once averaged.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Infinite where nothing was hit.
+    pub depth: f64,
+    /// Zero where nothing was hit.
+    pub normal: FreeVec3,
+    pub albedo: Color,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct: Color,
+    pub indirect: Color,
+    /// Zero for camera paths.
+    pub samples: u32,
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        let black = Color::new(0., 0., 0.);
+        Record {
+            depth: f64::INFINITY,
+            normal: FreeVec3::new(0., 0., 0.),
+            albedo: black,
+            object_id: 0,
+            material_id: 0,
+            direct: black,
+            indirect: black,
+            samples: 0,
+        }
+    }
+}
+
+/// Passes of the samples of a pixel.
+///
+/// Colors and normals are averaged, while the depth is the nearest one and the
+/// identifiers are those of the first sample, as averages of them are meaningless. The
+/// number of samples is counted.
+#[derive(Default)]
+pub struct Accumulator {
+    count: usize,
+    sum: Record,
+}
+
+impl Accumulator {
+    pub fn add(&mut self, sample: &Record) {
+        let sum = &mut self.sum;
+        if self.count == 0 {
+            sum.object_id = sample.object_id;
+            sum.material_id = sample.material_id;
+        }
+        sum.depth = sum.depth.min(sample.depth);
+        sum.normal += sample.normal;
+        sum.albedo += sample.albedo;
+        sum.direct += sample.direct;
+        sum.indirect += sample.indirect;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Record {
+        let scale = 1. / self.count.max(1) as f64;
+        Record {
+            normal: self.sum.normal * scale,
+            albedo: self.sum.albedo * scale,
+            direct: self.sum.direct * scale,
+            indirect: self.sum.indirect * scale,
+            samples: self.count as u32,
+            ..self.sum
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_samples() {
+        for aov in &Aov::ALL {
+            assert_eq!(Aov::named(aov.name()), Some(*aov));
+        }
+        assert_eq!(Aov::named("beauty"), None);
+        let set: AovSet = [Aov::Indirect, Aov::Depth].iter().copied().collect();
+        assert_eq!(set.iter().collect::<Vec<_>>(), [Aov::Depth, Aov::Indirect]);
+        assert!(!set.contains(Aov::Normal) && AovSet::default().is_empty());
+
+        let hit = Record {
+            depth: 3.,
+            normal: FreeVec3::new(0., 1., 0.),
+            albedo: Color::new(0.5, 0.25, 1.),
+            object_id: 2,
+            material_id: 5,
+            direct: Color::new(1., 1., 1.),
+            indirect: Color::new(0.5, 0.5, 0.5),
+            samples: 0,
+        };
+        let miss = Record {
+            direct: Color::new(3., 3., 3.),
+            ..Record::default()
+        };
+        let mut accumulator = Accumulator::default();
+        for sample in &[hit, miss, hit, hit] {
+            accumulator.add(sample);
+        }
+        let mean = accumulator.mean();
+        assert_eq!(mean.depth, 3.);
+        assert_eq!(Aov::Normal.values(&mean), [0., 0.75, 0.]);
+        assert_eq!(mean.albedo, Color::new(0.375, 0.1875, 0.75));
+        assert_eq!((mean.object_id, mean.material_id), (2, 5));
+        assert_eq!(mean.direct, Color::new(1.5, 1.5, 1.5));
+        assert_eq!(Aov::Indirect.values(&mean), [0.375, 0.375, 0.375]);
+        assert_eq!(Aov::Samples.values(&mean), [4., 0., 0.]);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::color::Color;
+use crate::light::{EnvironmentMap, Light, PhysicalSky};
+use crate::ray::Ray;
+use crate::spaces::Vec3;
+use std::sync::Arc;
+
+/// Radiance arriving along rays that leave the scene without hitting anything.
+#[derive(Clone)]
+pub enum Background {
+    Solid(Color),
+    /// Blend between the colors seen when looking straight down and straight up.
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// No light comes from the surroundings, the scene is lit only by its emitters.
+    Black,
+    /// High dynamic range image all around, which should also be among the sampled lights
+    /// of the scene.
+    Environment(Arc<EnvironmentMap>),
+    /// Daylight sky with the sun, which should also be among the sampled lights of the
+    /// scene.
+    Sky(Arc<PhysicalSky>),
+}
+
+impl Background {
+    /// White-to-blue sky.
+    pub fn sky() -> Self {
+        Background::Gradient {
+            bottom: Color::new(1., 1., 1.),
+            top: Color::new(0.5, 0.7, 1.),
+        }
+    }
+
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (ray.direction.y() + 1.);
+                *bottom * (1. - t) + *top * t
+            }
+            Background::Black => Color::new(0., 0., 0.),
+            Background::Environment(map) => map.radiance(&ray.direction),
+            Background::Sky(sky) => sky.radiance(&ray.direction),
+        }
+    }
+
+    /// Light sampling the background, for backgrounds that can be sampled.
+    pub fn light(&self) -> Option<Arc<dyn Light + Send + Sync>> {
+        match self {
+            Background::Environment(map) => Some(map.clone()),
+            Background::Sky(sky) => Some(sky.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::sky()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::{FreeVec3, Point};
+
+    fn ray(y: f64) -> Ray {
+        Ray::new(
+            &Point::new(0., 0., 0.),
+            &FreeVec3::new(0., y, -1.).into(),
+            0.,
+        )
+    }
+
+    #[test]
+    fn colors_rays_by_direction() {
+        let (bottom, top) = (Color::new(1., 0.5, 0.), Color::new(0., 0.5, 1.));
+        let gradient = Background::Gradient { bottom, top };
+        assert_eq!(gradient.color(&ray(1e9)), top);
+        assert_eq!(gradient.color(&ray(-1e9)), bottom);
+        assert_eq!(gradient.color(&ray(0.)), Color::new(0.5, 0.5, 0.5));
+
+        let solid = Background::Solid(Color::new(0.2, 0.3, 0.4));
+        for &y in &[-1., 0., 1.] {
+            assert_eq!(solid.color(&ray(y)), Color::new(0.2, 0.3, 0.4));
+            assert_eq!(Background::Black.color(&ray(y)), Color::new(0., 0., 0.));
+        }
+        assert!(solid.light().is_none() && Background::Black.light().is_none());
+    }
+}
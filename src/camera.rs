@@ -1,6 +1,35 @@
+use crate::random;
 use crate::ray::Ray;
 use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
 
+/// Placement and lens of a camera, independent of the shape of the rendered image.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraSettings {
+    pub lookfrom: Point,
+    pub lookat: FreeVec3,
+    pub vup: FreeVec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    /// Interval during which the shutter is open, rays are spread uniformly over it.
+    pub shutter: (f64, f64),
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )
+        .shutter(self.shutter.0, self.shutter.1)
+    }
+}
+
 pub struct Camera {
     origin: Point,
     lower_left_corner: Point,
@@ -9,6 +38,8 @@ pub struct Camera {
     u: FreeVec3,
     v: FreeVec3,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -41,12 +72,25 @@ impl Camera {
             vertical,
             u: u.into(),
             v,
+            shutter_open: 0.,
+            shutter_close: 0.,
+        }
+    }
+
+    /// Sets the interval during which the shutter is open, the default is the instant 0.
+    pub fn shutter(self, open: f64, close: f64) -> Self {
+        Camera {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
         }
     }
 
     pub fn ray(&self, s: f64, t: f64) -> Ray {
         let rd = FreeVec3::random_in_unit_disk() * self.lens_radius;
         let offset = self.u * rd.x() + self.v * rd.y();
+        let time =
+            self.shutter_open + (self.shutter_close - self.shutter_open) * random::random::<f64>();
 
         Ray::new(
             &(self.origin + offset),
@@ -54,6 +98,7 @@ impl Camera {
                 - self.origin
                 - offset)
                 .into(),
+            time,
         )
     }
 }
@@ -1,10 +1,15 @@
-use crate::material::scatter;
+use crate::aov::Record;
+use crate::background::Background;
+use crate::light::LightList;
+use crate::material::bsdf::Dielectric;
+use crate::material::{emitted, Bsdf, InteriorStack, Material};
 use crate::objects::Object;
 use crate::ray::Ray;
 use crate::spaces::Vec3;
+use crate::spectrum::Channels;
 use std::ops::{Add, AddAssign, Mul};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color {
     r: f64,
     g: f64,
@@ -28,16 +33,9 @@ impl Color {
         &self.b
     }
 
-    pub fn write(&self, samples: i32, pixels: &mut [u8]) {
-        // Divide the color by the number of samples and gamma-correct for gamma=2.0.
-        let scale = 1. / samples as f64;
-        let color_corr = |color: &f64| (scale * color).sqrt();
-
-        // Convert colors to their 8-bit values.
-        let fun = |color: &f64| (256. * clamp(color_corr(color), 0., 0.999)) as u8;
-        pixels[0] = fun(self.r());
-        pixels[1] = fun(self.g());
-        pixels[2] = fun(self.b());
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
     }
 }
 
@@ -73,32 +71,491 @@ impl Mul<f64> for Color {
     }
 }
 
-pub fn ray_color(ray: &Ray, world: &dyn Object, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::new(0., 0., 0.);
-    }
+/// Radiance arriving along the ray, estimated by a path of at most `max_depth`
+/// scattering events.
+///
+/// At every vertex of the path that is not specular one light gets sampled directly, and
+/// light found by the path itself is weighted against the chance of that light sample
+/// with the power heuristic of multiple importance sampling.
+///
+/// Paths keep track of the dielectrics they are inside of, which absorb light along the
+/// way, and pass straight through surfaces hidden by dielectrics of higher priority.
+/// Passing such a surface counts towards `max_depth`.
+///
+/// The returned radiance holds values for the `channels`, and so do the colors of
+/// materials and lights once converted. Where dispersion makes the wavelengths of a
+/// spectral path part ways, only the hero wavelength continues.
+pub fn ray_color(
+    ray: &Ray,
+    world: &dyn Object,
+    lights: &LightList,
+    background: &Background,
+    channels: &Channels,
+    max_depth: i32,
+) -> Color {
+    let mut record = Record::default();
+    ray_color_with_aovs(
+        ray,
+        world,
+        lights,
+        background,
+        channels,
+        max_depth,
+        &mut record,
+    )
+}
+
+/// Radiance arriving along the ray like `ray_color`, also recording the passes of the
+/// path in `record`. Its direct and indirect light hold values for the `channels`.
+pub fn ray_color_with_aovs(
+    ray: &Ray,
+    world: &dyn Object,
+    lights: &LightList,
+    background: &Background,
+    channels: &Channels,
+    max_depth: i32,
+    record: &mut Record,
+) -> Color {
+    let black = Color::new(0., 0., 0.);
+    // Light found after at most one scattering event, and after more of them.
+    let mut light = [black, black];
+    let mut scatterings = 0;
+    let mut throughput = Color::new(1., 1., 1.);
+    let mut ray = *ray;
+    // Density of the direction of `ray` if it was sampled from a non-specular material.
+    let mut scatter_pdf = None;
+    let mut interiors = InteriorStack::default();
+    // The ray continues through hidden surfaces, so its current segment may start after
+    // its origin.
+    let mut segment_start = 0.;
+    let mut dispersed = false;
+
+    for _ in 0..max_depth {
+        let hit = match world.hit(&ray, segment_start + 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, lights.pdf(&ray, f64::INFINITY)),
+                    None => 1.,
+                };
+                light[usize::from(scatterings > 1)] +=
+                    throughput * channels.illuminant(background.color(&ray)) * weight;
+                break;
+            }
+        };
+        throughput = throughput * interiors.transmittance(hit.t - segment_start, channels);
+
+        let emission = channels.illuminant(emitted(&hit));
+        if emission != black {
+            let weight = match scatter_pdf {
+                Some(pdf) => power_heuristic(pdf, lights.pdf(&ray, hit.t * (1. + 1e-9))),
+                None => 1.,
+            };
+            light[usize::from(scatterings > 1)] += throughput * emission * weight;
+        }
+
+        let interior = match hit.material {
+            Material::Dielectric(interior) => Some(interior),
+            _ => None,
+        };
+        let bsdf: Option<Box<dyn Bsdf>> = match interior {
+            Some(interior) => {
+                let indices = channels
+                    .wavelengths()
+                    .map(|wavelength| interiors.boundary(&interior, hit.front_face, wavelength));
+                if !dispersed && indices.iter().any(|&index| index != indices[0]) {
+                    // The hero wavelength in the first channel stands in for all of them
+                    // from here on.
+                    throughput = Color::new(3. * throughput.r, 0., 0.);
+                    dispersed = true;
+                }
+                match indices[0] {
+                    Some(relative_index) if relative_index != 1. => Some(Box::new(Dielectric {
+                        relative_index,
+                        normal: hit.normal,
+                    })),
+                    // Hidden surfaces and boundaries between equal indices do not scatter.
+                    _ => {
+                        interiors.cross(interior, hit.front_face);
+                        segment_start = hit.t;
+                        continue;
+                    }
+                }
+            }
+            None => hit.material.bsdf(&hit),
+        };
+        if scatterings == 0 {
+            *record = Record {
+                depth: hit.t,
+                normal: hit.normal.into(),
+                albedo: hit.material.albedo(&hit),
+                object_id: hit.object_id,
+                material_id: hit.material_id,
+                ..*record
+            };
+        }
+        let bsdf = match bsdf {
+            Some(bsdf) => bsdf,
+            None => break,
+        };
+        let wo = -ray.direction;
+
+        if !bsdf.is_delta() {
+            if let Some(sample) = lights.sample(&hit.p, ray.time) {
+                let value = channels.reflectance(bsdf.eval(&wo, &sample.direction));
+                let shadow = Ray::new(&hit.p, &sample.direction, ray.time);
+                // Stop short of the surface of the light.
+                let t_max = sample.distance * (1. - 1e-6) - 0.001;
+                if value != black && world.hit(&shadow, 0.001, t_max).is_none() {
+                    let weight = if sample.is_delta {
+                        1.
+                    } else {
+                        power_heuristic(sample.pdf, bsdf.pdf(&wo, &sample.direction))
+                    };
+                    // The vertex adds a scattering event of its own.
+                    light[usize::from(scatterings > 0)] += throughput
+                        * value
+                        * channels.illuminant(sample.radiance)
+                        * interiors.transmittance(sample.distance, channels)
+                        * (weight / sample.pdf);
+                }
+            }
+        }
 
-    if let Some(hit) = world.hit(ray, 0.001, f64::INFINITY) {
-        if let Some((attenuation, scattered)) = scatter(ray, &hit) {
-            return attenuation * ray_color(&scattered, world, depth - 1);
-        } else {
-            return Color::new(0., 0., 0.);
+        match bsdf.sample(&wo) {
+            Some(sample) => {
+                scatter_pdf = if sample.is_delta {
+                    None
+                } else {
+                    Some(sample.pdf)
+                };
+                throughput = throughput * channels.reflectance(sample.weight);
+                if let Some(interior) = interior {
+                    if sample.direction.dot(&hit.normal) < 0. {
+                        interiors.cross(interior, hit.front_face);
+                    }
+                }
+                segment_start = 0.;
+                scatterings += 1;
+                ray = Ray::new(&hit.p, &sample.direction, ray.time);
+            }
+            None => break,
         }
     }
+    record.direct = light[0];
+    record.indirect = light[1];
+    light[0] + light[1]
+}
+
+/// Weight of a sample taken with density `pdf` against another strategy with density
+/// `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0. {
+        0.
+    } else {
+        a / (a + b)
+    }
+}
 
-    // Background:
-    let t = 0.5 * (ray.direction.y() + 1.);
-    let start_color = Color::new(1., 1., 1.);
-    let end_color = Color::new(0.5, 0.7, 1.);
-    start_color * (1. - t) + end_color * t
+/// Converts an sRGB-encoded value in `[0, 1]` to linear intensity.
+pub fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
 }
 
-fn clamp(x: f64, min: f64, max: f64) -> f64 {
-    if x < min {
-        min
-    } else if x > max {
-        max
+/// Converts a linear intensity in `[0, 1]` to its sRGB encoding.
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
     } else {
-        x
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{DirectionalLight, QuadLight};
+    use crate::material::{Dispersion, Interior, Material};
+    use crate::objects::{ObjectList, Plane, Quad, Sphere, Tagged};
+    use crate::random;
+    use crate::spaces::{FreeVec3, Point};
+    use crate::spectrum::Wavelengths;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    fn floor(albedo: f64) -> Arc<Plane> {
+        Arc::new(Plane::new(
+            Point::new(0., 0., 0.),
+            FreeVec3::new(0., 1., 0.).into(),
+            Material::Lambertian(Color::new(albedo, albedo, albedo).into()),
+        ))
+    }
+
+    fn down() -> Ray {
+        Ray::new(
+            &Point::new(0., 0.5, 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        )
+    }
+
+    #[test]
+    fn directional_light_on_diffuse_floor() {
+        let world = ObjectList {
+            objects: vec![floor(0.5)],
+        };
+        let mut lights = LightList::default();
+        lights.add(Arc::new(DirectionalLight::new(
+            FreeVec3::new(1., -1., 0.).into(),
+            Color::new(2., 2., 2.),
+        )));
+        let color = ray_color(
+            &down(),
+            &world,
+            &lights,
+            &Background::Black,
+            &Channels::Rgb,
+            5,
+        );
+        let expected = 0.5 / PI * 2. * (0.5f64).sqrt();
+        assert!((color.r - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn records_passes_of_the_first_hit() {
+        // A wall lit by the same light bounces it onto the floor.
+        let wall = Quad::new(
+            Point::new(1., 0., -5.),
+            FreeVec3::new(0., 0., 10.),
+            FreeVec3::new(0., 5., 0.),
+            Material::Lambertian(Color::new(0.9, 0.9, 0.9).into()),
+        );
+        let world = ObjectList {
+            objects: vec![
+                Arc::new(Tagged::new(floor(0.5), Some(3), Some(7))),
+                Arc::new(wall),
+            ],
+        };
+        let mut lights = LightList::default();
+        lights.add(Arc::new(DirectionalLight::new(
+            FreeVec3::new(1., -1., 0.).into(),
+            Color::new(2., 2., 2.),
+        )));
+        random::reseed(5, 0);
+        let mut record = Record::default();
+        let color = ray_color_with_aovs(
+            &down(),
+            &world,
+            &lights,
+            &Background::Black,
+            &Channels::Rgb,
+            5,
+            &mut record,
+        );
+        assert_eq!(record.depth, 0.5);
+        assert_eq!(record.normal, FreeVec3::new(0., 1., 0.));
+        assert_eq!(record.albedo, Color::new(0.5, 0.5, 0.5));
+        assert_eq!((record.object_id, record.material_id), (3, 7));
+        let expected = 0.5 / PI * 2. * (0.5f64).sqrt();
+        assert!((record.direct.r - expected).abs() < 1e-12);
+        assert_eq!(color, record.direct + record.indirect);
+
+        // On average the wall adds light to the floor.
+        let mut indirect = 0.;
+        for _ in 0..1000 {
+            ray_color_with_aovs(
+                &down(),
+                &world,
+                &lights,
+                &Background::Black,
+                &Channels::Rgb,
+                5,
+                &mut record,
+            );
+            indirect += record.indirect.r / 1000.;
+        }
+        assert!(indirect > 0.01, "indirect light {}", indirect);
+    }
+
+    #[test]
+    fn light_sampling_matches_path_tracing() {
+        // A unit square lamp at height 1, centered above the lit point.
+        let (corner, u, v) = (
+            Point::new(-0.5, 1., -0.5),
+            FreeVec3::new(1., 0., 0.),
+            FreeVec3::new(0., 0., 1.),
+        );
+        let emission = Color::new(3., 3., 3.);
+        let world = ObjectList {
+            objects: vec![
+                floor(0.8),
+                Arc::new(Quad::new(corner, u, v, Material::DiffuseLight(emission))),
+            ],
+        };
+        let mut lights = LightList::default();
+        lights.add(Arc::new(QuadLight::new(corner, u, v, emission)));
+
+        // Form factor from a point to a rectangle above one of its corners, summed over
+        // the four quarters of the lamp.
+        let corner_factor = |a: f64, b: f64| {
+            let (ra, rb) = ((1. + a * a).sqrt(), (1. + b * b).sqrt());
+            (a / ra * (b / ra).atan() + b / rb * (a / rb).atan()) / (2. * PI)
+        };
+        let expected = 0.8 * 3. * 4. * corner_factor(0.5, 0.5);
+
+        let n = 20000;
+        let mean = |lights: &LightList| {
+            random::reseed(7, 0);
+            (0..n)
+                .map(|_| {
+                    ray_color(
+                        &down(),
+                        &world,
+                        lights,
+                        &Background::Black,
+                        &Channels::Rgb,
+                        2,
+                    )
+                    .r
+                })
+                .sum::<f64>()
+                / n as f64
+        };
+        assert!((mean(&lights) - expected).abs() < 0.01 * expected);
+        assert!((mean(&LightList::default()) - expected).abs() < 0.05 * expected);
+    }
+
+    #[test]
+    fn light_sampling_accounts_for_absorption() {
+        // The lamp and the floor it lights are inside an absorbing medium, whose surface
+        // the ray from above passes straight through.
+        let (corner, u, v) = (
+            Point::new(-0.5, 1., -0.5),
+            FreeVec3::new(1., 0., 0.),
+            FreeVec3::new(0., 0., 1.),
+        );
+        let emission = Color::new(3., 3., 3.);
+        let medium = Interior::new(1.).absorption(Color::new(0.5, 0.5, 0.5));
+        let world = ObjectList {
+            objects: vec![
+                floor(0.8),
+                Arc::new(Quad::new(corner, u, v, Material::DiffuseLight(emission))),
+                Arc::new(Sphere::new(
+                    Point::new(0., 0., 0.),
+                    4.,
+                    Material::Dielectric(medium),
+                )),
+            ],
+        };
+        let mut lights = LightList::default();
+        lights.add(Arc::new(QuadLight::new(corner, u, v, emission)));
+
+        let ray = Ray::new(
+            &Point::new(1.5, 5., 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        let n = 40000;
+        let mean = |lights: &LightList| {
+            random::reseed(11, 0);
+            (0..n)
+                .map(|_| ray_color(&ray, &world, lights, &Background::Black, &Channels::Rgb, 3).r)
+                .sum::<f64>()
+                / n as f64
+        };
+        let (sampled, path_traced) = (mean(&lights), mean(&LightList::default()));
+        assert!(
+            (sampled - path_traced).abs() < 0.05 * path_traced,
+            "{} {}",
+            sampled,
+            path_traced
+        );
+    }
+
+    #[test]
+    fn nested_dielectrics_absorb() {
+        // Refraction indices of 1 let rays along the axis pass straight through.
+        let ball = |radius: f64, absorption: f64, priority: u32| {
+            let interior = Interior::new(1.)
+                .absorption(Color::new(absorption, 0., 0.))
+                .priority(priority);
+            Arc::new(Sphere::new(
+                Point::new(0., 0., 0.),
+                radius,
+                Material::Dielectric(interior),
+            ))
+        };
+        let ray = Ray::new(
+            &Point::new(0., 3., 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        let background = Background::Solid(Color::new(1., 1., 1.));
+        let color = |inner_priority| {
+            let world = ObjectList {
+                objects: vec![ball(1., 0.5, 1), ball(0.5, 4., inner_priority)],
+            };
+            ray_color(
+                &ray,
+                &world,
+                &LightList::default(),
+                &background,
+                &Channels::Rgb,
+                10,
+            )
+        };
+        // The inner ball is hidden by the outer one of higher priority.
+        assert!((color(0).r - (-1f64).exp()).abs() < 1e-9);
+        assert_eq!(color(0).g, 1.);
+        assert!((color(2).r - (-4.5f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dispersion_keeps_the_hero_wavelength() {
+        let glass = Interior::dispersive(Dispersion::Cauchy { a: 1.5, b: 0.01 });
+        let world = ObjectList {
+            objects: vec![Arc::new(Sphere::new(
+                Point::new(0., 0., 0.),
+                1.,
+                Material::Dielectric(glass),
+            ))],
+        };
+        let ray = Ray::new(
+            &Point::new(0., 3., 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        let white = Color::new(1., 1., 1.);
+        let background = Background::Solid(white);
+        random::reseed(8, 0);
+        let channels = Channels::Spectral(Wavelengths::sample());
+        // Whether reflected or refracted, the path ends in the white background.
+        let color = ray_color(
+            &ray,
+            &world,
+            &LightList::default(),
+            &background,
+            &channels,
+            10,
+        );
+        let expected = channels.illuminant(white);
+        // Smits' white reflectance is off from 1 by up to 1e-3.
+        assert!((color.r - 3. * expected.r).abs() < 3e-3 * expected.r);
+        assert_eq!((color.g, color.b), (0., 0.));
+
+        let color = ray_color(
+            &ray,
+            &world,
+            &LightList::default(),
+            &background,
+            &Channels::Rgb,
+            10,
+        );
+        assert_eq!(color, white);
     }
 }
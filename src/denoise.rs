@@ -0,0 +1,290 @@
+//! Removal of the noise of renders with few samples per pixel.
+use crate::aov::Record;
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::spaces::Vec3;
+use rayon::prelude::*;
+
+/// Joint non-local means filter guided by the passes of the first hits.
+///
+/// Every pixel becomes a weighted mean of the pixels around it. Neighbours count as
+/// much as the patches around them look like the patch around the pixel, taking into
+/// account how noisy both are, as in Rousselle et al., "Adaptive Rendering with
+/// Non-Local Means Filtering" (2012). As noise can make distinct surfaces look alike,
+/// neighbours whose albedo, normal or depth differ are also excluded, like in a joint
+/// bilateral filter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Denoiser {
+    /// Distance in pixels up to which neighbours are averaged.
+    pub search_radius: usize,
+    /// Distance in pixels up to which patches are compared.
+    pub patch_radius: usize,
+    /// Higher values average patches that differ more.
+    pub strength: f64,
+    /// Differences of albedo, of the cosine between normals and of relative depth that
+    /// reduce the weight of neighbours by a factor of e.
+    pub albedo_tolerance: f64,
+    pub normal_tolerance: f64,
+    pub depth_tolerance: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            search_radius: 6,
+            patch_radius: 1,
+            strength: 0.45,
+            albedo_tolerance: 0.1,
+            normal_tolerance: 0.1,
+            depth_tolerance: 0.05,
+        }
+    }
+}
+
+type Rgb = [f64; 3];
+
+fn rgb(c: &Color) -> Rgb {
+    [*c.r(), *c.g(), *c.b()]
+}
+
+impl Denoiser {
+    /// Image with the noise of the radiance of `image` removed, which must have the
+    /// passes of its pixels. Its variances, if any, are those of the means of the pixels.
+    pub fn denoise(&self, image: &Framebuffer) -> Framebuffer {
+        let (width, height) = (image.width(), image.height());
+        let records = image.records();
+        assert_eq!(records.len(), width * height, "denoising needs the passes");
+        let colors: Vec<Rgb> = image.pixels().iter().map(rgb).collect();
+        let variances = match image.variances() {
+            Some(variances) => {
+                let variances: Vec<Rgb> = variances.iter().map(rgb).collect();
+                box_filter(width, height, &variances)
+            }
+            // Without variances of the samples the spread of the neighbours stands in.
+            None => local_variance(width, height, &colors),
+        };
+
+        let pixels = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let mut sum = [0.; 3];
+                let mut total = 0.;
+                for qy in y.saturating_sub(self.search_radius)..=(y + self.search_radius) {
+                    for qx in x.saturating_sub(self.search_radius)..=(x + self.search_radius) {
+                        if qx >= width || qy >= height {
+                            continue;
+                        }
+                        let q = qy * width + qx;
+                        let weight = self.guide_weight(&records[index], &records[q])
+                            * self.patch_weight(
+                                width,
+                                height,
+                                &colors,
+                                &variances,
+                                (x, y),
+                                (qx, qy),
+                            );
+                        for (s, c) in sum.iter_mut().zip(&colors[q]) {
+                            *s += weight * c;
+                        }
+                        total += weight;
+                    }
+                }
+                // The pixel itself always has weight 1.
+                Color::new(sum[0] / total, sum[1] / total, sum[2] / total)
+            })
+            .collect();
+        Framebuffer::new(width, height, pixels).with_aovs(image.aovs(), records.to_vec())
+    }
+
+    /// Weight of a neighbour whose patch looks like that of the pixel, once the
+    /// difference expected from their variances is taken out.
+    fn patch_weight(
+        &self,
+        width: usize,
+        height: usize,
+        colors: &[Rgb],
+        variances: &[Rgb],
+        (x, y): (usize, usize),
+        (qx, qy): (usize, usize),
+    ) -> f64 {
+        let r = self.patch_radius as isize;
+        let clamp = |v: isize, n: usize| v.clamp(0, n as isize - 1) as usize;
+        let mut distance = 0.;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let p = clamp(y as isize + dy, height) * width + clamp(x as isize + dx, width);
+                let q = clamp(qy as isize + dy, height) * width + clamp(qx as isize + dx, width);
+                for c in 0..3 {
+                    let (vp, vq) = (variances[p][c], variances[q][c]);
+                    let difference = colors[p][c] - colors[q][c];
+                    distance += (difference * difference - (vp + vp.min(vq)))
+                        / (1e-10 + self.strength * self.strength * (vp + vq));
+                }
+            }
+        }
+        let count = 3 * (2 * self.patch_radius + 1).pow(2);
+        (-(distance / count as f64).max(0.)).exp()
+    }
+
+    /// Weight of a neighbour by how much its first hit looks like that of the pixel.
+    fn guide_weight(&self, p: &Record, q: &Record) -> f64 {
+        let albedo = rgb(&p.albedo)
+            .iter()
+            .zip(&rgb(&q.albedo))
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            / (self.albedo_tolerance * self.albedo_tolerance);
+        let normal = (1. - p.normal.dot(&q.normal)).max(0.) / self.normal_tolerance;
+        let depth = match (p.depth.is_finite(), q.depth.is_finite()) {
+            (true, true) => {
+                let relative = (p.depth - q.depth) / (self.depth_tolerance * p.depth.max(1e-9));
+                relative * relative
+            }
+            (false, false) => 0.,
+            // Surfaces and the background do not mix.
+            _ => return 0.,
+        };
+        (-(albedo + normal + depth)).exp()
+    }
+}
+
+/// Means over the 3×3 neighbourhoods of the pixels.
+fn box_filter(width: usize, height: usize, values: &[Rgb]) -> Vec<Rgb> {
+    neighbourhoods(width, height, |neighbours| {
+        let mut mean = [0.; 3];
+        for &i in neighbours {
+            for c in 0..3 {
+                mean[c] += values[i][c] / neighbours.len() as f64;
+            }
+        }
+        mean
+    })
+}
+
+/// Variances of the colors over the 3×3 neighbourhoods of the pixels, zero for the single
+/// pixel of a 1×1 image.
+fn local_variance(width: usize, height: usize, colors: &[Rgb]) -> Vec<Rgb> {
+    neighbourhoods(width, height, |neighbours| {
+        let n = neighbours.len() as f64;
+        let mut variance = [0.; 3];
+        if neighbours.len() < 2 {
+            return variance;
+        }
+        for (c, v) in variance.iter_mut().enumerate() {
+            let mean = neighbours.iter().map(|&i| colors[i][c]).sum::<f64>() / n;
+            *v = neighbours
+                .iter()
+                .map(|&i| (colors[i][c] - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.);
+        }
+        variance
+    })
+}
+
+/// Applies `f` to the indices of the pixels of the 3×3 neighbourhood of every pixel,
+/// clipped to the image.
+fn neighbourhoods(width: usize, height: usize, f: impl Fn(&[usize]) -> Rgb + Sync) -> Vec<Rgb> {
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let mut neighbours = Vec::with_capacity(9);
+            for qy in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for qx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    neighbours.push(qy * width + qx);
+                }
+            }
+            f(&neighbours)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::AovSet;
+    use crate::random;
+    use crate::spaces::FreeVec3;
+
+    /// Noisy image of two surfaces of different albedo meeting at a vertical edge, with
+    /// the noise-free image.
+    fn noisy(samples: usize) -> (Framebuffer, Vec<Color>) {
+        let (width, height) = (32, 24);
+        random::reseed(3, 0);
+        let mut pixels = Vec::new();
+        let mut variances = Vec::new();
+        let mut records = Vec::new();
+        let mut truth = Vec::new();
+        for index in 0..width * height {
+            let level = if index % width < width / 2 { 0.2 } else { 0.8 };
+            // Samples spread uniformly by ±level around it.
+            let values: Vec<f64> = (0..samples)
+                .map(|_| level * (2. * random::random::<f64>()))
+                .collect();
+            let mean = values.iter().sum::<f64>() / samples as f64;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
+                / (samples as f64 - 1.).max(1.)
+                / samples as f64;
+            pixels.push(Color::new(mean, mean, mean));
+            variances.push(Color::new(variance, variance, variance));
+            truth.push(Color::new(level, level, level));
+            records.push(Record {
+                depth: 4.,
+                normal: FreeVec3::new(0., 0., 1.),
+                albedo: Color::new(level, level, level),
+                ..Record::default()
+            });
+        }
+        let image = Framebuffer::new(width, height, pixels).with_aovs(AovSet::default(), records);
+        let image = if samples > 1 {
+            image.with_variances(variances)
+        } else {
+            image
+        };
+        (image, truth)
+    }
+
+    fn squared_error(pixels: &[Color], truth: &[Color]) -> f64 {
+        pixels
+            .iter()
+            .zip(truth)
+            .map(|(p, t)| (p.r() - t.r()).powi(2))
+            .sum::<f64>()
+            / pixels.len() as f64
+    }
+
+    #[test]
+    fn removes_noise_but_keeps_edges() {
+        for &samples in &[1, 8] {
+            let (image, truth) = noisy(samples);
+            let denoised = Denoiser::default().denoise(&image);
+            let before = squared_error(image.pixels(), &truth);
+            let after = squared_error(denoised.pixels(), &truth);
+            assert!(
+                after < before / 5.,
+                "{} spp: {} to {}",
+                samples,
+                before,
+                after
+            );
+            // Pixels next to the edge are not pulled towards the other side.
+            for y in 0..image.height() {
+                assert!(*denoised.pixel(15, y).r() < 0.4);
+                assert!(*denoised.pixel(16, y).r() > 0.6);
+            }
+            assert_eq!(denoised.records(), image.records());
+        }
+    }
+
+    #[test]
+    fn keeps_single_pixels() {
+        let pixel = Color::new(0.3, 0.5, 0.7);
+        let image = Framebuffer::new(1, 1, vec![pixel])
+            .with_aovs(AovSet::default(), vec![Record::default()]);
+        assert_eq!(local_variance(1, 1, &[rgb(&pixel)]), vec![[0.; 3]]);
+        assert_eq!(Denoiser::default().denoise(&image).pixels(), &[pixel]);
+    }
+}
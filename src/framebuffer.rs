@@ -0,0 +1,485 @@
+//! Floating-point images of rendered radiance and their output to files.
+use crate::aov::{Aov, AovSet, Record};
+use crate::color::Color;
+use crate::tonemap::{Operator, ToneMapping};
+use exr::prelude::{f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Layer, SmallVec};
+use exr::prelude::{LayerAttributes, WritableImage};
+use image::codecs::hdr::HdrEncoder;
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, Rgb};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum OutputError {
+    Io { file: PathBuf, error: io::Error },
+    Encoding { file: PathBuf, message: String },
+    UnsupportedFormat { file: PathBuf },
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            OutputError::Encoding { file, message } => {
+                write!(f, "{}: {}", file.display(), message)
+            }
+            OutputError::UnsupportedFormat { file } => write!(
+                f,
+                "unsupported output format of {}, expected a .png, .exr or .hdr file",
+                file.display()
+            ),
+        }
+    }
+}
+
+impl Error for OutputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OutputError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Formats of the written images.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8-bit sRGB, clamping the radiance.
+    Png,
+    /// OpenEXR with linear radiance.
+    Exr,
+    /// Radiance RGBE with linear radiance.
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Format given by the extension of `path`.
+    pub fn from_path(path: &Path) -> Result<Self, OutputError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(OutputFormat::Png),
+            Some("exr") => Ok(OutputFormat::Exr),
+            Some("hdr") => Ok(OutputFormat::Hdr),
+            _ => Err(OutputError::UnsupportedFormat {
+                file: path.to_owned(),
+            }),
+        }
+    }
+}
+
+/// Precision of the samples of OpenEXR images.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precision {
+    Half,
+    Float,
+}
+
+/// Radiance of the pixels of a rendered image, and the passes rendered alongside it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    /// Pixels row by row from the top.
+    pixels: Vec<Color>,
+    aovs: AovSet,
+    /// Passes of the pixels, empty without any.
+    records: Vec<Record>,
+    /// Variances of the means of the pixels, if known.
+    variances: Option<Vec<Color>>,
+}
+
+impl Framebuffer {
+    /// Image of the given pixels, row by row from the top.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Framebuffer {
+            width,
+            height,
+            pixels,
+            aovs: AovSet::default(),
+            records: Vec::new(),
+            variances: None,
+        }
+    }
+
+    /// Adds the passes of the pixels in `records` to the image, of which `aovs` are
+    /// written with it.
+    pub fn with_aovs(self, aovs: AovSet, records: Vec<Record>) -> Self {
+        assert_eq!(records.len(), self.pixels.len());
+        Framebuffer {
+            aovs,
+            records,
+            ..self
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Radiance of the pixel in column `x` and row `y` from the top.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Adds the variances of the means of the pixels to the image.
+    pub fn with_variances(self, variances: Vec<Color>) -> Self {
+        assert_eq!(variances.len(), self.pixels.len());
+        Framebuffer {
+            variances: Some(variances),
+            ..self
+        }
+    }
+
+    pub fn variances(&self) -> Option<&[Color]> {
+        self.variances.as_deref()
+    }
+
+    pub fn aovs(&self) -> AovSet {
+        self.aovs
+    }
+
+    /// Passes of the pixels, row by row from the top.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// 8-bit sRGB values of the pixels, row by row from the top.
+    pub fn to_rgb8(&self, tone_mapping: &ToneMapping) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &pixel)| tone_mapping.encode(pixel, i % self.width, i / self.width))
+            .collect()
+    }
+
+    /// 8-bit values showing a pass in PNG images. Lighting is tone mapped like the image,
+    /// the nearest depth is white, normals are mapped from -1 to 1, identifiers get
+    /// random colors and numbers of samples go from black through red and yellow to white
+    /// at the largest one.
+    fn pass_to_rgb8(&self, aov: Aov, tone_mapping: &ToneMapping) -> Vec<u8> {
+        let nearest = self
+            .records
+            .iter()
+            .map(|r| r.depth)
+            .filter(|&d| d > 0.)
+            .fold(f64::INFINITY, f64::min);
+        let most_samples = self.records.iter().map(|r| r.samples).max().unwrap_or(0);
+        let raw = |c: Color| [c.r(), c.g(), c.b()].map(|v| (255. * v + 0.5).clamp(0., 255.) as u8);
+        let albedo = ToneMapping {
+            operator: Operator::Clamp,
+            exposure: 0.,
+            ..*tone_mapping
+        };
+        self.records
+            .iter()
+            .enumerate()
+            .flat_map(|(i, record)| {
+                let (x, y) = (i % self.width, i / self.width);
+                let shown = shown(aov, record);
+                match aov {
+                    Aov::Direct | Aov::Indirect => tone_mapping.encode(shown, x, y),
+                    Aov::Albedo => albedo.encode(shown, x, y),
+                    Aov::Depth if *shown.r() > 0. => {
+                        let v = nearest / shown.r();
+                        raw(Color::new(v, v, v))
+                    }
+                    Aov::Samples => {
+                        let v = *shown.r() / most_samples.max(1) as f64;
+                        let ramp = |start: f64| (3. * v - start).clamp(0., 1.);
+                        raw(Color::new(ramp(0.), ramp(1.), ramp(2.)))
+                    }
+                    _ => raw(shown),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the image to `path` in the format given by its extension. High dynamic range
+    /// formats keep the radiance, so `tone_mapping` only applies to PNG images and
+    /// `precision` only to OpenEXR ones.
+    ///
+    /// Passes become layers of OpenEXR images, named like `normal.X`, and separate images
+    /// for other formats, named like `image.normal.png` for `image.png`.
+    pub fn save(
+        &self,
+        path: &Path,
+        tone_mapping: &ToneMapping,
+        precision: Precision,
+    ) -> Result<(), OutputError> {
+        match OutputFormat::from_path(path)? {
+            OutputFormat::Exr => self.save_exr(path, precision),
+            format => {
+                self.save_rgb(path, format, None, tone_mapping)?;
+                for aov in self.aovs.iter() {
+                    let name = format!(
+                        "{}.{}.{}",
+                        path.file_stem().unwrap_or_default().to_string_lossy(),
+                        aov.name(),
+                        path.extension().unwrap_or_default().to_string_lossy()
+                    );
+                    self.save_rgb(&path.with_file_name(name), format, Some(aov), tone_mapping)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the image, or one of its passes, to a PNG or Radiance file.
+    fn save_rgb(
+        &self,
+        path: &Path,
+        format: OutputFormat,
+        aov: Option<Aov>,
+        tone_mapping: &ToneMapping,
+    ) -> Result<(), OutputError> {
+        let encoding = |error: &dyn Error| OutputError::Encoding {
+            file: path.to_owned(),
+            message: error.to_string(),
+        };
+        let create = || {
+            File::create(path)
+                .map(BufWriter::new)
+                .map_err(|error| OutputError::Io {
+                    file: path.to_owned(),
+                    error,
+                })
+        };
+        let (width, height) = (self.width as u32, self.height as u32);
+        match format {
+            OutputFormat::Png => {
+                let values = match aov {
+                    Some(aov) => self.pass_to_rgb8(aov, tone_mapping),
+                    None => self.to_rgb8(tone_mapping),
+                };
+                PngEncoder::new(create()?)
+                    .write_image(&values, width, height, ColorType::Rgb8)
+                    .map_err(|e| encoding(&e))
+            }
+            _ => {
+                let colors: Vec<Color> = match aov {
+                    Some(aov) => self.records.iter().map(|r| shown(aov, r)).collect(),
+                    None => self.pixels.clone(),
+                };
+                // RGBE cannot hold negative values.
+                let pixels: Vec<Rgb<f32>> = colors
+                    .iter()
+                    .map(|p| Rgb([p.r(), p.g(), p.b()].map(|c| c.max(0.) as f32)))
+                    .collect();
+                HdrEncoder::new(create()?)
+                    .encode(&pixels, self.width, self.height)
+                    .map_err(|e| encoding(&e))
+            }
+        }
+    }
+
+    fn save_exr(&self, path: &Path, precision: Precision) -> Result<(), OutputError> {
+        let mut channels: SmallVec<_> = [("R", 0), ("G", 1), ("B", 2)]
+            .iter()
+            .map(|&(name, i)| {
+                let values = self.pixels.iter().map(|p| *[p.r(), p.g(), p.b()][i]);
+                AnyChannel::new(name, samples(values, precision))
+            })
+            .collect();
+        for aov in self.aovs.iter() {
+            for (i, channel) in aov.channels().iter().enumerate() {
+                let name = format!("{}.{}", aov.name(), channel);
+                let values = self.records.iter().map(|r| aov.values(r)[i]);
+                let samples = if aov.is_id() {
+                    FlatSamples::U32(values.map(|v| v as u32).collect())
+                } else {
+                    samples(values, precision)
+                };
+                channels.push(AnyChannel::new(name.as_str(), samples));
+            }
+        }
+        let layer = Layer::new(
+            (self.width, self.height),
+            LayerAttributes::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels),
+        );
+        exr::prelude::Image::from_layer(layer)
+            .write()
+            .to_file(path)
+            .map_err(|error| OutputError::Encoding {
+                file: path.to_owned(),
+                message: error.to_string(),
+            })
+    }
+}
+
+/// Linear colors showing a pass in images without channels of their own: the depth is 0
+/// for the background, normals are mapped from -1 to 1, identifiers get random colors
+/// and numbers of samples are grey levels.
+fn shown(aov: Aov, record: &Record) -> Color {
+    let [a, b, c] = aov.values(record);
+    match aov {
+        Aov::Depth if a.is_finite() => Color::new(a, a, a),
+        Aov::Depth => Color::new(0., 0., 0.),
+        Aov::Samples => Color::new(a, a, a),
+        Aov::Normal => Color::new(0.5 + 0.5 * a, 0.5 + 0.5 * b, 0.5 + 0.5 * c),
+        Aov::ObjectId | Aov::MaterialId if a > 0. => {
+            // Spreads consecutive identifiers over distinct hues.
+            let hash = (a as u32).wrapping_mul(2_654_435_761);
+            let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as f64 / 255.;
+            Color::new(channel(24), channel(16), channel(8))
+        }
+        _ => Color::new(a, b, c),
+    }
+}
+
+/// Samples of a channel of an OpenEXR image.
+fn samples(values: impl Iterator<Item = f64>, precision: Precision) -> FlatSamples {
+    match precision {
+        // Beyond the largest half-precision number finite values would become infinite.
+        Precision::Half => FlatSamples::F16(
+            values
+                .map(|v| {
+                    let max = f16::MAX.to_f64();
+                    f16::from_f64(if v.is_finite() { v.clamp(-max, max) } else { v })
+                })
+                .collect(),
+        ),
+        Precision::Float => FlatSamples::F32(values.map(|v| v as f32).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::hdr::HdrDecoder;
+    use std::io::BufReader;
+
+    fn framebuffer() -> Framebuffer {
+        let pixels = (0..6)
+            .map(|i| Color::new(i as f64 * 0.25, 1000.5, 0.125))
+            .collect();
+        Framebuffer::new(3, 2, pixels)
+    }
+
+    fn temporary(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("manta-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn chooses_format_by_extension() {
+        let format = |p: &str| OutputFormat::from_path(Path::new(p)).ok();
+        assert_eq!(format("out.PNG"), Some(OutputFormat::Png));
+        assert_eq!(format("a/out.exr"), Some(OutputFormat::Exr));
+        assert_eq!(format("out.hdr"), Some(OutputFormat::Hdr));
+        assert_eq!(format("out.jpg"), None);
+        assert_eq!(format("out"), None);
+    }
+
+    #[test]
+    fn keeps_dynamic_range() {
+        let image = framebuffer();
+        for &(name, precision) in &[
+            ("float.exr", Precision::Float),
+            ("half.exr", Precision::Half),
+        ] {
+            let path = temporary(name);
+            image
+                .save(&path, &ToneMapping::default(), precision)
+                .unwrap();
+            let read = image::open(&path).unwrap().into_rgb32f();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!((read.width(), read.height()), (3, 2));
+            for (x, y, pixel) in read.enumerate_pixels() {
+                let expected = image.pixel(x as usize, y as usize);
+                assert_eq!(pixel.0[0] as f64, *expected.r());
+                // Half precision has 11 significant bits.
+                assert!((pixel.0[1] as f64 - expected.g()).abs() <= 0.5);
+                assert_eq!(pixel.0[2] as f64, *expected.b());
+            }
+        }
+
+        let path = temporary("image.hdr");
+        let tone_mapping = ToneMapping::default();
+        image.save(&path, &tone_mapping, Precision::Float).unwrap();
+        let decoder = HdrDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let read = decoder.read_image_hdr().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (pixel, expected) in read.iter().zip(image.pixels()) {
+            // RGBE shares an exponent between the channels.
+            assert!((pixel.0[1] as f64 - expected.g()).abs() < 1e-2 * expected.g());
+            assert!((pixel.0[0] as f64 - expected.r()).abs() < 1e-2 * expected.g());
+        }
+    }
+
+    #[test]
+    fn writes_passes() {
+        let records = (0..6)
+            .map(|i| Record {
+                depth: if i == 0 { f64::INFINITY } else { i as f64 },
+                object_id: i,
+                samples: 2 * i,
+                ..Record::default()
+            })
+            .collect();
+        let aovs = [Aov::Depth, Aov::ObjectId, Aov::Samples]
+            .iter()
+            .copied()
+            .collect();
+        let image = framebuffer().with_aovs(aovs, records);
+
+        let path = temporary("passes.exr");
+        let tone_mapping = ToneMapping::default();
+        image.save(&path, &tone_mapping, Precision::Half).unwrap();
+        let read = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let channels = &read.layer_data.channel_data.list;
+        let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(
+            names,
+            ["B", "G", "R", "depth.Z", "object_id.id", "samples.count"]
+        );
+        let depth: Vec<f32> = channels[3].sample_data.values_as_f32().collect();
+        assert_eq!(depth, [f32::INFINITY, 1., 2., 3., 4., 5.]);
+        assert!(matches!(&channels[4].sample_data, FlatSamples::U32(ids) if ids[5] == 5));
+        assert!(matches!(&channels[5].sample_data, FlatSamples::U32(counts) if counts[5] == 10));
+
+        // Other formats get an image per pass.
+        let path = temporary("passes.png");
+        image.save(&path, &tone_mapping, Precision::Half).unwrap();
+        let names = [
+            "passes.png",
+            "passes.depth.png",
+            "passes.object_id.png",
+            "passes.samples.png",
+        ];
+        for name in &names {
+            let pass = temporary(name);
+            let read = image::open(&pass).unwrap().into_rgb8();
+            std::fs::remove_file(&pass).unwrap();
+            assert_eq!((read.width(), read.height()), (3, 2));
+            if name.contains("depth") {
+                // The nearest surface is white and the background black.
+                let values: Vec<u8> = read.pixels().map(|p| p.0[0]).collect();
+                assert_eq!(values, [0, 255, 128, 85, 64, 51]);
+            }
+            if name.contains("samples") {
+                // The most sampled pixel is white.
+                let values: Vec<[u8; 3]> = read.pixels().map(|p| p.0).collect();
+                assert_eq!(values[0], [0, 0, 0]);
+                assert_eq!(values[2], [255, 51, 0]);
+                assert_eq!(values[5], [255, 255, 255]);
+            }
+        }
+    }
+}
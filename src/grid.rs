@@ -0,0 +1,347 @@
+//! Dense voxel grids of scalar values such as the densities of simulated smoke.
+//!
+//! Grids are read from NumPy `.npy` files holding a three-dimensional array of shape
+//! `(nx, ny, nz)`, or from headerless raw files of little-endian 32-bit floats whose
+//! dimensions are given separately. In both cases the z index varies fastest, like in a
+//! NumPy array in C order.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum GridError {
+    Io { file: PathBuf, error: io::Error },
+    Format { file: PathBuf, message: String },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            GridError::Format { file, message } => write!(f, "{}: {}", file.display(), message),
+        }
+    }
+}
+
+impl Error for GridError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GridError::Io { error, .. } => Some(error),
+            GridError::Format { .. } => None,
+        }
+    }
+}
+
+/// Scalar values at the centers of the cells of a regular grid over the unit cube.
+pub struct DensityGrid {
+    dimensions: [usize; 3],
+    values: Vec<f32>,
+    max: f64,
+}
+
+impl DensityGrid {
+    /// Grid of the given numbers of cells along x, y and z with the values in C order.
+    pub fn new(dimensions: [usize; 3], values: Vec<f32>) -> Self {
+        assert!(dimensions.iter().all(|&n| n > 0), "grid must not be empty");
+        assert_eq!(
+            values.len(),
+            dimensions.iter().product::<usize>(),
+            "number of values must match the dimensions"
+        );
+        let max = values.iter().fold(0f64, |m, &v| m.max(v as f64));
+        DensityGrid {
+            dimensions,
+            values,
+            max,
+        }
+    }
+
+    /// Loads a `.npy` file.
+    pub fn load_npy(path: &Path) -> Result<Self, GridError> {
+        parse_npy(&read(path)?, path)
+    }
+
+    /// Loads a raw file of little-endian 32-bit floats with the given dimensions.
+    pub fn load_raw(path: &Path, dimensions: [usize; 3]) -> Result<Self, GridError> {
+        parse_raw(&read(path)?, dimensions, path)
+    }
+
+    pub fn dimensions(&self) -> [usize; 3] {
+        self.dimensions
+    }
+
+    /// Largest value of the grid, which bounds all interpolated values.
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [_, ny, nz] = self.dimensions;
+        self.values[(x * ny + y) * nz + z] as f64
+    }
+
+    /// Trilinear interpolation at the point with the given coordinates in the unit cube.
+    /// Values are constant beyond the outermost cell centers and zero outside the cube.
+    pub fn value(&self, x: f64, y: f64, z: f64) -> f64 {
+        if [x, y, z].iter().any(|c| !(0. ..=1.).contains(c)) {
+            return 0.;
+        }
+        let mut cells = [(0, 0, 0.); 3];
+        for (axis, &c) in [x, y, z].iter().enumerate() {
+            let n = self.dimensions[axis];
+            let position = (c * n as f64 - 0.5).clamp(0., (n - 1) as f64);
+            let low = (position.floor() as usize).min(n - 1);
+            cells[axis] = (low, (low + 1).min(n - 1), position - low as f64);
+        }
+        let [(x0, x1, fx), (y0, y1, fy), (z0, z1, fz)] = cells;
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let along_z = |x, y| lerp(self.at(x, y, z0), self.at(x, y, z1), fz);
+        lerp(
+            lerp(along_z(x0, y0), along_z(x0, y1), fy),
+            lerp(along_z(x1, y0), along_z(x1, y1), fy),
+            fx,
+        )
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, GridError> {
+    fs::read(path).map_err(|error| GridError::Io {
+        file: path.to_owned(),
+        error,
+    })
+}
+
+fn format_error(file: &Path, message: impl Into<String>) -> GridError {
+    GridError::Format {
+        file: file.to_owned(),
+        message: message.into(),
+    }
+}
+
+/// Number of bytes of a grid with the given dimensions and bytes per value.
+fn byte_count(dimensions: [usize; 3], size: usize, file: &Path) -> Result<usize, GridError> {
+    dimensions
+        .iter()
+        .try_fold(size, |count, &n| count.checked_mul(n))
+        .ok_or_else(|| format_error(file, format!("grid of {:?} is too large", dimensions)))
+}
+
+/// Grid of the values read from `file`, which must all be densities.
+fn grid(dimensions: [usize; 3], values: Vec<f32>, file: &Path) -> Result<DensityGrid, GridError> {
+    match values.iter().position(|v| !(v.is_finite() && *v >= 0.)) {
+        Some(index) => Err(format_error(
+            file,
+            format!(
+                "values must be finite and not negative, found {} at index {}",
+                values[index], index
+            ),
+        )),
+        None => Ok(DensityGrid::new(dimensions, values)),
+    }
+}
+
+fn parse_raw(bytes: &[u8], dimensions: [usize; 3], file: &Path) -> Result<DensityGrid, GridError> {
+    if dimensions.contains(&0) {
+        return Err(format_error(file, "grid dimensions must be positive"));
+    }
+    let expected = byte_count(dimensions, 4, file)?;
+    if bytes.len() != expected {
+        return Err(format_error(
+            file,
+            format!(
+                "expected {} bytes for a grid of {:?} floats, found {}",
+                expected,
+                dimensions,
+                bytes.len()
+            ),
+        ));
+    }
+    let values = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    grid(dimensions, values, file)
+}
+
+/// Value of `key` in the Python dictionary literal of a `.npy` header.
+fn header_entry<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(rest[..end].trim())
+}
+
+fn parse_npy(bytes: &[u8], file: &Path) -> Result<DensityGrid, GridError> {
+    let error = |message: &str| format_error(file, message);
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(error("not a NumPy .npy file"));
+    }
+    let (header_start, header_len) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 if bytes.len() >= 12 => (
+            12,
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+        ),
+        _ => return Err(error("unsupported .npy format version")),
+    };
+    let data = header_start + header_len;
+    let header = bytes
+        .get(header_start..data)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| error("truncated or malformed .npy header"))?;
+
+    let descr = header_entry(header, "descr").ok_or_else(|| error("missing data type"))?;
+    let fortran_order = match header_entry(header, "fortran_order") {
+        Some("False") => false,
+        Some("True") => true,
+        _ => return Err(error("missing array order")),
+    };
+    let shape: Vec<usize> = header_entry(header, "shape")
+        .and_then(|s| {
+            s.trim_matches(['(', ')'])
+                .split(',')
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .map(|d| d.parse().ok())
+                .collect()
+        })
+        .ok_or_else(|| error("malformed array shape"))?;
+    let dimensions = match shape[..] {
+        [nx, ny, nz] if nx > 0 && ny > 0 && nz > 0 => [nx, ny, nz],
+        _ => {
+            return Err(format_error(
+                file,
+                format!(
+                    "expected a non-empty three-dimensional array, found shape {:?}",
+                    shape
+                ),
+            ))
+        }
+    };
+
+    let (size, convert): (usize, fn(&[u8]) -> f32) = match descr.trim_matches('\'') {
+        "<f4" => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        "<f8" => (8, |b| {
+            f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+        }),
+        "|u1" | "<u1" => (1, |b| b[0] as f32),
+        other => {
+            return Err(format_error(
+                file,
+                format!("unsupported data type {}, expected <f4, <f8 or |u1", other),
+            ))
+        }
+    };
+    let expected = byte_count(dimensions, size, file)?;
+    let count = expected / size;
+    let payload = &bytes[data..];
+    if payload.len() != expected {
+        return Err(format_error(
+            file,
+            format!(
+                "expected {} bytes of data for shape {:?}, found {}",
+                expected,
+                dimensions,
+                payload.len()
+            ),
+        ));
+    }
+    let mut values: Vec<f32> = payload.chunks_exact(size).map(convert).collect();
+
+    if fortran_order {
+        // Reorder so that the last index varies fastest.
+        let [nx, ny, nz] = dimensions;
+        let fortran = values;
+        values = Vec::with_capacity(count);
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    values.push(fortran[x + nx * (y + ny * z)]);
+                }
+            }
+        }
+    }
+    grid(dimensions, values, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, fortran_order: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+            descr,
+            if fortran_order { "True" } else { "False" },
+            shape
+        );
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn parses_npy_in_both_orders() {
+        let values: Vec<f32> = (0..6).map(|i| i as f32).collect();
+        let file = Path::new("test.npy");
+        let c = parse_npy(&npy("<f4", false, "(1, 2, 3)", &floats(&values)), file).unwrap();
+        assert_eq!(c.dimensions(), [1, 2, 3]);
+        assert_eq!(c.at(0, 1, 0), 3.);
+        assert_eq!(c.max(), 5.);
+
+        // The same array stored with the first index varying fastest.
+        let fortran: Vec<f32> = vec![0., 3., 1., 4., 2., 5.];
+        let f = parse_npy(&npy("<f4", true, "(1, 2, 3)", &floats(&fortran)), file).unwrap();
+        assert_eq!(f.values, c.values);
+
+        let bytes = npy("|u1", false, "(2, 1, 1)", &[7, 9]);
+        assert_eq!(parse_npy(&bytes, file).unwrap().values, vec![7., 9.]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let file = Path::new("test.npy");
+        let bad = |bytes: Vec<u8>| matches!(parse_npy(&bytes, file), Err(GridError::Format { .. }));
+        assert!(bad(b"P6 1 1 255\n".to_vec()));
+        assert!(bad(npy("<f4", false, "(2, 3)", &floats(&[0.; 6]))));
+        assert!(bad(npy("<f4", false, "(1, 2, 3)", &floats(&[0.; 5]))));
+        assert!(bad(npy("<i8", false, "(1, 1, 1)", &[0; 8])));
+        assert!(parse_raw(&floats(&[0.; 5]), [1, 2, 3], file).is_err());
+        // Densities cannot be negative or undefined.
+        assert!(bad(npy("<f4", false, "(1, 1, 2)", &floats(&[1., -0.5]))));
+        assert!(parse_raw(&floats(&[f32::NAN]), [1, 1, 1], file).is_err());
+        // Shapes too large to count are rejected rather than wrapping around.
+        let huge = format!("({}, {}, 2)", usize::MAX / 2, usize::MAX / 2);
+        assert!(bad(npy("<f4", false, &huge, &[])));
+        assert!(parse_raw(&[], [usize::MAX, 2, 1], file).is_err());
+    }
+
+    #[test]
+    fn interpolates_between_cell_centers() {
+        let grid = DensityGrid::new([2, 1, 1], vec![1., 3.]);
+        assert_eq!(grid.value(0.1, 0.5, 0.5), 1.);
+        assert_eq!(grid.value(0.5, 0.5, 0.5), 2.);
+        assert_eq!(grid.value(0.625, 0.2, 0.9), 2.5);
+        assert_eq!(grid.value(1., 0.5, 0.5), 3.);
+        assert_eq!(grid.value(1.1, 0.5, 0.5), 0.);
+    }
+}
@@ -0,0 +1,19 @@
+pub mod adaptive;
+pub mod aov;
+pub mod background;
+pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod framebuffer;
+pub mod grid;
+pub mod light;
+pub mod material;
+pub mod obj;
+pub mod objects;
+pub mod random;
+pub mod ray;
+pub mod scene;
+pub mod spaces;
+pub mod spectrum;
+pub mod texture;
+pub mod tonemap;
@@ -0,0 +1,189 @@
+use super::{Light, LightSample};
+use crate::color::Color;
+use crate::material::Material;
+use crate::objects::{Object, Quad, Sphere};
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::vec3::orthonormal_basis;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+use std::f64::consts::PI;
+
+/// Sphere emitting uniformly from its outside, sampled within the cone of directions it
+/// covers as seen from the lit point.
+pub struct SphereLight {
+    center: Point,
+    radius: f64,
+    emission: Color,
+    /// Surface used to find the rays meeting the light.
+    shape: Sphere,
+}
+
+impl SphereLight {
+    pub fn new(center: Point, radius: f64, emission: Color) -> Self {
+        SphereLight {
+            center,
+            radius,
+            emission,
+            shape: Sphere::new(center, radius, Material::DiffuseLight(emission)),
+        }
+    }
+
+    /// Cosine of the half-angle of the cone covered by the sphere, `None` from inside.
+    fn cos_max(&self, origin: &Point) -> Option<f64> {
+        let distance_squared = (self.center - *origin).length_squared();
+        let ratio = self.radius * self.radius / distance_squared;
+        if ratio >= 1. {
+            None
+        } else {
+            Some((1. - ratio).sqrt())
+        }
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, origin: &Point, _time: f64) -> Option<LightSample> {
+        let cos_max = self.cos_max(origin)?;
+        let to_center = self.center - *origin;
+        let w = UnitVec3::from(to_center);
+        let (u, v) = orthonormal_basis(&w);
+
+        let cos = 1. - random::random::<f64>() * (1. - cos_max);
+        let sin = (1. - cos * cos).max(0.).sqrt();
+        let phi = 2. * PI * random::random::<f64>();
+        let direction = UnitVec3::from(u * (sin * phi.cos()) + v * (sin * phi.sin()) + w * cos);
+
+        // Nearer intersection of the direction with the sphere, clamped for directions
+        // grazing the silhouette.
+        let along = to_center.dot(&direction);
+        let across = to_center.length_squared() - along * along;
+        let distance = along - (self.radius * self.radius - across).max(0.).sqrt();
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.emission,
+            pdf: 1. / (2. * PI * (1. - cos_max)),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, t_max: f64) -> f64 {
+        match self.cos_max(&ray.origin) {
+            Some(cos_max) if self.shape.hit(ray, 0.001, t_max).is_some() => {
+                1. / (2. * PI * (1. - cos_max))
+            }
+            _ => 0.,
+        }
+    }
+}
+
+/// Parallelogram emitting from its front side, sampled uniformly over its area.
+pub struct QuadLight {
+    corner: Point,
+    u: FreeVec3,
+    v: FreeVec3,
+    normal: UnitVec3,
+    area: f64,
+    emission: Color,
+    shape: Quad,
+}
+
+impl QuadLight {
+    /// Light spanned by the edges `u` and `v`, emitting in the direction of `u × v`.
+    pub fn new(corner: Point, u: FreeVec3, v: FreeVec3, emission: Color) -> Self {
+        let shape = Quad::new(corner, u, v, Material::DiffuseLight(emission));
+        QuadLight {
+            corner,
+            u,
+            v,
+            normal: u.cross(&v).into(),
+            area: shape.area(),
+            emission,
+            shape,
+        }
+    }
+
+    /// Converts the area density of a point at `distance` seen under `direction` to a
+    /// solid-angle density, zero from behind.
+    fn solid_angle_pdf(&self, direction: &UnitVec3, distance: f64) -> f64 {
+        let cos = -self.normal.dot(direction);
+        if cos <= 0. {
+            0.
+        } else {
+            distance * distance / (cos * self.area)
+        }
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, origin: &Point, _time: f64) -> Option<LightSample> {
+        let point =
+            self.corner + self.u * random::random::<f64>() + self.v * random::random::<f64>();
+        let offset = point - *origin;
+        let distance = offset.length();
+        let direction = UnitVec3::from(offset);
+        let pdf = self.solid_angle_pdf(&direction, distance);
+        if pdf == 0. || !pdf.is_finite() {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.emission,
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, t_max: f64) -> f64 {
+        match self.shape.hit(ray, 0.001, t_max) {
+            Some(hit) => self.solid_angle_pdf(&ray.direction, hit.t),
+            None => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that sampled directions have the density reported for them and estimates
+    /// the solid angle covered by the light as the mean of the inverse densities.
+    fn solid_angle(light: &dyn Light, origin: Point) -> f64 {
+        let n = 10000;
+        let mut sum = 0.;
+        for _ in 0..n {
+            let sample = light.sample(&origin, 0.).unwrap();
+            let ray = Ray::new(&origin, &sample.direction, 0.);
+            let pdf = light.pdf(&ray, f64::INFINITY);
+            assert!((pdf - sample.pdf).abs() < 1e-6 * pdf);
+            sum += 1. / sample.pdf;
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn sphere_light_covers_its_cone() {
+        random::reseed(3, 0);
+        let light = SphereLight::new(Point::new(0., 0., -2.), 1., Color::new(1., 1., 1.));
+        let expected = 2. * PI * (1. - (0.75f64).sqrt());
+        assert!((solid_angle(&light, Point::new(0., 0., 0.)) - expected).abs() < 1e-9);
+        let sample = light.sample(&Point::new(0., 0., 0.), 0.).unwrap();
+        let on_light = Ray::new(&Point::new(0., 0., 0.), &sample.direction, 0.).at(sample.distance);
+        assert!(((on_light - Point::new(0., 0., -2.)).length() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quad_light_covers_its_solid_angle() {
+        random::reseed(4, 0);
+        // A 1 x 2 rectangle seen from the point at height 1 above one of its corners.
+        let light = QuadLight::new(
+            Point::new(0., 1., 0.),
+            FreeVec3::new(1., 0., 0.),
+            FreeVec3::new(0., 0., 2.),
+            Color::new(1., 1., 1.),
+        );
+        let expected = (2f64 / (2f64 * 5.).sqrt()).asin();
+        assert!((solid_angle(&light, Point::new(0., 0., 0.)) - expected).abs() < 0.01);
+        assert!(light.sample(&Point::new(0., 2., 0.), 0.).is_none());
+    }
+}
@@ -0,0 +1,38 @@
+use super::{Light, LightSample};
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::spaces::{Point, UnitVec3};
+
+/// Parallel light of a distant source such as the sun.
+pub struct DirectionalLight {
+    /// Direction towards the light, opposite to the one the light travels in.
+    towards_light: UnitVec3,
+    /// Irradiance on a surface facing the light.
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    /// Light travelling in `direction` with the given irradiance.
+    pub fn new(direction: UnitVec3, irradiance: Color) -> Self {
+        DirectionalLight {
+            towards_light: -direction,
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _origin: &Point, _time: f64) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.towards_light,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.,
+            is_delta: true,
+        })
+    }
+
+    fn pdf(&self, _ray: &Ray, _t_max: f64) -> f64 {
+        0.
+    }
+}
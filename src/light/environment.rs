@@ -0,0 +1,292 @@
+use super::{Light, LightSample};
+use crate::color::{srgb_to_linear, Color};
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+use image::codecs::hdr::HdrDecoder;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{ColorType, ImageError, ImageResult};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Light arriving from all around the scene, given by an equirectangular image.
+///
+/// The top row of the image is straight up, the bottom one straight down, and the middle
+/// of the image looks towards `-z` unless rotated. Directions are sampled in proportion
+/// to the luminance of the pixels, so that small bright areas like the sun are found
+/// quickly.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// Radiance of the pixels, row by row from the top.
+    pixels: Vec<Color>,
+    /// Rotation around the vertical axis in radians.
+    rotation: f64,
+    intensity: f64,
+    /// Cumulative sampling weights of the pixels.
+    cdf: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    /// Loads an image in any of the formats supported by the `image` crate. High dynamic
+    /// range ones, e.g. Radiance `.hdr` or OpenEXR, hold linear radiance, while the values
+    /// of integer formats like PNG or JPEG are taken to be sRGB encoded.
+    pub fn load(path: &Path, rotation: f64, intensity: f64) -> ImageResult<Self> {
+        let is_hdr = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+        // Opening Radiance files as dynamic images would tone map them to 8 bits.
+        let (width, height, values) = if is_hdr {
+            let file = BufReader::new(File::open(path).map_err(ImageError::IoError)?);
+            let decoder = HdrDecoder::new(file)?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+            let values = pixels.iter().flat_map(|p| p.0).collect();
+            (metadata.width, metadata.height, values)
+        } else {
+            let image = image::open(path)?;
+            let encoded = !matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+            let image = image.into_rgb32f();
+            let (width, height) = image.dimensions();
+            let mut values = image.into_raw();
+            if encoded {
+                for v in &mut values {
+                    *v = srgb_to_linear(*v as f64) as f32;
+                }
+            }
+            (width, height, values)
+        };
+        let pixels = values
+            .chunks_exact(3)
+            .map(|p: &[f32]| {
+                let channel = |c: f32| if c.is_finite() { c.max(0.) as f64 } else { 0. };
+                Color::new(channel(p[0]), channel(p[1]), channel(p[2]))
+            })
+            .collect();
+        EnvironmentMap::new(width as usize, height as usize, pixels, rotation, intensity)
+    }
+
+    /// Map of the given pixels, rotated by `rotation` degrees around the y axis in the
+    /// sense of instances, i.e. from `-z` towards `-x`, and scaled by `intensity`. Fails
+    /// for empty maps and for pixels that do not fill the map.
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+        rotation: f64,
+        intensity: f64,
+    ) -> ImageResult<Self> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        // Rows near the poles cover less solid angle than those near the horizon.
+        let mut total = 0.;
+        let cdf = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let theta = ((i / width) as f64 + 0.5) / height as f64 * PI;
+                total += pixel.luminance() * theta.sin();
+                total
+            })
+            .collect();
+        Ok(EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: rotation.to_radians(),
+            intensity,
+            cdf,
+        })
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &UnitVec3) -> Color {
+        let (u, v) = self.coordinates(direction);
+        self.pixels[self.pixel(u, v)] * self.intensity
+    }
+
+    /// Coordinates of the direction in the image, between 0 and 1 from the left and from
+    /// the top.
+    fn coordinates(&self, direction: &UnitVec3) -> (f64, f64) {
+        let phi = direction.x().atan2(-direction.z()) + self.rotation;
+        let u = (0.5 + phi / (2. * PI)).rem_euclid(1.);
+        let v = direction.y().clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    fn direction(&self, u: f64, v: f64) -> UnitVec3 {
+        let phi = (u - 0.5) * 2. * PI - self.rotation;
+        let theta = v * PI;
+        let sin = theta.sin();
+        FreeVec3::new(sin * phi.sin(), theta.cos(), -sin * phi.cos()).into()
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> usize {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        j * self.width + i
+    }
+
+    fn total(&self) -> f64 {
+        self.cdf.last().copied().unwrap_or(0.)
+    }
+
+    /// Solid-angle density of sampling `direction`.
+    fn density(&self, direction: &UnitVec3) -> f64 {
+        let total = self.total();
+        let (u, v) = self.coordinates(direction);
+        let sin = (v * PI).sin();
+        if total == 0. || sin == 0. {
+            return 0.;
+        }
+        let index = self.pixel(u, v);
+        let weight = self.cdf[index] - if index > 0 { self.cdf[index - 1] } else { 0. };
+        // Density over the image, which is spread over 2π² sin θ of solid angle per unit
+        // of area.
+        let image_density = weight / total * (self.width * self.height) as f64;
+        image_density / (2. * PI * PI * sin)
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _origin: &Point, _time: f64) -> Option<LightSample> {
+        let total = self.total();
+        if total == 0. {
+            return None;
+        }
+        let target = random::random::<f64>() * total;
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= target)
+            .min(self.cdf.len() - 1);
+        let (i, j) = (index % self.width, index / self.width);
+        let u = (i as f64 + random::random::<f64>()) / self.width as f64;
+        let v = (j as f64 + random::random::<f64>()) / self.height as f64;
+        let direction = self.direction(u, v);
+        let pdf = self.density(&direction);
+        if pdf == 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance(&direction),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, t_max: f64) -> f64 {
+        // Only rays leaving the scene reach the environment.
+        if t_max.is_finite() {
+            0.
+        } else {
+            self.density(&ray.direction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map with a bright spot next to a dim sky.
+    fn map(rotation: f64) -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.2, 0.3, 0.5); width * height];
+        pixels[2 * width + 5] = Color::new(100., 90., 80.);
+        EnvironmentMap::new(width, height, pixels, rotation, 2.).unwrap()
+    }
+
+    #[test]
+    fn rejects_empty_maps() {
+        let pixels = || vec![Color::new(1., 1., 1.); 4];
+        assert!(EnvironmentMap::new(0, 4, Vec::new(), 0., 1.).is_err());
+        assert!(EnvironmentMap::new(4, 0, Vec::new(), 0., 1.).is_err());
+        assert!(EnvironmentMap::new(2, 3, pixels(), 0., 1.).is_err());
+        assert!(EnvironmentMap::new(2, 2, pixels(), 0., 1.).is_ok());
+    }
+
+    #[test]
+    fn decodes_srgb_images() {
+        let path = std::env::temp_dir().join(format!("manta-{}-sky.png", std::process::id()));
+        image::RgbImage::from_pixel(4, 2, image::Rgb([255, 188, 0]))
+            .save(&path)
+            .unwrap();
+        let map = EnvironmentMap::load(&path, 0., 1.);
+        std::fs::remove_file(&path).unwrap();
+        let radiance = map.unwrap().radiance(&FreeVec3::new(0., 1., 0.).into());
+        assert!((radiance.r() - 1.).abs() < 1e-6);
+        assert!((radiance.g() - srgb_to_linear(188. / 255.)).abs() < 1e-6);
+        assert!((radiance.g() - 0.5).abs() < 0.01, "{}", radiance.g());
+        assert_eq!(*radiance.b(), 0.);
+    }
+
+    #[test]
+    fn maps_directions_to_pixels() {
+        let map = map(30.);
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.93, 0.81)] {
+            let (u2, v2) = map.coordinates(&map.direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+        let up = FreeVec3::new(0., 1., 0.).into();
+        assert!(map.coordinates(&up).1 < 1e-9);
+        // The middle of an unrotated map looks forward.
+        let forward = FreeVec3::new(0., 0., -1.).into();
+        assert!((self::map(0.).coordinates(&forward).0 - 0.5).abs() < 1e-9);
+        let left = FreeVec3::new(-1., 0., 0.).into();
+        assert!((self::map(90.).coordinates(&left).0 - 0.5).abs() < 1e-9);
+        assert_eq!(map.radiance(&up), Color::new(0.4, 0.6, 1.));
+    }
+
+    #[test]
+    fn samples_match_density() {
+        random::reseed(9, 0);
+        let map = map(75.);
+        let origin = Point::new(0., 0., 0.);
+        // The density integrates to one over the sphere.
+        let n = 200;
+        let mut integral = 0.;
+        for j in 0..n {
+            for i in 0..2 * n {
+                let u = (i as f64 + 0.5) / (2 * n) as f64;
+                let v = (j as f64 + 0.5) / n as f64;
+                let solid_angle = 2. * PI * PI * (v * PI).sin() / (2 * n * n) as f64;
+                integral += map.density(&map.direction(u, v)) * solid_angle;
+            }
+        }
+        assert!(
+            (integral - 1.).abs() < 1e-3,
+            "density integrates to {}",
+            integral
+        );
+
+        // Estimating the irradiance of the upper hemisphere by sampling the map agrees
+        // with integrating it.
+        let up: UnitVec3 = FreeVec3::new(0., 1., 0.).into();
+        let mut expected = 0.;
+        for j in 0..n / 2 {
+            for i in 0..2 * n {
+                let u = (i as f64 + 0.5) / (2 * n) as f64;
+                let v = (j as f64 + 0.5) / n as f64;
+                let direction = map.direction(u, v);
+                let solid_angle = 2. * PI * PI * (v * PI).sin() / (2 * n * n) as f64;
+                expected += map.radiance(&direction).r() * direction.dot(&up) * solid_angle;
+            }
+        }
+        let samples = 20000;
+        let mut estimate = 0.;
+        for _ in 0..samples {
+            let sample = map.sample(&origin, 0.).unwrap();
+            assert!((sample.pdf - map.density(&sample.direction)).abs() < 1e-9 * sample.pdf);
+            let cos = sample.direction.dot(&up).max(0.);
+            estimate += sample.radiance.r() * cos / sample.pdf / samples as f64;
+        }
+        assert!((estimate - expected).abs() < 0.02 * expected);
+    }
+}
@@ -0,0 +1,80 @@
+//! Lights that can be sampled from any point of the scene, so that paths connect to them
+//! directly instead of hitting them by chance.
+pub mod area;
+pub mod directional;
+pub mod environment;
+pub mod point;
+pub mod sky;
+
+pub use area::{QuadLight, SphereLight};
+pub use directional::DirectionalLight;
+pub use environment::EnvironmentMap;
+pub use point::PointLight;
+pub use sky::PhysicalSky;
+
+use crate::color::Color;
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::{Point, UnitVec3};
+use std::sync::Arc;
+
+/// Direction towards a light chosen from a point of the scene.
+pub struct LightSample {
+    pub direction: UnitVec3,
+    /// Distance to the sampled point of the light, infinite for lights at infinity.
+    pub distance: f64,
+    /// Radiance arriving from the light, or irradiance for lights without extent.
+    pub radiance: Color,
+    /// Solid-angle density of the direction, 1 for lights without extent.
+    pub pdf: f64,
+    /// Whether the light is a point or a direction, which rays can never hit.
+    pub is_delta: bool,
+}
+
+pub trait Light {
+    /// Samples a direction to the light as seen from `origin`, `None` if no light reaches
+    /// the point, e.g. because it is behind an area light.
+    fn sample(&self, origin: &Point, time: f64) -> Option<LightSample>;
+
+    /// Solid-angle density of `sample` choosing the direction of the ray if the ray meets
+    /// the light before `t_max`, zero otherwise and always for lights without extent.
+    fn pdf(&self, ray: &Ray, t_max: f64) -> f64;
+}
+
+/// All lights of a scene, sampled by picking one of them uniformly.
+#[derive(Clone, Default)]
+pub struct LightList {
+    pub lights: Vec<Arc<dyn Light + Send + Sync>>,
+}
+
+impl LightList {
+    pub fn add(&mut self, light: Arc<dyn Light + Send + Sync>) {
+        self.lights.push(light);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Samples one of the lights, the density of the sample includes the choice of the
+    /// light.
+    pub fn sample(&self, origin: &Point, time: f64) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let count = self.lights.len();
+        let mut sample = self.lights[random::gen_range(0, count)].sample(origin, time)?;
+        sample.pdf /= count as f64;
+        Some(sample)
+    }
+
+    /// Density of `sample` choosing the direction of the ray, for rays meeting a light
+    /// before `t_max`.
+    pub fn pdf(&self, ray: &Ray, t_max: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 0.;
+        }
+        let sum: f64 = self.lights.iter().map(|l| l.pdf(ray, t_max)).sum();
+        sum / self.lights.len() as f64
+    }
+}
@@ -0,0 +1,41 @@
+use super::{Light, LightSample};
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::spaces::{Point, Vec3};
+
+/// Light emitted uniformly in all directions from a single point.
+pub struct PointLight {
+    position: Point,
+    /// Radiant intensity, the power per solid angle.
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, origin: &Point, _time: f64) -> Option<LightSample> {
+        let offset = self.position - *origin;
+        let distance = offset.length();
+        if distance == 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction: offset.into(),
+            distance,
+            radiance: self.intensity * (1. / (distance * distance)),
+            pdf: 1.,
+            is_delta: true,
+        })
+    }
+
+    fn pdf(&self, _ray: &Ray, _t_max: f64) -> f64 {
+        0.
+    }
+}
@@ -0,0 +1,260 @@
+use super::{Light, LightSample};
+use crate::color::Color;
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::vec3::orthonormal_basis;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::ops::RangeInclusive;
+
+/// Elevations of the sun above the horizon in degrees the sky model holds for.
+pub const ELEVATIONS: RangeInclusive<f64> = 0.0..=90.0;
+/// Turbidities the sky model holds for, from a very clear sky to haze.
+pub const TURBIDITIES: RangeInclusive<f64> = 1.7..=10.0;
+
+/// Radiance of the renderer per kilocandela per square metre of the model. Makes a high
+/// sun and the sky light the ground about as much as the default gradient background.
+const SCALE: f64 = 1. / 30.;
+/// Luminance of the sun outside the atmosphere in kilocandelas per square metre.
+const SUN_LUMINANCE: f64 = 1.96e6;
+/// Angular radius of the sun in radians.
+const SUN_RADIUS: f64 = 0.00465;
+
+/// Daylight sky of the analytic model of Preetham et al., "A Practical Analytic Model for
+/// Daylight" (1999), with the disk of the sun sampled as a light.
+///
+/// The sun is placed by its elevation above the horizon and its azimuth from `-z` towards
+/// `+x`, like a compass bearing with north at `-z` and east at `+x`. The turbidity grows
+/// with the haze of the atmosphere, from 1.7 for a very clear sky.
+#[derive(Clone)]
+pub struct PhysicalSky {
+    elevation: f64,
+    azimuth: f64,
+    turbidity: f64,
+    intensity: f64,
+    towards_sun: UnitVec3,
+    /// Coefficients of the Perez distribution of luminance and of the chromaticities x
+    /// and y.
+    perez: [[f64; 5]; 3],
+    /// Luminance and chromaticities at the zenith, divided by the Perez distribution
+    /// there.
+    zenith: [f64; 3],
+    sun_radiance: Color,
+}
+
+impl PhysicalSky {
+    /// Sky for the sun at `elevation` and `azimuth` in degrees, whose radiance is scaled by
+    /// `intensity`. The elevation and the turbidity must be within `ELEVATIONS` and
+    /// `TURBIDITIES`.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, intensity: f64) -> Self {
+        assert!(ELEVATIONS.contains(&elevation) && TURBIDITIES.contains(&turbidity));
+        let t = turbidity;
+        let theta_sun = FRAC_PI_2 - elevation.to_radians();
+        let phi = azimuth.to_radians();
+        let towards_sun = FreeVec3::new(
+            theta_sun.sin() * phi.sin(),
+            theta_sun.cos(),
+            -theta_sun.sin() * phi.cos(),
+        )
+        .into();
+
+        let perez = [
+            [
+                0.1787 * t - 1.463,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.067 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.095 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_sun);
+        let luminance = (4.0453 * t - 4.971) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic = |c: [f64; 4]| ((c[0] * theta_sun + c[1]) * theta_sun + c[2]) * theta_sun + c[3];
+        let chromaticity = |c: [[f64; 4]; 3]| t * t * cubic(c[0]) + t * cubic(c[1]) + cubic(c[2]);
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.0061, 0.00317, 0.],
+            [-0.04214, 0.0897, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.0667, 0.26688],
+        ]);
+        let mut zenith = [luminance, x, y];
+        for (z, coefficients) in zenith.iter_mut().zip(&perez) {
+            *z /= perez_distribution(coefficients, 1., theta_sun.cos());
+        }
+
+        PhysicalSky {
+            elevation,
+            azimuth,
+            turbidity,
+            intensity,
+            towards_sun,
+            perez,
+            zenith,
+            sun_radiance: sun_transmittance(theta_sun, turbidity) * (SUN_LUMINANCE * SCALE),
+        }
+    }
+
+    pub fn elevation(&self) -> f64 {
+        self.elevation
+    }
+
+    pub fn azimuth(&self) -> f64 {
+        self.azimuth
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    /// Radiance arriving from `direction`, including the disk of the sun. Below the
+    /// horizon, where the model does not hold, the sky continues as at the horizon.
+    pub fn radiance(&self, direction: &UnitVec3) -> Color {
+        let cos_gamma = direction.dot(&self.towards_sun);
+        let sky = self.sky(direction.y().max(0.), cos_gamma);
+        if cos_gamma >= SUN_RADIUS.cos() {
+            sky + self.sun_radiance * self.intensity
+        } else {
+            sky
+        }
+    }
+
+    /// Radiance of the sky alone for the cosines of the angles with the zenith and with
+    /// the sun.
+    fn sky(&self, cos_theta: f64, cos_gamma: f64) -> Color {
+        // The distribution grows without bounds towards the horizon.
+        let cos_theta = cos_theta.max(0.01);
+        let [luminance, x, y] = [0, 1, 2]
+            .map(|i| self.zenith[i] * perez_distribution(&self.perez[i], cos_theta, cos_gamma));
+        // From xyY to XYZ and linear sRGB.
+        let (big_x, big_z) = (x / y * luminance, (1. - x - y) / y * luminance);
+        let rgb = Color::new(
+            3.2404542 * big_x - 1.5371385 * luminance - 0.4985314 * big_z,
+            -0.969266 * big_x + 1.8760108 * luminance + 0.041556 * big_z,
+            0.0556434 * big_x - 0.2040259 * luminance + 1.0572252 * big_z,
+        );
+        let clamp = |c: f64| c.max(0.) * SCALE * self.intensity;
+        Color::new(clamp(*rgb.r()), clamp(*rgb.g()), clamp(*rgb.b()))
+    }
+}
+
+/// Perez et al.'s distribution of luminance over the sky, for the cosines of the angles
+/// with the zenith and with the sun.
+fn perez_distribution(c: &[f64; 5], cos_theta: f64, cos_gamma: f64) -> f64 {
+    let gamma = cos_gamma.clamp(-1., 1.).acos();
+    (1. + c[0] * (c[1] / cos_theta).exp())
+        * (1. + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+/// Fraction of the light of the sun at its zenith angle reaching the ground through
+/// Rayleigh scattering and aerosols, evaluated at the wavelengths of red, green and blue.
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    // Relative optical mass of the air of Kasten and Young.
+    let degrees = theta_sun.to_degrees().min(93.);
+    let mass = 1. / (theta_sun.cos().max(0.) + 0.50572 * (96.07995 - degrees).powf(-1.6364));
+    // Ångström's turbidity coefficient for the turbidity of Preetham et al.
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |micrometres: f64| {
+        let rayleigh = 0.008735 * micrometres.powf(-4.08);
+        let aerosols = beta * micrometres.powf(-1.3);
+        (-mass * (rayleigh + aerosols)).exp()
+    };
+    Color::new(channel(0.65), channel(0.55), channel(0.45))
+}
+
+impl Light for PhysicalSky {
+    /// Samples the disk of the sun, leaving the rest of the sky to the paths.
+    fn sample(&self, _origin: &Point, _time: f64) -> Option<LightSample> {
+        let cos_max = SUN_RADIUS.cos();
+        let (u, v) = orthonormal_basis(&self.towards_sun);
+        let cos = 1. - random::random::<f64>() * (1. - cos_max);
+        let sin = (1. - cos * cos).max(0.).sqrt();
+        let phi = 2. * PI * random::random::<f64>();
+        let direction = UnitVec3::from(
+            u * (sin * phi.cos()) + v * (sin * phi.sin()) + FreeVec3::from(self.towards_sun) * cos,
+        );
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance(&direction),
+            pdf: 1. / (2. * PI * (1. - cos_max)),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, t_max: f64) -> f64 {
+        let cos_max = SUN_RADIUS.cos();
+        if t_max.is_finite() || ray.direction.dot(&self.towards_sun) < cos_max {
+            0.
+        } else {
+            1. / (2. * PI * (1. - cos_max))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction(elevation: f64, azimuth: f64) -> UnitVec3 {
+        let (e, a) = (elevation.to_radians(), azimuth.to_radians());
+        FreeVec3::new(e.cos() * a.sin(), e.sin(), -e.cos() * a.cos()).into()
+    }
+
+    #[test]
+    fn places_the_sun() {
+        let sky = PhysicalSky::new(30., 90., 3., 1.);
+        let sun = direction(30., 90.);
+        assert!((sky.towards_sun.dot(&sun) - 1.).abs() < 1e-12);
+        assert!(sky.towards_sun.x() > 0.);
+        let sample = sky.sample(&Point::new(0., 0., 0.), 0.).unwrap();
+        assert!(sample.direction.dot(&sun) >= SUN_RADIUS.cos() - 1e-12);
+        let ray = Ray::new(&Point::new(0., 0., 0.), &sample.direction, 0.);
+        assert_eq!(sky.pdf(&ray, f64::INFINITY), sample.pdf);
+        assert_eq!(sky.pdf(&ray, 10.), 0.);
+    }
+
+    #[test]
+    fn looks_like_daylight() {
+        let sky = PhysicalSky::new(40., 0., 3., 1.);
+        // Blue overhead and away from the sun, brighter around the sun and towards the
+        // horizon.
+        let zenith = sky.radiance(&direction(90., 0.));
+        assert!(zenith.b() > zenith.r());
+        let near_sun = sky.radiance(&direction(45., 0.));
+        let away = sky.radiance(&direction(45., 180.));
+        assert!(near_sun.luminance() > 2. * away.luminance());
+        let horizon = sky.radiance(&direction(2., 90.));
+        assert!(horizon.luminance() > zenith.luminance());
+
+        // The low sun is dimmer and redder than the high one.
+        let sun = |elevation: f64| {
+            let sky = PhysicalSky::new(elevation, 0., 3., 1.);
+            sky.radiance(&direction(elevation, 0.))
+        };
+        let (high, low) = (sun(60.), sun(5.));
+        assert!(high.luminance() > 2. * low.luminance());
+        assert!(low.r() / low.b() > high.r() / high.b());
+    }
+}
@@ -1,155 +1,590 @@
-use color::Color;
-use image::{png::*, ColorType};
-use material::Material;
-use objects::{ObjectList, Sphere};
-use rand::prelude::*;
+use clap::{Parser, ValueEnum};
+use manta::adaptive::{AdaptiveSampling, Estimate};
+use manta::aov::{self, Aov, AovSet};
+use manta::background::Background;
+use manta::camera::CameraSettings;
+use manta::color::{self, Color};
+use manta::denoise::Denoiser;
+use manta::framebuffer::{Framebuffer, OutputFormat, Precision};
+use manta::light::{sky, LightList, PhysicalSky};
+use manta::material::{Interior, Material};
+use manta::objects::{Bvh, ObjectList, Plane, Sphere};
+use manta::random;
+use manta::scene::{RenderSettings, Scene};
+use manta::spaces::{FreeVec3, Point, Vec3};
+use manta::spectrum::{Channels, Wavelengths};
+use manta::texture::Checker;
+use manta::tonemap::{Operator, ToneMapping};
 use rayon::prelude::*;
-use spaces::{FreeVec3, Point, Vec3};
 use std::error::Error;
-use std::fs::File;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::process;
 use std::sync::Arc;
 
-mod camera;
-mod color;
-mod material;
-mod objects;
-mod ray;
-mod spaces;
-
-fn write_image(pixels: &[u8], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
-    let output = File::create("image.png")?;
-    let encoder = PngEncoder::new(output);
-    encoder.encode(&pixels, width, height, ColorType::Rgb8)?;
-    Ok(())
+/// Path tracer rendering scenes described in TOML files.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene description file
+    #[arg(required_unless_present = "preset", conflicts_with = "preset")]
+    scene: Option<PathBuf>,
+
+    /// Render a built-in scene instead of a scene file
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Image width in pixels, keeps the aspect ratio of the scene unless the height is
+    /// given too
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Image height in pixels, keeps the aspect ratio of the scene unless the width is
+    /// given too
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// Number of samples per pixel, the most any pixel takes with adaptive sampling
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    spp: Option<i32>,
+
+    /// Stop sampling pixels once the 95% confidence interval of their luminance is within
+    /// this fraction of it
+    #[arg(long, value_name = "THRESHOLD", value_parser = parse_positive)]
+    adaptive: Option<f64>,
+
+    /// Number of samples every pixel takes with adaptive sampling, which --adaptive or the
+    /// scene must enable [default: 16]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    min_spp: Option<i32>,
+
+    /// Maximum number of bounces of a ray
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    max_depth: Option<i32>,
+
+    /// Trace wavelengths rather than red, green and blue, showing dispersion
+    #[arg(long)]
+    spectral: bool,
+
+    /// Light the scene by a daylight sky with the sun this many degrees above the horizon
+    #[arg(long, value_parser = parse_elevation)]
+    sun_elevation: Option<f64>,
+
+    /// Azimuth of the sun of the sky in degrees, from -z towards +x
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_finite)]
+    sun_azimuth: Option<f64>,
+
+    /// Turbidity of the air of the sky, from 1.7 for a very clear sky to 10 for haze
+    #[arg(long, value_parser = parse_turbidity)]
+    turbidity: Option<f64>,
+
+    /// File the rendered image is written to, a .png image or a high dynamic range .exr
+    /// or .hdr one
+    #[arg(short, long, default_value = "image.png")]
+    output: PathBuf,
+
+    /// Store OpenEXR images with half-precision rather than single-precision floats
+    #[arg(long)]
+    half: bool,
+
+    /// Exposure of PNG images in stops, each doubling the brightness
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_finite)]
+    exposure: Option<f64>,
+
+    /// Tone mapping operator of PNG images: clamp, reinhard, aces or agx
+    #[arg(long, value_parser = parse_operator)]
+    tone_map: Option<Operator>,
+
+    /// Break up banding of PNG images by ordered dithering
+    #[arg(long)]
+    dither: bool,
+
+    /// Passes rendered alongside the image: depth, normal, albedo, object_id, material_id,
+    /// direct or indirect
+    #[arg(long = "aov", value_delimiter = ',', value_parser = parse_aov)]
+    aovs: Vec<Aov>,
+
+    /// Remove the noise of the image, guided by the albedo, normal and depth of the
+    /// first surfaces
+    #[arg(long)]
+    denoise: bool,
+
+    /// Number of rendering threads [default: number of logical CPUs]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Seed of the random number generator, makes the render reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Preset {
+    /// Final scene of "Ray Tracing in One Weekend"
+    RandomSpheres,
+}
+
+impl Args {
+    /// Overrides the settings of the scene by the ones given on the command line.
+    fn apply(&self, settings: &mut RenderSettings) -> Result<(), String> {
+        let aspect_ratio = settings.aspect_ratio();
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                settings.image_width = width as usize;
+                settings.image_height = height as usize;
+            }
+            (Some(width), None) => {
+                settings.image_width = width as usize;
+                settings.image_height = ((width as f64 / aspect_ratio) as usize).max(1);
+            }
+            (None, Some(height)) => {
+                settings.image_width = ((height as f64 * aspect_ratio) as usize).max(1);
+                settings.image_height = height as usize;
+            }
+            (None, None) => {}
+        }
+        if let Some(spp) = self.spp {
+            settings.samples_per_pixel = spp;
+        }
+        if let Some(threshold) = self.adaptive {
+            let min_samples = settings
+                .adaptive
+                .map_or(AdaptiveSampling::DEFAULT_MIN_SAMPLES, |a| a.min_samples);
+            settings.adaptive = Some(AdaptiveSampling {
+                min_samples,
+                threshold,
+            });
+        }
+        if let Some(min_spp) = self.min_spp {
+            match &mut settings.adaptive {
+                Some(adaptive) => adaptive.min_samples = min_spp,
+                None => return Err("--min-spp needs adaptive sampling, see --adaptive".into()),
+            }
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        settings.spectral |= self.spectral;
+        if let Some(exposure) = self.exposure {
+            settings.tone_mapping.exposure = exposure;
+        }
+        if let Some(operator) = self.tone_map {
+            settings.tone_mapping.operator = operator;
+        }
+        settings.tone_mapping.dither |= self.dither;
+        for &aov in &self.aovs {
+            settings.aovs.insert(aov);
+        }
+        settings.denoise |= self.denoise;
+        Ok(())
+    }
+
+    /// Daylight sky replacing `background` when any of its parameters are given on the
+    /// command line, keeping the other ones of a sky background.
+    fn sky(&self, background: &Background) -> Option<Background> {
+        if self.sun_elevation.is_none() && self.sun_azimuth.is_none() && self.turbidity.is_none() {
+            return None;
+        }
+        let (elevation, azimuth, turbidity, intensity) = match background {
+            Background::Sky(sky) => (
+                sky.elevation(),
+                sky.azimuth(),
+                sky.turbidity(),
+                sky.intensity(),
+            ),
+            _ => (45., 0., 3., 1.),
+        };
+        Some(Background::Sky(Arc::new(PhysicalSky::new(
+            self.sun_elevation.unwrap_or(elevation),
+            self.sun_azimuth.unwrap_or(azimuth),
+            self.turbidity.unwrap_or(turbidity),
+            intensity,
+        ))))
+    }
+}
+
+fn parse_in_range(value: &str, range: RangeInclusive<f64>) -> Result<f64, String> {
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a number", value))?;
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!(
+            "{} is not between {} and {}",
+            value,
+            range.start(),
+            range.end()
+        ))
+    }
+}
+
+fn parse_elevation(value: &str) -> Result<f64, String> {
+    parse_in_range(value, sky::ELEVATIONS)
+}
+
+fn parse_turbidity(value: &str) -> Result<f64, String> {
+    parse_in_range(value, sky::TURBIDITIES)
+}
+
+fn parse_finite(value: &str) -> Result<f64, String> {
+    parse_in_range(value, f64::MIN..=f64::MAX)
 }
 
-fn random_scene() -> ObjectList {
+fn parse_positive(value: &str) -> Result<f64, String> {
+    parse_in_range(value, f64::MIN_POSITIVE..=f64::MAX)
+}
+
+fn parse_aov(value: &str) -> Result<Aov, String> {
+    Aov::named(value).ok_or_else(|| format!("unknown pass `{}`, expected {}", value, Aov::NAMES))
+}
+
+fn parse_operator(value: &str) -> Result<Operator, String> {
+    Operator::named(value)
+        .ok_or_else(|| format!("unknown operator `{}`, expected {}", value, Operator::NAMES))
+}
+
+fn random_scene() -> Scene {
     let mut world = ObjectList {
         objects: Vec::new(),
     };
     // Ground
-    let material_ground = Material::Lambertian(Color::new(0.5, 0.5, 0.5));
-    world.add(Arc::new(Sphere::new(
-        Point::new(0., -1000., 0.),
-        1000.,
+    let checker = Checker::new(
+        Color::new(0.9, 0.9, 0.9).into(),
+        Color::new(0.2, 0.3, 0.1).into(),
+        0.32,
+    );
+    let material_ground = Material::Lambertian(Arc::new(checker));
+    world.add(Arc::new(Plane::new(
+        Point::new(0., 0., 0.),
+        FreeVec3::new(0., 1., 0.).into(),
         material_ground,
     )));
 
-    let mut rng = rand::thread_rng();
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat: f64 = rng.gen();
+            let choose_mat: f64 = random::random();
             let center = Point::new(
-                0.9 * rng.gen::<f64>() + (a as f64),
+                0.9 * random::random::<f64>() + (a as f64),
                 0.2,
-                0.9 * rng.gen::<f64>() + (b as f64),
+                0.9 * random::random::<f64>() + (b as f64),
             );
 
             if (center - Point::new(4., 0.2, 0.)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     // Diffuse
-                    let c1 = Color::new(rng.gen(), rng.gen(), rng.gen());
-                    let c2 = Color::new(rng.gen(), rng.gen(), rng.gen());
+                    let c1 = Color::new(random::random(), random::random(), random::random());
+                    let c2 = Color::new(random::random(), random::random(), random::random());
 
                     let albedo = c1 * c2;
-                    let sphere_material = Material::Lambertian(albedo);
+                    let sphere_material = Material::Lambertian(albedo.into());
                     world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
                 } else if choose_mat < 0.95 {
                     // Metal
                     let albedo = Color::new(
-                        rng.gen_range(0.5, 1.),
-                        rng.gen_range(0.5, 1.),
-                        rng.gen_range(0.5, 1.),
+                        random::gen_range(0.5, 1.),
+                        random::gen_range(0.5, 1.),
+                        random::gen_range(0.5, 1.),
                     );
-                    let fuzziness = rng.gen_range(0., 0.5);
+                    let fuzziness = random::gen_range(0., 0.5);
                     let sphere_material = Material::Metal {
-                        color: albedo,
+                        color: albedo.into(),
                         fuzziness,
                     };
                     world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
                 } else {
                     // Glass
-                    let sphere_material = Material::Dielectric(1.5);
+                    let sphere_material = Material::Dielectric(Interior::new(1.5));
                     world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
                 }
             }
         }
     }
-    let material1 = Material::Dielectric(1.5);
+    let material1 = Material::Dielectric(Interior::new(1.5));
     world.add(Arc::new(Sphere::new(Point::new(0., 1., 0.), 1., material1)));
 
-    let material2 = Material::Lambertian(Color::new(0.4, 0.2, 0.1));
+    let material2 = Material::Lambertian(Color::new(0.4, 0.2, 0.1).into());
     world.add(Arc::new(Sphere::new(
         Point::new(-4., 1., 0.),
         1.,
         material2,
     )));
     let material3 = Material::Metal {
-        color: Color::new(0.7, 0.6, 0.5),
+        color: Color::new(0.7, 0.6, 0.5).into(),
         fuzziness: 0.,
     };
     world.add(Arc::new(Sphere::new(Point::new(4., 1., 0.), 1., material3)));
 
-    world
-}
-
-fn main() {
-    // Image
     let aspect_ratio = 3.0 / 2.0;
     let image_width = 400;
-    let image_height = (image_width as f64 / aspect_ratio) as usize;
-    let samples_per_pixel = 100;
-    let max_depth = 50;
-
-    // World
-
-    let world = random_scene();
-
-    // Camera
-    let lookfrom = Point::new(13., 2., 3.);
-    let lookat = FreeVec3::new(0., 0., 0.);
-    let cam = camera::Camera::new(
-        lookfrom,
-        lookat,
-        FreeVec3::new(0., 1., 0.),
-        20.,
-        aspect_ratio,
-        0.1,
-        10.,
-    );
+    let settings = RenderSettings {
+        image_width,
+        image_height: (image_width as f64 / aspect_ratio) as usize,
+        samples_per_pixel: 100,
+        max_depth: 50,
+        spectral: false,
+        tone_mapping: ToneMapping::default(),
+        aovs: AovSet::default(),
+        denoise: false,
+        adaptive: None,
+    };
 
-    // Render
-    let mut pixels = vec![vec![0u8; 3 * image_width]; image_height]
+    let camera = CameraSettings {
+        lookfrom: Point::new(13., 2., 3.),
+        lookat: FreeVec3::new(0., 0., 0.),
+        vup: FreeVec3::new(0., 1., 0.),
+        vfov: 20.,
+        aperture: 0.1,
+        focus_dist: 10.,
+        shutter: (0., 1.),
+    };
+
+    Scene {
+        camera,
+        world,
+        lights: LightList::default(),
+        background: Background::sky(),
+        settings,
+    }
+}
+
+fn render(scene: Scene, seed: u64) -> Framebuffer {
+    let RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel,
+        max_depth,
+        spectral,
+        aovs,
+        denoise,
+        adaptive,
+        ..
+    } = scene.settings;
+    // The denoiser is guided by the passes.
+    let record = denoise || !aovs.is_empty();
+    let cam = scene.camera.build(scene.settings.aspect_ratio());
+    let world = Bvh::from(scene.world);
+    let background = scene.background;
+    let lights = scene.lights;
+
+    let black = Color::new(0., 0., 0.);
+    let row = vec![(black, black, aov::Record::default()); image_width];
+    let mut pixels = vec![row; image_height]
         .into_iter()
         .enumerate()
         .rev()
-        .collect::<Vec<(usize, Vec<u8>)>>();
-    pixels.par_iter_mut().for_each_init(
-        || rand::thread_rng(),
-        |rng, (j, chunk)| {
-            for i in 0..image_width {
-                let mut pixel_color = color::Color::new(0., 0., 0.);
-                for _ in 0..samples_per_pixel {
-                    let u = (i as f64 + rng.gen::<f64>()) / (image_width - 1) as f64;
-                    let v = (*j as f64 + rng.gen::<f64>()) / (image_height - 1) as f64;
-                    let r = cam.ray(u, v);
-                    pixel_color += color::ray_color(&r, &world, max_depth);
+        .collect::<Vec<(usize, Vec<(Color, Color, aov::Record)>)>>();
+    pixels.par_iter_mut().for_each(|(j, chunk)| {
+        // Stream 0 is used for building the scene.
+        random::reseed(seed, *j as u64 + 1);
+        for (i, (pixel, variance, passes_mean)) in chunk.iter_mut().enumerate() {
+            let mut estimate = Estimate::default();
+            let mut passes = aov::Accumulator::default();
+            for _ in 0..samples_per_pixel {
+                let u = (i as f64 + random::random::<f64>()) / image_width as f64;
+                let v = (*j as f64 + random::random::<f64>()) / image_height as f64;
+                let r = cam.ray(u, v);
+                let channels = if spectral {
+                    Channels::Spectral(Wavelengths::sample())
+                } else {
+                    Channels::Rgb
+                };
+                let mut sample = aov::Record::default();
+                let radiance = color::ray_color_with_aovs(
+                    &r,
+                    &world,
+                    &lights,
+                    &background,
+                    &channels,
+                    max_depth,
+                    &mut sample,
+                );
+                estimate.add(channels.to_rgb(radiance));
+                if record {
+                    sample.direct = channels.to_rgb(sample.direct);
+                    sample.indirect = channels.to_rgb(sample.indirect);
+                    passes.add(&sample);
+                }
+                if adaptive.is_some_and(|adaptive| adaptive.converged(&estimate)) {
+                    break;
                 }
-                let pixel_index = 3 * i;
-                pixel_color.write(samples_per_pixel, &mut chunk[pixel_index..pixel_index + 3]);
             }
-        },
-    );
-    write_image(
-        &pixels
-            .into_iter()
-            .map(|(_, chunk)| chunk)
-            .flatten()
-            .collect::<Vec<u8>>(),
-        image_width as u32,
-        image_height as u32,
-    )
-    .expect("error writing PNG file");
+            *pixel = estimate.mean();
+            *variance = estimate.variance_of_mean();
+            *passes_mean = passes.mean();
+        }
+    });
+    let mut image_pixels = Vec::with_capacity(image_width * image_height);
+    let mut variances = Vec::with_capacity(image_width * image_height);
+    let mut records = Vec::with_capacity(image_width * image_height);
+    for (pixel, variance, passes) in pixels.into_iter().flat_map(|(_, chunk)| chunk) {
+        image_pixels.push(pixel);
+        variances.push(variance);
+        records.push(passes);
+    }
+    let image = Framebuffer::new(image_width, image_height, image_pixels);
+    if !record {
+        image
+    } else if samples_per_pixel > 1 {
+        image.with_aovs(aovs, records).with_variances(variances)
+    } else {
+        image.with_aovs(aovs, records)
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    // Fail before rendering rather than after.
+    OutputFormat::from_path(&args.output)?;
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()?;
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    random::reseed(seed, 0);
+    let mut scene = match (&args.scene, args.preset) {
+        (Some(path), _) => Scene::load(path)?,
+        (None, Some(Preset::RandomSpheres)) => random_scene(),
+        (None, None) => unreachable!("clap requires a scene or a preset"),
+    };
+    args.apply(&mut scene.settings)?;
+    if let Some(sky) = args.sky(&scene.background) {
+        scene.set_background(sky);
+    }
+
+    let tone_mapping = scene.settings.tone_mapping;
+    let denoise = scene.settings.denoise;
+    let mut image = render(scene, seed);
+    if denoise {
+        image = Denoiser::default().denoise(&image);
+    }
+    let precision = if args.half {
+        Precision::Half
+    } else {
+        Precision::Float
+    };
+    image
+        .save(&args.output, &tone_mapping, precision)
+        .map_err(|error| format!("cannot write {}", error))?;
 
     eprintln!("\nDone.");
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(error) = run(&args) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scene of a checkered floor under the sky, seen level with the horizon.
+    fn horizon(adaptive: Option<AdaptiveSampling>) -> Scene {
+        let checker = Checker::new(
+            Color::new(0.9, 0.9, 0.9).into(),
+            Color::new(0.1, 0.1, 0.1).into(),
+            0.5,
+        );
+        let floor = Plane::new(
+            Point::new(0., 0., 0.),
+            FreeVec3::new(0., 1., 0.).into(),
+            Material::Lambertian(Arc::new(checker)),
+        );
+        let mut aovs = AovSet::default();
+        aovs.insert(Aov::Samples);
+        Scene {
+            camera: CameraSettings {
+                lookfrom: Point::new(0., 1., 0.),
+                lookat: FreeVec3::new(0., 1., -1.),
+                vup: FreeVec3::new(0., 1., 0.),
+                vfov: 60.,
+                aperture: 0.,
+                focus_dist: 1.,
+                shutter: (0., 0.),
+            },
+            world: ObjectList {
+                objects: vec![Arc::new(floor)],
+            },
+            lights: LightList::default(),
+            background: Background::sky(),
+            settings: RenderSettings {
+                image_width: 8,
+                image_height: 8,
+                samples_per_pixel: 64,
+                max_depth: 8,
+                spectral: false,
+                tone_mapping: ToneMapping::default(),
+                aovs,
+                denoise: false,
+                adaptive,
+            },
+        }
+    }
+
+    #[test]
+    fn stops_sampling_converged_pixels() {
+        let counts = |adaptive| -> Vec<u32> {
+            let image = render(horizon(adaptive), 7);
+            image.records().iter().map(|r| r.samples).collect()
+        };
+        assert!(counts(None).iter().all(|&n| n == 64));
+        let counts = counts(Some(AdaptiveSampling {
+            min_samples: 16,
+            threshold: 0.2,
+        }));
+        assert!(
+            counts.iter().all(|&n| (16..=64).contains(&n)),
+            "{:?}",
+            counts
+        );
+        // The smooth sky converges at once, pixels across the edges of the checkers
+        // take longer, some of them all the samples.
+        let (sky, floor) = counts.split_at(counts.len() / 2);
+        assert!(sky.iter().all(|&n| n == 16), "{:?}", sky);
+        assert!(floor.iter().any(|&n| n > 16 && n < 64), "{:?}", floor);
+        assert!(floor.contains(&64), "{:?}", floor);
+    }
+
+    #[test]
+    fn needs_adaptive_sampling_for_min_samples() {
+        let args = |extra: &[&str]| {
+            let mut args = vec!["manta", "--preset", "random-spheres", "--min-spp", "8"];
+            args.extend_from_slice(extra);
+            Args::try_parse_from(args).unwrap()
+        };
+        let mut settings = horizon(None).settings;
+        assert!(args(&[]).apply(&mut settings).is_err());
+        args(&["--adaptive", "0.1"]).apply(&mut settings).unwrap();
+        assert_eq!(settings.adaptive.map(|a| a.min_samples), Some(8));
+        // Scenes can turn adaptive sampling on themselves.
+        let mut settings = horizon(Some(AdaptiveSampling {
+            min_samples: 4,
+            threshold: 0.1,
+        }))
+        .settings;
+        args(&[]).apply(&mut settings).unwrap();
+        assert_eq!(settings.adaptive.map(|a| a.min_samples), Some(8));
+    }
+
+    #[test]
+    fn takes_negative_azimuths() {
+        let args = |azimuth: &str| {
+            Args::try_parse_from([
+                "manta",
+                "--preset",
+                "random-spheres",
+                "--sun-azimuth",
+                azimuth,
+            ])
+        };
+        assert_eq!(args("-30").unwrap().sun_azimuth, Some(-30.));
+        assert_eq!(args("-1e2").unwrap().sun_azimuth, Some(-100.));
+        assert!(args("-inf").is_err());
+    }
 }
@@ -0,0 +1,333 @@
+use crate::color::Color;
+use crate::random;
+use crate::spaces::vec3::{reflection, refraction};
+use crate::spaces::{FreeVec3, UnitVec3, Vec3};
+use std::f64::consts::PI;
+
+/// Direction chosen by a BSDF for continuing a path.
+pub struct BsdfSample {
+    /// Direction the light arrives from, i.e. the direction of the scattered ray.
+    pub direction: UnitVec3,
+    /// Value of the BSDF times the cosine factor, divided by the density of the sample.
+    pub weight: Color,
+    /// Solid-angle density of the direction, or the probability of choosing it for
+    /// delta samples.
+    pub pdf: f64,
+    /// Whether the direction was chosen among a few isolated directions, like the
+    /// reflection of a mirror, whose value cannot be evaluated for other directions.
+    pub is_delta: bool,
+}
+
+/// Scattering function at a single point of a surface or medium.
+///
+/// Directions point away from the point: `wo` towards the viewer and `wi` towards where
+/// the light comes from. Values of surfaces include the cosine factor of `wi` with the
+/// shading normal.
+pub trait Bsdf {
+    /// Samples the direction of the scattered ray, `None` if the path is absorbed.
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample>;
+
+    /// Value of the BSDF for the pair of directions, zero for delta BSDFs.
+    fn eval(&self, wo: &UnitVec3, wi: &UnitVec3) -> Color;
+
+    /// Solid-angle density of `sample` choosing `wi`, zero for delta BSDFs.
+    fn pdf(&self, wo: &UnitVec3, wi: &UnitVec3) -> f64;
+
+    /// Whether the BSDF scatters only into isolated directions, so that lights cannot be
+    /// sampled for it.
+    fn is_delta(&self) -> bool;
+}
+
+/// Ideal diffuse reflection, sampled proportionally to the cosine.
+pub struct Lambertian {
+    pub albedo: Color,
+    pub normal: UnitVec3,
+}
+
+impl Bsdf for Lambertian {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        // A uniform point on the unit sphere around the tip of the normal has a cosine
+        // distributed direction.
+        let direction = FreeVec3::from(self.normal) + UnitVec3::random_unit_vector();
+        let direction = if direction.length_squared() < 1e-12 {
+            self.normal
+        } else {
+            direction.into()
+        };
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo,
+            pdf: self.pdf(wo, &direction),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, _wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        self.albedo * (self.normal.dot(wi).max(0.) / PI)
+    }
+
+    fn pdf(&self, _wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        self.normal.dot(wi).max(0.) / PI
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// Mirror reflection perturbed by an offset uniformly distributed on a sphere of radius
+/// `fuzziness`. Perturbed directions below the surface are absorbed.
+pub struct Metal {
+    pub color: Color,
+    pub fuzziness: f64,
+    pub normal: UnitVec3,
+}
+
+impl Bsdf for Metal {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        let mirror = reflection(-*wo, self.normal);
+        if self.is_delta() {
+            return Some(BsdfSample {
+                direction: mirror.into(),
+                weight: self.color,
+                pdf: 1.,
+                is_delta: true,
+            });
+        }
+        let direction = UnitVec3::from(mirror + UnitVec3::random_unit_vector() * self.fuzziness);
+        if direction.dot(&self.normal) <= 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            direction,
+            weight: self.color,
+            pdf: self.pdf(wo, &direction),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        // Every sample is weighted by the color, so the value is the color times the
+        // density.
+        self.color * self.pdf(wo, wi)
+    }
+
+    fn pdf(&self, wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        if self.is_delta() || wi.dot(&self.normal) <= 0. {
+            return 0.;
+        }
+        // The sampled points `t wi` lie on the sphere of radius `fuzziness` around the
+        // mirror direction `r`, uniformly by area. Each intersection of the ray along `wi`
+        // with that sphere contributes the area density converted to solid angle.
+        let mirror = reflection(-*wo, self.normal);
+        let along = wi.dot(&mirror);
+        let discriminant = along * along - 1. + self.fuzziness * self.fuzziness;
+        if discriminant <= 0. {
+            return 0.;
+        }
+        let root = discriminant.sqrt();
+        [along - root, along + root]
+            .iter()
+            .filter(|&&t| t > 0.)
+            .map(|t| t * t / (4. * PI * self.fuzziness * root))
+            .sum()
+    }
+
+    fn is_delta(&self) -> bool {
+        self.fuzziness == 0.
+    }
+}
+
+/// Smooth boundary between two transparent media, choosing between reflection and
+/// refraction with the Fresnel reflectance of Schlick's approximation.
+pub struct Dielectric {
+    /// Refraction index of the side the normal points away from relative to the side it
+    /// points to.
+    pub relative_index: f64,
+    pub normal: UnitVec3,
+}
+
+impl Bsdf for Dielectric {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        let ratio = 1. / self.relative_index;
+        let cos_theta = wo.dot(&self.normal).min(1.);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let reflect_probability = if ratio * sin_theta > 1. {
+            1.
+        } else {
+            reflectance(cos_theta, ratio)
+        };
+        let (direction, pdf) = if reflect_probability > random::random() {
+            (reflection(-*wo, self.normal), reflect_probability)
+        } else {
+            (
+                refraction(-*wo, self.normal, ratio),
+                1. - reflect_probability,
+            )
+        };
+        Some(BsdfSample {
+            direction: direction.into(),
+            weight: Color::new(1., 1., 1.),
+            pdf,
+            is_delta: true,
+        })
+    }
+
+    fn eval(&self, _wo: &UnitVec3, _wi: &UnitVec3) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    fn pdf(&self, _wo: &UnitVec3, _wi: &UnitVec3) -> f64 {
+        0.
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+fn reflectance(cos: f64, ref_idx: f64) -> f64 {
+    // Use Schlick's reflectance approximation.
+    let r0 = (1. - ref_idx) / (1. + ref_idx);
+    let r0 = r0 * r0;
+    r0 + (1. - r0) * (1. - cos).powi(5)
+}
+
+/// Phase function of a medium scattering uniformly in all directions.
+pub struct Isotropic {
+    pub albedo: Color,
+}
+
+impl Bsdf for Isotropic {
+    fn sample(&self, _wo: &UnitVec3) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            direction: UnitVec3::random_unit_vector(),
+            weight: self.albedo,
+            pdf: 1. / (4. * PI),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, _wo: &UnitVec3, _wi: &UnitVec3) -> Color {
+        self.albedo * (1. / (4. * PI))
+    }
+
+    fn pdf(&self, _wo: &UnitVec3, _wi: &UnitVec3) -> f64 {
+        1. / (4. * PI)
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::chi_square::{assert_samples_match_pdf, Resolution};
+
+    fn normal() -> UnitVec3 {
+        FreeVec3::new(0.2, 1., -0.3).into()
+    }
+
+    fn grazing() -> UnitVec3 {
+        FreeVec3::new(1., 0.3, 0.2).into()
+    }
+
+    /// Checks that the weights of the samples are the values over the densities.
+    fn assert_consistent_weights(bsdf: &dyn Bsdf, wo: &UnitVec3) {
+        for _ in 0..100 {
+            if let Some(sample) = bsdf.sample(wo) {
+                let value = bsdf.eval(wo, &sample.direction);
+                let pdf = bsdf.pdf(wo, &sample.direction);
+                assert!((pdf - sample.pdf).abs() <= 1e-9 * pdf);
+                assert!((*(sample.weight * pdf).r() - value.r()).abs() <= 1e-9 * value.r());
+            }
+        }
+    }
+
+    #[test]
+    fn lambertian_samples_cosine() {
+        random::reseed(1, 0);
+        let bsdf = Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+            normal: normal(),
+        };
+        assert_samples_match_pdf(&bsdf, &grazing(), &normal(), &Resolution::FINE);
+        assert_consistent_weights(&bsdf, &grazing());
+    }
+
+    #[test]
+    fn metal_samples_fuzzy_reflection() {
+        random::reseed(2, 0);
+        // The density is singular at the rim of the cone of perturbed directions, which
+        // is at a cosine of 0.8 from the mirror direction for a fuzziness of 0.6.
+        for &fuzziness in &[0.6, 1.] {
+            let bsdf = Metal {
+                color: Color::new(0.9, 0.8, 0.7),
+                fuzziness,
+                normal: normal(),
+            };
+            for wo in &[normal(), grazing()] {
+                let mirror = reflection(-*wo, normal()).into();
+                assert_samples_match_pdf(&bsdf, wo, &mirror, &Resolution::FINE);
+                assert_consistent_weights(&bsdf, wo);
+            }
+        }
+        let mirror = Metal {
+            color: Color::new(1., 1., 1.),
+            fuzziness: 0.,
+            normal: normal(),
+        };
+        let sample = mirror.sample(&grazing()).unwrap();
+        assert!(sample.is_delta && mirror.is_delta());
+        assert!((sample.direction.dot(&normal()) - grazing().dot(&normal())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn isotropic_samples_sphere() {
+        random::reseed(3, 0);
+        let bsdf = Isotropic {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        };
+        assert_samples_match_pdf(&bsdf, &grazing(), &normal(), &Resolution::FINE);
+        assert_consistent_weights(&bsdf, &grazing());
+    }
+
+    #[test]
+    fn dielectric_chooses_by_reflectance() {
+        random::reseed(4, 0);
+        let bsdf = Dielectric {
+            relative_index: 1.5,
+            normal: normal(),
+        };
+        let wo = grazing();
+        let n = 100000;
+        let mut reflected = 0;
+        let mut reflect_probability = 0.;
+        for _ in 0..n {
+            let sample = bsdf.sample(&wo).unwrap();
+            assert!(sample.is_delta);
+            if sample.direction.dot(&normal()) > 0. {
+                reflected += 1;
+                reflect_probability = sample.pdf;
+            } else {
+                assert!(sample.direction.dot(&normal()) < -0.1);
+            }
+        }
+        // Binomial standard deviation of the number of reflections.
+        let expected = n as f64 * reflect_probability;
+        let deviation = (expected * (1. - reflect_probability)).sqrt();
+        assert!(reflect_probability > 0.1);
+        assert!((reflected as f64 - expected).abs() < 4. * deviation);
+
+        // Light inside the denser medium is reflected entirely beyond the critical angle.
+        let inside = Dielectric {
+            relative_index: 1. / 1.5,
+            normal: normal(),
+        };
+        let sample = inside.sample(&wo).unwrap();
+        assert_eq!(sample.pdf, 1.);
+        assert!(sample.direction.dot(&normal()) > 0.);
+    }
+}
@@ -0,0 +1,145 @@
+//! Statistical test of BSDF samplers against their densities.
+use super::bsdf::Bsdf;
+use crate::spaces::vec3::orthonormal_basis;
+use crate::spaces::{FreeVec3, UnitVec3, Vec3};
+use std::f64::consts::PI;
+
+/// Bounds of the number of subdivisions of a bin along each axis when integrating the
+/// density over it.
+const MIN_SUBDIVISIONS: usize = 4;
+const MAX_SUBDIVISIONS: usize = 64;
+
+/// Number of samples of a test and of the bins they are sorted into, more of which make
+/// the test more sensitive but slower.
+#[derive(Copy, Clone)]
+pub struct Resolution {
+    pub samples: usize,
+    pub cosine_bins: usize,
+    pub azimuth_bins: usize,
+}
+
+impl Resolution {
+    pub const FINE: Resolution = Resolution {
+        samples: 200_000,
+        cosine_bins: 20,
+        azimuth_bins: 40,
+    };
+}
+
+/// Runs Pearson's chi-square test on directions sampled for `wo`, binned by their cosine
+/// and azimuth around `axis`, against the counts expected from the density.
+/// Discontinuities and singularities of the density should be at boundaries of the bins
+/// of the cosine around `axis`, multiples of 0.1 for 20 bins. Absorbed samples form an
+/// extra bin that is expected to receive the probability missing from the density.
+/// Panics if the hypothesis that the samples follow the density is rejected at a
+/// significance level of 0.01 %.
+pub fn assert_samples_match_pdf(
+    bsdf: &dyn Bsdf,
+    wo: &UnitVec3,
+    axis: &UnitVec3,
+    resolution: &Resolution,
+) {
+    let Resolution {
+        samples,
+        cosine_bins,
+        azimuth_bins,
+    } = *resolution;
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let bin = |d: &UnitVec3| {
+        let cos = d.dot(axis).clamp(-1., 1.);
+        let phi = d.dot(&bitangent).atan2(d.dot(&tangent)) + PI;
+        let i = (((cos + 1.) / 2. * cosine_bins as f64) as usize).min(cosine_bins - 1);
+        let j = ((phi / (2. * PI) * azimuth_bins as f64) as usize).min(azimuth_bins - 1);
+        i * azimuth_bins + j
+    };
+
+    let absorbed = cosine_bins * azimuth_bins;
+    let mut observed = vec![0.; absorbed + 1];
+    for _ in 0..samples {
+        match bsdf.sample(wo) {
+            Some(sample) => observed[bin(&sample.direction)] += 1.,
+            None => observed[absorbed] += 1.,
+        }
+    }
+
+    // Midpoint rule in the cosine and the azimuth, which are uniform in solid angle. The
+    // cosine is substituted by a smoothstep within each bin, whose vanishing derivative
+    // cancels singularities like `1 / sqrt(cos - edge)` at the boundaries of the bins.
+    let (cos_step, phi_step) = (2. / cosine_bins as f64, 2. * PI / azimuth_bins as f64);
+    let integrate = |i: usize, j: usize, subdivisions: usize| {
+        let n = subdivisions as f64;
+        let mut integral = 0.;
+        for k in 0..subdivisions {
+            for l in 0..subdivisions {
+                let u = (k as f64 + 0.5) / n;
+                let cos = -1. + (i as f64 + u * u * (3. - 2. * u)) * cos_step;
+                let phi = (j as f64 + (l as f64 + 0.5) / n) * phi_step - PI;
+                let sin = (1. - cos * cos).max(0.).sqrt();
+                let d = UnitVec3::from(
+                    tangent * (sin * phi.cos())
+                        + bitangent * (sin * phi.sin())
+                        + FreeVec3::from(*axis) * cos,
+                );
+                integral += bsdf.pdf(wo, &d) * 6. * u * (1. - u);
+            }
+        }
+        integral * cos_step * phi_step / (n * n)
+    };
+    let mut expected = vec![0.; absorbed + 1];
+    for i in 0..cosine_bins {
+        for j in 0..azimuth_bins {
+            // Refine until the integral settles, which takes long only for bins holding
+            // a narrow peak of the density.
+            let mut subdivisions = MIN_SUBDIVISIONS;
+            let mut integral = integrate(i, j, subdivisions);
+            while subdivisions < MAX_SUBDIVISIONS {
+                subdivisions *= 2;
+                let refined = integrate(i, j, subdivisions);
+                let settled = (refined - integral).abs() <= 1e-3 * refined + 1e-7;
+                integral = refined;
+                if settled {
+                    break;
+                }
+            }
+            expected[i * azimuth_bins + j] = integral;
+        }
+    }
+    let total: f64 = expected.iter().sum();
+    assert!(total < 1.001, "density integrates to {}", total);
+    expected[absorbed] = (1. - total).max(0.);
+
+    // Bins with few expected samples are pooled so that the test statistic stays
+    // chi-square distributed.
+    let (mut statistic, mut bins) = (0., 0);
+    let (mut pooled_observed, mut pooled_expected) = (0., 0.);
+    for (o, e) in observed
+        .iter()
+        .zip(expected.iter().map(|p| p * samples as f64))
+    {
+        if e < 5. {
+            pooled_observed += o;
+            pooled_expected += e;
+        } else {
+            statistic += (o - e) * (o - e) / e;
+            bins += 1;
+        }
+    }
+    if pooled_expected > 0. {
+        statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        bins += 1;
+    } else {
+        assert_eq!(pooled_observed, 0., "samples in directions of zero density");
+    }
+
+    // Wilson-Hilferty approximation of the quantile of the chi-square distribution.
+    let dof = (bins - 1) as f64;
+    let z = 3.719;
+    let threshold = dof * (1. - 2. / (9. * dof) + z * (2. / (9. * dof)).sqrt()).powi(3);
+    assert!(
+        statistic < threshold,
+        "chi-square statistic {} exceeds {} for {} degrees of freedom",
+        statistic,
+        threshold,
+        dof
+    );
+}
@@ -0,0 +1,55 @@
+//! Refraction indices varying with the wavelength, which split white light into colors.
+
+/// Empirical formula of the refraction index of a transparent material, with wavelengths
+/// in micrometres.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, fitting glasses well over the visible range.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ b_i λ² / (λ² - c_i)`, the form of the data sheets of optical glasses.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Refraction index at `wavelength` in nanometres.
+    pub fn refraction_index(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength * 1e-3;
+        let squared = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b
+                    .iter()
+                    .zip(c)
+                    .map(|(b, c)| b * squared / (squared - c))
+                    .sum();
+                (1. + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_borosilicate_glass() {
+        // Schott N-BK7, whose index at the helium d line of 587.6 nm is 1.5168.
+        let sellmeier = Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((sellmeier.refraction_index(587.6) - 1.5168).abs() < 1e-4);
+        let cauchy = Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        for &wavelength in &[450., 550., 650.] {
+            let difference =
+                cauchy.refraction_index(wavelength) - sellmeier.refraction_index(wavelength);
+            assert!(difference.abs() < 2e-3);
+        }
+        assert!(sellmeier.refraction_index(450.) > sellmeier.refraction_index(650.));
+    }
+}
@@ -0,0 +1,537 @@
+//! Rough surfaces made of perfectly smooth microscopic facets whose normals follow the
+//! GGX distribution.
+//!
+//! Directions are sampled from the distribution of the normals visible from `wo`
+//! (Heitz, "Sampling the GGX Distribution of Visible Normals", 2018), and shadowing and
+//! masking use the height-correlated Smith function.
+use super::bsdf::{Bsdf, BsdfSample};
+use crate::color::Color;
+use crate::random;
+use crate::spaces::vec3::orthonormal_basis;
+use crate::spaces::{FreeVec3, UnitVec3, Vec3};
+use std::f64::consts::PI;
+
+/// Roughness below which surfaces are treated as perfectly smooth.
+pub(super) const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// Complex refraction index `eta + i k` of a conductor at the red, green and blue
+/// wavelengths.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+impl ComplexIor {
+    pub fn gold() -> Self {
+        ComplexIor {
+            eta: Color::new(0.143, 0.374, 1.442),
+            k: Color::new(3.983, 2.385, 1.603),
+        }
+    }
+
+    pub fn copper() -> Self {
+        ComplexIor {
+            eta: Color::new(0.200, 0.924, 1.102),
+            k: Color::new(3.912, 2.452, 2.142),
+        }
+    }
+
+    pub fn aluminium() -> Self {
+        ComplexIor {
+            eta: Color::new(1.657, 0.880, 0.521),
+            k: Color::new(9.224, 6.270, 4.837),
+        }
+    }
+
+    /// Preset of the given name, one of `gold`, `copper` and `aluminium`.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "gold" => Some(ComplexIor::gold()),
+            "copper" => Some(ComplexIor::copper()),
+            "aluminium" | "aluminum" => Some(ComplexIor::aluminium()),
+            _ => None,
+        }
+    }
+
+    /// Fresnel reflectance of the conductor seen from air.
+    pub fn reflectance(&self, cos: f64) -> Color {
+        let channel = |eta: f64, k: f64| conductor_reflectance(cos, eta, k);
+        Color::new(
+            channel(*self.eta.r(), *self.k.r()),
+            channel(*self.eta.g(), *self.k.g()),
+            channel(*self.eta.b(), *self.k.b()),
+        )
+    }
+}
+
+/// Width parameter of the GGX distribution for a perceptual roughness in `[0, 1]`.
+pub fn alpha(roughness: f64) -> f64 {
+    roughness * roughness
+}
+
+/// Exact Fresnel reflectance of a conductor for unpolarised light.
+fn conductor_reflectance(cos: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos * cos;
+    let sin2 = 1. - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2. * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+/// Exact Fresnel reflectance for unpolarised light arriving under `cos` at a boundary to
+/// a medium with relative refraction index `eta`, 1 for total internal reflection.
+pub fn dielectric_reflectance(cos: f64, eta: f64) -> f64 {
+    let sin2_t = (1. - cos * cos) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let rs = (cos - eta * cos_t) / (cos + eta * cos_t);
+    let rp = (eta * cos - cos_t) / (eta * cos + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Isotropic GGX distribution of microfacet normals with width `alpha`, in the local
+/// frame where the macroscopic normal is the z axis.
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    /// Density of microfacet normals per unit of projected area.
+    pub fn d(&self, m: &FreeVec3) -> f64 {
+        if m.z() <= 0. {
+            return 0.;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = m.z() * m.z() * (a2 - 1.) + 1.;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: &FreeVec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.
+    }
+
+    /// Fraction of the microfacets visible from `w`.
+    pub fn g1(&self, w: &FreeVec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both directions.
+    pub fn g2(&self, wo: &FreeVec3, wi: &FreeVec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, which `sample_visible` follows.
+    pub fn visible_pdf(&self, wo: &FreeVec3, m: &FreeVec3) -> f64 {
+        self.g1(wo) * wo.dot(m).max(0.) * self.d(m) / wo.z()
+    }
+
+    /// Samples a microfacet normal visible from `wo`, which must be above the surface.
+    pub fn sample_visible(&self, wo: &FreeVec3) -> FreeVec3 {
+        let a = self.alpha;
+        // Stretch the view direction to the configuration of a hemisphere.
+        let v = unit(FreeVec3::new(a * wo.x(), a * wo.y(), wo.z()));
+        let length2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if length2 > 0. {
+            FreeVec3::new(-v.y(), v.x(), 0.) / length2.sqrt()
+        } else {
+            FreeVec3::new(1., 0., 0.)
+        };
+        let t2 = v.cross(&t1);
+
+        // Uniform point on the disk, warped towards the part of the projected hemisphere
+        // that is visible.
+        let r = random::random::<f64>().sqrt();
+        let phi = 2. * PI * random::random::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + v.z());
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = t1 * p1 + t2 * p2 + v * (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+
+        unit(FreeVec3::new(a * n.x(), a * n.y(), n.z().max(1e-9)))
+    }
+}
+
+pub(super) fn unit(v: FreeVec3) -> FreeVec3 {
+    v / v.length()
+}
+
+/// Local frame around the shading normal.
+pub(super) struct Frame {
+    tangent: FreeVec3,
+    bitangent: FreeVec3,
+    normal: FreeVec3,
+}
+
+impl Frame {
+    pub(super) fn new(normal: &UnitVec3) -> Self {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        Frame {
+            tangent,
+            bitangent,
+            normal: (*normal).into(),
+        }
+    }
+
+    pub(super) fn to_local<T: Vec3>(&self, v: &T) -> FreeVec3 {
+        FreeVec3::new(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
+
+    pub(super) fn to_world(&self, v: &FreeVec3) -> UnitVec3 {
+        (self.tangent * v.x() + self.bitangent * v.y() + self.normal * v.z()).into()
+    }
+}
+
+pub(super) fn reflect(wo: &FreeVec3, m: &FreeVec3) -> FreeVec3 {
+    *m * (2. * wo.dot(m)) - *wo
+}
+
+/// Direction of `wo` refracted at a facet with normal `m` into a medium with relative
+/// refraction index `eta`, `None` for total internal reflection.
+fn refract(wo: &FreeVec3, m: &FreeVec3, eta: f64) -> Option<FreeVec3> {
+    let cos_o = wo.dot(m);
+    let sin2_t = (1. - cos_o * cos_o).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(*m * (cos_o / eta - cos_t) - *wo / eta)
+}
+
+/// Rough metal reflecting with the Fresnel reflectance of its complex refraction index.
+pub struct RoughConductor {
+    pub ior: ComplexIor,
+    pub alpha: f64,
+    pub normal: UnitVec3,
+}
+
+impl Bsdf for RoughConductor {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&self.normal);
+        let o = frame.to_local(wo);
+        if o.z() <= 0. {
+            return None;
+        }
+        if self.is_delta() {
+            let i = FreeVec3::new(-o.x(), -o.y(), o.z());
+            return Some(BsdfSample {
+                direction: frame.to_world(&i),
+                weight: self.ior.reflectance(o.z()),
+                pdf: 1.,
+                is_delta: true,
+            });
+        }
+        let ggx = Ggx { alpha: self.alpha };
+        let m = ggx.sample_visible(&o);
+        let i = reflect(&o, &m);
+        if i.z() <= 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: frame.to_world(&i),
+            weight: self.ior.reflectance(o.dot(&m)) * (ggx.g2(&o, &i) / ggx.g1(&o)),
+            pdf: ggx.visible_pdf(&o, &m) / (4. * o.dot(&m)),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        if self.is_delta() || o.z() <= 0. || i.z() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        let ggx = Ggx { alpha: self.alpha };
+        let m = unit(o + i);
+        self.ior.reflectance(o.dot(&m)) * (ggx.d(&m) * ggx.g2(&o, &i) / (4. * o.z()))
+    }
+
+    fn pdf(&self, wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        if self.is_delta() || o.z() <= 0. || i.z() <= 0. {
+            return 0.;
+        }
+        let ggx = Ggx { alpha: self.alpha };
+        let m = unit(o + i);
+        ggx.visible_pdf(&o, &m) / (4. * o.dot(&m))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+}
+
+/// Rough boundary between two transparent media (Walter et al., "Microfacet Models for
+/// Refraction through Rough Surfaces", 2007).
+pub struct RoughDielectric {
+    /// Refraction index of the side the normal points away from relative to the side it
+    /// points to.
+    pub relative_index: f64,
+    pub alpha: f64,
+    pub normal: UnitVec3,
+}
+
+impl RoughDielectric {
+    /// Microfacet normal turning `o` into `i` by reflection or refraction, facing the
+    /// side of the normal, and whether it is a reflection.
+    fn half_vector(&self, o: &FreeVec3, i: &FreeVec3) -> Option<(FreeVec3, bool)> {
+        let reflected = i.z() > 0.;
+        let m = if reflected {
+            *o + *i
+        } else {
+            -(*o + *i * self.relative_index)
+        };
+        if m.length_squared() == 0. {
+            return None;
+        }
+        let m = if m.z() < 0. { -unit(m) } else { unit(m) };
+        // Both directions must be on the same sides of the facet as of the surface.
+        if o.dot(&m) <= 0. || (i.dot(&m) > 0.) != reflected {
+            return None;
+        }
+        Some((m, reflected))
+    }
+
+    /// Change of the density of the microfacet normal to that of the refracted direction.
+    fn refraction_jacobian(&self, o: &FreeVec3, i: &FreeVec3, m: &FreeVec3) -> f64 {
+        let eta = self.relative_index;
+        let denominator = o.dot(m) + eta * i.dot(m);
+        eta * eta * i.dot(m).abs() / (denominator * denominator)
+    }
+}
+
+impl Bsdf for RoughDielectric {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&self.normal);
+        let o = frame.to_local(wo);
+        if o.z() <= 0. {
+            return None;
+        }
+        let eta = self.relative_index;
+        if self.is_delta() {
+            let m = FreeVec3::new(0., 0., 1.);
+            let reflectance = dielectric_reflectance(o.z(), eta);
+            let (i, pdf) = match refract(&o, &m, eta) {
+                Some(t) if random::random::<f64>() >= reflectance => (t, 1. - reflectance),
+                _ => (reflect(&o, &m), reflectance),
+            };
+            return Some(BsdfSample {
+                direction: frame.to_world(&i),
+                weight: Color::new(1., 1., 1.),
+                pdf,
+                is_delta: true,
+            });
+        }
+
+        let ggx = Ggx { alpha: self.alpha };
+        let m = ggx.sample_visible(&o);
+        let reflectance = dielectric_reflectance(o.dot(&m), eta);
+        let (i, pdf) = match refract(&o, &m, eta) {
+            Some(t) if random::random::<f64>() >= reflectance => {
+                if t.z() >= 0. {
+                    return None;
+                }
+                let jacobian = self.refraction_jacobian(&o, &t, &m);
+                (t, (1. - reflectance) * ggx.visible_pdf(&o, &m) * jacobian)
+            }
+            _ => {
+                let r = reflect(&o, &m);
+                if r.z() <= 0. {
+                    return None;
+                }
+                (r, reflectance * ggx.visible_pdf(&o, &m) / (4. * o.dot(&m)))
+            }
+        };
+        // The Fresnel factor cancels with the probability of choosing the event.
+        let weight = ggx.g2(&o, &i) / ggx.g1(&o);
+        Some(BsdfSample {
+            direction: frame.to_world(&i),
+            weight: Color::new(weight, weight, weight),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        let (m, reflected) = match self.half_vector(&o, &i) {
+            Some(half) if !self.is_delta() && o.z() > 0. => half,
+            _ => return Color::new(0., 0., 0.),
+        };
+        let ggx = Ggx { alpha: self.alpha };
+        let reflectance = dielectric_reflectance(o.dot(&m), self.relative_index);
+        let common = ggx.d(&m) * ggx.g2(&o, &i) / o.z();
+        // Like for smooth glass, radiance is not scaled by the squared ratio of the
+        // refraction indices when crossing the boundary.
+        let value = if reflected {
+            reflectance * common / 4.
+        } else {
+            (1. - reflectance) * common * o.dot(&m) * self.refraction_jacobian(&o, &i, &m)
+        };
+        Color::new(value, value, value)
+    }
+
+    fn pdf(&self, wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        let (m, reflected) = match self.half_vector(&o, &i) {
+            Some(half) if !self.is_delta() && o.z() > 0. => half,
+            _ => return 0.,
+        };
+        let ggx = Ggx { alpha: self.alpha };
+        let reflectance = dielectric_reflectance(o.dot(&m), self.relative_index);
+        if reflected {
+            reflectance * ggx.visible_pdf(&o, &m) / (4. * o.dot(&m))
+        } else {
+            (1. - reflectance) * ggx.visible_pdf(&o, &m) * self.refraction_jacobian(&o, &i, &m)
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::chi_square::{assert_samples_match_pdf, Resolution};
+
+    /// Fewer samples than for the simpler BSDFs, which keeps the many roughnesses,
+    /// indices and directions tested quick.
+    const RESOLUTION: Resolution = Resolution {
+        samples: 100_000,
+        ..Resolution::FINE
+    };
+
+    fn normal() -> UnitVec3 {
+        FreeVec3::new(0.2, 1., -0.3).into()
+    }
+
+    fn directions() -> Vec<UnitVec3> {
+        vec![normal(), FreeVec3::new(1., 0.3, 0.2).into()]
+    }
+
+    /// Checks that the weights of the samples are the values over the densities.
+    fn assert_consistent_weights(bsdf: &dyn Bsdf, wo: &UnitVec3) {
+        for _ in 0..1000 {
+            if let Some(sample) = bsdf.sample(wo) {
+                let value = bsdf.eval(wo, &sample.direction);
+                let pdf = bsdf.pdf(wo, &sample.direction);
+                assert!(
+                    (pdf - sample.pdf).abs() <= 1e-6 * pdf,
+                    "{} {}",
+                    pdf,
+                    sample.pdf
+                );
+                let weighted = *(sample.weight * pdf).g();
+                assert!((weighted - value.g()).abs() <= 1e-6 * value.g());
+            }
+        }
+    }
+
+    #[test]
+    fn normal_distribution_is_normalised() {
+        // The projected areas of the microfacets add up to the macroscopic area.
+        let ggx = Ggx { alpha: 0.3 };
+        let n = 2000;
+        let integral: f64 = (0..n)
+            .map(|i| {
+                let cos = (i as f64 + 0.5) / n as f64;
+                let m = FreeVec3::new((1. - cos * cos).sqrt(), 0., cos);
+                ggx.d(&m) * cos * 2. * PI / n as f64
+            })
+            .sum();
+        assert!((integral - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fresnel_reflectance() {
+        assert!((dielectric_reflectance(1., 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(dielectric_reflectance(0.1, 1. / 1.5), 1.);
+        // A conductor without absorption reflects like a dielectric.
+        let cos = 0.7;
+        let expected = dielectric_reflectance(cos, 1.5);
+        assert!((conductor_reflectance(cos, 1.5, 0.) - expected).abs() < 1e-12);
+        let gold = ComplexIor::gold().reflectance(1.);
+        assert!(gold.r() > gold.b());
+        assert!(*ComplexIor::aluminium().reflectance(1.).g() > 0.9);
+    }
+
+    #[test]
+    fn conductor_samples_visible_normals() {
+        random::reseed(5, 0);
+        for &alpha in &[0.2, 0.5] {
+            let bsdf = RoughConductor {
+                ior: ComplexIor::copper(),
+                alpha,
+                normal: normal(),
+            };
+            for wo in &directions() {
+                assert_samples_match_pdf(&bsdf, wo, &normal(), &RESOLUTION);
+                assert_consistent_weights(&bsdf, wo);
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_samples_visible_normals() {
+        random::reseed(6, 0);
+        for &eta in &[1.5, 1. / 1.33] {
+            let bsdf = RoughDielectric {
+                relative_index: eta,
+                alpha: 0.4,
+                normal: normal(),
+            };
+            for wo in &directions() {
+                assert_samples_match_pdf(&bsdf, wo, &normal(), &RESOLUTION);
+                assert_consistent_weights(&bsdf, wo);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_limit_is_specular() {
+        random::reseed(7, 0);
+        let wo = directions()[1];
+        let conductor = RoughConductor {
+            ior: ComplexIor::gold(),
+            alpha: 0.,
+            normal: normal(),
+        };
+        let sample = conductor.sample(&wo).unwrap();
+        assert!(sample.is_delta);
+        assert!((sample.direction.dot(&normal()) - wo.dot(&normal())).abs() < 1e-12);
+
+        let glass = RoughDielectric {
+            relative_index: 1.5,
+            alpha: 0.,
+            normal: normal(),
+        };
+        let refracted = (0..100)
+            .map(|_| glass.sample(&wo).unwrap())
+            .find(|s| s.direction.dot(&normal()) < 0.)
+            .unwrap();
+        // Snell's law for the sines of the angles to the normal.
+        let sin = |d: &UnitVec3| (1. - d.dot(&normal()).powi(2)).sqrt();
+        assert!((sin(&wo) - 1.5 * sin(&refracted.direction)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,164 @@
+//! Materials of surfaces and media, and the scattering functions they have at a point.
+pub mod bsdf;
+#[cfg(test)]
+mod chi_square;
+pub mod dispersion;
+pub mod microfacet;
+pub mod nested;
+pub mod principled;
+
+pub use bsdf::{Bsdf, BsdfSample};
+pub use dispersion::Dispersion;
+pub use microfacet::ComplexIor;
+pub use nested::{Interior, InteriorStack};
+pub use principled::Principled;
+
+use crate::color::Color;
+use crate::objects::HitRecord;
+use crate::texture::Texture;
+use bsdf::{Dielectric, Isotropic, Lambertian, Metal};
+use microfacet::{RoughConductor, RoughDielectric};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub enum Material {
+    Lambertian(Arc<dyn Texture + Send + Sync>),
+    Metal {
+        color: Arc<dyn Texture + Send + Sync>,
+        fuzziness: f64,
+    },
+    /// Smooth glass or liquid, which absorbs light inside and may be nested in other
+    /// dielectrics.
+    Dielectric(Interior),
+    /// Metal with a GGX microfacet surface, `roughness` 0 being a perfect mirror.
+    Conductor {
+        ior: ComplexIor,
+        roughness: f64,
+    },
+    /// Glass with a GGX microfacet surface, `roughness` 0 being perfectly smooth.
+    RoughDielectric {
+        refraction_index: f64,
+        roughness: f64,
+    },
+    /// Uber material blending diffuse, metallic, glossy and transmissive looks.
+    Principled(Arc<Principled>),
+    /// Emits light from the front side of the surface and does not scatter.
+    DiffuseLight(Color),
+    /// Phase function of participating media, scattering uniformly in all directions.
+    Isotropic(Arc<dyn Texture + Send + Sync>),
+    /// Isotropic phase function of a medium that also emits light at the collision.
+    EmissiveIsotropic {
+        albedo: Arc<dyn Texture + Send + Sync>,
+        emission: Color,
+    },
+}
+
+/// Light emitted by the surface at the hit point.
+pub fn emitted(hit: &HitRecord) -> Color {
+    match &hit.material {
+        Material::DiffuseLight(emission) if hit.front_face => *emission,
+        Material::EmissiveIsotropic { emission, .. } => *emission,
+        _ => Color::new(0., 0., 0.),
+    }
+}
+
+impl Material {
+    /// Color of the material at the hit point, as seen by guides of denoisers: the
+    /// reflectance at normal incidence of surfaces, white for clear dielectrics and the
+    /// hue of the emission of lights.
+    pub fn albedo(&self, hit: &HitRecord) -> Color {
+        let white = Color::new(1., 1., 1.);
+        match self {
+            Material::Lambertian(texture)
+            | Material::Metal { color: texture, .. }
+            | Material::Isotropic(texture)
+            | Material::EmissiveIsotropic {
+                albedo: texture, ..
+            } => texture.value(hit.u, hit.v, &hit.p),
+            Material::Dielectric(_) | Material::RoughDielectric { .. } => white,
+            Material::Conductor { ior, .. } => ior.reflectance(1.),
+            Material::Principled(principled) => principled.base_color.value(hit.u, hit.v, &hit.p),
+            Material::DiffuseLight(emission) => {
+                let max = emission.r().max(*emission.g()).max(*emission.b());
+                if max > 0. {
+                    *emission * (1. / max)
+                } else {
+                    white
+                }
+            }
+        }
+    }
+
+    /// Scattering function at the hit point, `None` for materials that only emit.
+    pub fn bsdf(&self, hit: &HitRecord) -> Option<Box<dyn Bsdf>> {
+        let normal = hit.normal;
+        Some(match self {
+            Material::Lambertian(texture) => Box::new(Lambertian {
+                albedo: texture.value(hit.u, hit.v, &hit.p),
+                normal,
+            }),
+            Material::Metal { color, fuzziness } => Box::new(Metal {
+                color: color.value(hit.u, hit.v, &hit.p),
+                fuzziness: *fuzziness,
+                normal,
+            }),
+            Material::Dielectric(interior) => Box::new(Dielectric {
+                relative_index: if hit.front_face {
+                    interior.refraction_index
+                } else {
+                    1. / interior.refraction_index
+                },
+                normal,
+            }),
+            &Material::Conductor { ior, roughness } => Box::new(RoughConductor {
+                ior,
+                alpha: microfacet::alpha(roughness),
+                normal,
+            }),
+            &Material::RoughDielectric {
+                refraction_index,
+                roughness,
+            } => Box::new(RoughDielectric {
+                relative_index: if hit.front_face {
+                    refraction_index
+                } else {
+                    1. / refraction_index
+                },
+                alpha: microfacet::alpha(roughness),
+                normal,
+            }),
+            Material::Principled(principled) => Box::new(principled.bsdf(hit)),
+            Material::DiffuseLight(_) => return None,
+            Material::Isotropic(albedo) | Material::EmissiveIsotropic { albedo, .. } => {
+                Box::new(Isotropic {
+                    albedo: albedo.value(hit.u, hit.v, &hit.p),
+                })
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Object, Sphere};
+    use crate::ray::Ray;
+    use crate::spaces::{FreeVec3, Point};
+
+    #[test]
+    fn lights_emit_from_the_front_only() {
+        let emission = Color::new(4., 2., 1.);
+        let lamp = Sphere::new(Point::new(0., 0., 0.), 1., Material::DiffuseLight(emission));
+        let emitted_towards = |origin: Point| {
+            let ray = Ray::new(&origin, &FreeVec3::new(0., 0., -1.).into(), 0.);
+            let hit = lamp.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            emitted(&hit)
+        };
+        assert_eq!(emitted_towards(Point::new(0., 0., 5.)), emission);
+        // From inside, the ray meets the back of the surface.
+        assert_eq!(
+            emitted_towards(Point::new(0., 0., 0.)),
+            Color::new(0., 0., 0.)
+        );
+    }
+}
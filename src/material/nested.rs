@@ -0,0 +1,173 @@
+//! Glass and liquids whose volumes touch or overlap, after Schmidt and Budge, "Simple
+//! Nested Dielectrics in Ray Traced Images" (2002).
+//!
+//! Paths keep a stack of the dielectric interiors they are inside of. Where volumes
+//! overlap, the interior of the highest priority wins, and surfaces of interiors of lower
+//! priority within it are ignored. This way a liquid can be modelled slightly overlapping
+//! the inside of its glass, and the boundary between them refracts with the ratio of their
+//! refraction indices.
+use super::Dispersion;
+use crate::color::Color;
+use crate::spectrum::{Channels, SODIUM_D};
+
+/// Interior of a dielectric object, which absorbs light following the Beer-Lambert law.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Interior {
+    /// Refraction index at the sodium D line.
+    pub refraction_index: f64,
+    /// Variation of the refraction index with the wavelength, if any.
+    pub dispersion: Option<Dispersion>,
+    /// Absorption coefficients per unit of distance for red, green and blue.
+    pub absorption: Color,
+    /// Interiors of higher priority take precedence where volumes overlap.
+    pub priority: u32,
+}
+
+impl Interior {
+    /// Clear interior of the lowest priority.
+    pub fn new(refraction_index: f64) -> Self {
+        Interior {
+            refraction_index,
+            dispersion: None,
+            absorption: Color::new(0., 0., 0.),
+            priority: 0,
+        }
+    }
+
+    /// Clear dispersive interior of the lowest priority.
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Interior {
+            dispersion: Some(dispersion),
+            ..Interior::new(dispersion.refraction_index(SODIUM_D))
+        }
+    }
+
+    pub fn absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Refraction index at `wavelength` in nanometres.
+    pub fn refraction_index_at(&self, wavelength: f64) -> f64 {
+        match self.dispersion {
+            Some(dispersion) => dispersion.refraction_index(wavelength),
+            None => self.refraction_index,
+        }
+    }
+
+    /// Fraction of light left in each of the `channels` after travelling `distance`
+    /// through the interior.
+    pub fn transmittance(&self, distance: f64, channels: &Channels) -> Color {
+        let a = channels.reflectance(self.absorption);
+        let channel = |a: f64| if a == 0. { 1. } else { (-a * distance).exp() };
+        Color::new(channel(*a.r()), channel(*a.g()), channel(*a.b()))
+    }
+}
+
+/// Interiors a path is inside of, in the order they were entered.
+#[derive(Clone, Debug, Default)]
+pub struct InteriorStack {
+    interiors: Vec<Interior>,
+}
+
+impl InteriorStack {
+    /// The interior the path is in, the most recently entered one of the highest
+    /// priority.
+    pub fn current(&self) -> Option<&Interior> {
+        self.interiors
+            .iter()
+            .max_by_key(|interior| interior.priority)
+    }
+
+    /// Fraction of light left in each of the `channels` after travelling `distance`
+    /// through the current interior.
+    pub fn transmittance(&self, distance: f64, channels: &Channels) -> Color {
+        match self.current() {
+            Some(interior) => interior.transmittance(distance, channels),
+            None => Color::new(1., 1., 1.),
+        }
+    }
+
+    /// Refraction index at `wavelength` beyond the surface of `interior` relative to the
+    /// one on the side of the path, which is `entering` the interior or leaving it. `None`
+    /// if the surface lies within an interior of higher priority and is to be passed
+    /// straight through.
+    pub fn boundary(&self, interior: &Interior, entering: bool, wavelength: f64) -> Option<f64> {
+        let index = |i: &Interior| i.refraction_index_at(wavelength);
+        let outside = |i: Option<&Interior>| i.map_or(1., index);
+        if entering {
+            let current = self.current();
+            if current.is_some_and(|c| c.priority > interior.priority) {
+                return None;
+            }
+            return Some(index(interior) / outside(current));
+        }
+
+        let mut remaining = self.clone();
+        if let Some(position) = self.position(interior) {
+            if self.current() != Some(interior) {
+                return None;
+            }
+            remaining.interiors.remove(position);
+        }
+        Some(outside(remaining.current()) / index(interior))
+    }
+
+    /// Records that the path passed through the surface of `interior`.
+    pub fn cross(&mut self, interior: Interior, entering: bool) {
+        if entering {
+            self.interiors.push(interior);
+        } else if let Some(position) = self.position(&interior) {
+            self.interiors.remove(position);
+        }
+    }
+
+    /// Position of the last entered interior equal to `interior`, interiors with the same
+    /// properties are interchangeable.
+    fn position(&self, interior: &Interior) -> Option<usize> {
+        self.interiors.iter().rposition(|i| i == interior)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn liquid_in_glass() {
+        let glass = Interior::new(1.5).priority(2);
+        let water = Interior::new(1.33)
+            .absorption(Color::new(0.5, 0.1, 0.))
+            .priority(1);
+        let mut stack = InteriorStack::default();
+
+        // Entering the glass from air.
+        assert_eq!(stack.boundary(&glass, true, SODIUM_D), Some(1.5));
+        stack.cross(glass, true);
+        // The surface of the water overlapping the glass is hidden by it.
+        assert_eq!(stack.boundary(&water, true, SODIUM_D), None);
+        stack.cross(water, true);
+        assert_eq!(stack.current(), Some(&glass));
+        // Leaving the glass into the water.
+        assert_eq!(stack.boundary(&glass, false, SODIUM_D), Some(1.33 / 1.5));
+        stack.cross(glass, false);
+        assert_eq!(stack.current(), Some(&water));
+
+        let transmittance = stack.transmittance(2., &Channels::Rgb);
+        assert!((transmittance.r() - (-1f64).exp()).abs() < 1e-12);
+        assert_eq!(*transmittance.b(), 1.);
+
+        // Leaving the water through its bottom, where it overlaps the glass again.
+        stack.cross(glass, true);
+        assert_eq!(stack.boundary(&water, false, SODIUM_D), None);
+        stack.cross(water, false);
+        assert_eq!(stack.boundary(&glass, false, SODIUM_D), Some(1. / 1.5));
+        stack.cross(glass, false);
+        assert!(stack.current().is_none());
+    }
+}
@@ -0,0 +1,394 @@
+//! Principled material after Burley, "Physically Based Shading at Disney" (2012) and
+//! "Extending the Disney BRDF to a BSDF with Integrated Subsurface Scattering" (2015).
+//!
+//! The BSDF mixes a diffuse lobe with retro-reflection, sheen and a flattening that
+//! approximates subsurface scattering, a GGX specular lobe, a rough glass lobe for
+//! transmission and a clearcoat layer. One lobe is sampled at a time and weighted against
+//! the densities of all of them.
+use super::bsdf::{Bsdf, BsdfSample};
+use super::microfacet::{reflect, unit, Frame, Ggx, RoughDielectric, SMOOTH_ALPHA};
+use crate::color::Color;
+use crate::objects::HitRecord;
+use crate::random;
+use crate::spaces::{FreeVec3, UnitVec3, Vec3};
+use crate::texture::Texture;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Width of the GGX distribution of the clearcoat layer.
+const CLEARCOAT_ALPHA: f64 = 0.05;
+
+/// Parameters of the principled material, each given by a texture. Scalar parameters
+/// are the luminance of their texture, clamped to `[0, 1]`.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture + Send + Sync>,
+    /// Blend from a dielectric to a metal whose reflectance is the base color.
+    pub metallic: Arc<dyn Texture + Send + Sync>,
+    pub roughness: Arc<dyn Texture + Send + Sync>,
+    /// Reflectance of dielectrics at normal incidence, 1 being 8 %. It also sets the
+    /// refraction index of transmission, 0.5 giving 1.5.
+    pub specular: Arc<dyn Texture + Send + Sync>,
+    /// Additional white reflection at grazing angles, as seen on cloth.
+    pub sheen: Arc<dyn Texture + Send + Sync>,
+    /// Strength of a second, glossy and uncolored specular layer.
+    pub clearcoat: Arc<dyn Texture + Send + Sync>,
+    /// Blend from an opaque to a glass-like dielectric.
+    pub transmission: Arc<dyn Texture + Send + Sync>,
+    /// Blend of the diffuse lobe to the flatter look of subsurface scattering.
+    pub subsurface: Arc<dyn Texture + Send + Sync>,
+}
+
+impl Principled {
+    /// Rough dielectric of the given color, with all other parameters at their defaults.
+    pub fn new(base_color: Arc<dyn Texture + Send + Sync>) -> Self {
+        let constant = |x: f64| Color::new(x, x, x).into();
+        Principled {
+            base_color,
+            metallic: constant(0.),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.),
+            clearcoat: constant(0.),
+            transmission: constant(0.),
+            subsurface: constant(0.),
+        }
+    }
+
+    /// Scattering function with the parameters at the hit point.
+    pub fn bsdf(&self, hit: &HitRecord) -> PrincipledBsdf {
+        let scalar = |texture: &Arc<dyn Texture + Send + Sync>| {
+            texture
+                .value(hit.u, hit.v, &hit.p)
+                .luminance()
+                .clamp(0., 1.)
+        };
+        let specular = scalar(&self.specular);
+        // Refraction index whose reflectance at normal incidence is that of `specular`.
+        let r0 = (0.08 * specular).sqrt().min(0.99);
+        let refraction_index = (1. + r0) / (1. - r0);
+        PrincipledBsdf {
+            base_color: self.base_color.value(hit.u, hit.v, &hit.p),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular,
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+            subsurface: scalar(&self.subsurface),
+            relative_index: if hit.front_face {
+                refraction_index
+            } else {
+                1. / refraction_index
+            },
+            normal: hit.normal,
+        }
+    }
+}
+
+/// Principled BSDF at a single point, see the module documentation.
+pub struct PrincipledBsdf {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub transmission: f64,
+    pub subsurface: f64,
+    /// Refraction index of the side the normal points away from relative to the side it
+    /// points to.
+    pub relative_index: f64,
+    pub normal: UnitVec3,
+}
+
+/// Indices of the lobes in the sampling probabilities.
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const GLASS: usize = 2;
+const CLEARCOAT: usize = 3;
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1. - cos).clamp(0., 1.).powi(5)
+}
+
+impl PrincipledBsdf {
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(SMOOTH_ALPHA)
+    }
+
+    /// Probabilities of sampling the diffuse, specular, glass and clearcoat lobes.
+    fn lobe_probabilities(&self) -> [f64; 4] {
+        let dielectric = 1. - self.metallic;
+        let weights = [
+            dielectric * (1. - self.transmission),
+            // Even rough dielectrics show a noticeable highlight.
+            (1. - dielectric * self.transmission).max(0.1),
+            dielectric * self.transmission,
+            0.25 * self.clearcoat,
+        ];
+        let total: f64 = weights.iter().sum();
+        let mut probabilities = [0.; 4];
+        for (p, w) in probabilities.iter_mut().zip(weights.iter()) {
+            *p = w / total;
+        }
+        probabilities
+    }
+
+    fn glass(&self) -> RoughDielectric {
+        RoughDielectric {
+            relative_index: self.relative_index,
+            alpha: self.alpha(),
+            normal: self.normal,
+        }
+    }
+
+    /// Reflectance of the specular lobe, untinted for dielectrics and the base color for
+    /// metals.
+    fn specular_reflectance(&self, cos: f64) -> Color {
+        let dielectric = 0.08 * self.specular;
+        let f0 = Color::new(dielectric, dielectric, dielectric) * (1. - self.metallic)
+            + self.base_color * self.metallic;
+        let white = Color::new(1., 1., 1.);
+        let weight = schlick_weight(cos);
+        f0 * (1. - weight) + white * weight
+    }
+
+    /// Values of the lobes in the local frame, including the cosine factor.
+    fn lobe_values(&self, o: &FreeVec3, i: &FreeVec3, wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        let black = Color::new(0., 0., 0.);
+        let dielectric = 1. - self.metallic;
+        let mut value = if dielectric * self.transmission > 0. {
+            self.glass().eval(wo, wi) * (dielectric * self.transmission)
+        } else {
+            black
+        };
+        if o.z() <= 0. || i.z() <= 0. {
+            return value;
+        }
+
+        let h = unit(*o + *i);
+        let cos_d = i.dot(&h);
+        let (f_o, f_i) = (schlick_weight(o.z()), schlick_weight(i.z()));
+
+        // Diffuse with retro-reflection at grazing angles for rough surfaces, blended
+        // with the flatter response of subsurface scattering.
+        let fd90 = 0.5 + 2. * self.roughness * cos_d * cos_d;
+        let diffuse = (1. + (fd90 - 1.) * f_o) * (1. + (fd90 - 1.) * f_i);
+        let fss90 = self.roughness * cos_d * cos_d;
+        let fss = (1. + (fss90 - 1.) * f_o) * (1. + (fss90 - 1.) * f_i);
+        let subsurface = 1.25 * (fss * (1. / (o.z() + i.z()) - 0.5) + 0.5);
+        let lambert = diffuse * (1. - self.subsurface) + subsurface * self.subsurface;
+        let sheen = self.sheen * schlick_weight(cos_d);
+        let diffuse_weight = dielectric * (1. - self.transmission) * i.z();
+        value +=
+            (self.base_color * (lambert / PI) + Color::new(sheen, sheen, sheen)) * diffuse_weight;
+
+        let ggx = Ggx {
+            alpha: self.alpha(),
+        };
+        // Transmissive dielectrics reflect through the glass lobe instead.
+        let specular_weight = 1. - dielectric * self.transmission;
+        let specular = specular_weight * ggx.d(&h) * ggx.g2(o, i) / (4. * o.z());
+        value += self.specular_reflectance(o.dot(&h)) * specular;
+
+        if self.clearcoat > 0. {
+            let coat = Ggx {
+                alpha: CLEARCOAT_ALPHA,
+            };
+            let fresnel = 0.04 + 0.96 * schlick_weight(o.dot(&h));
+            let c = 0.25 * self.clearcoat * fresnel * coat.d(&h) * coat.g2(o, i) / (4. * o.z());
+            value += Color::new(c, c, c);
+        }
+        value
+    }
+
+    /// Densities of the lobes weighted by their probabilities.
+    fn lobe_pdf(&self, o: &FreeVec3, i: &FreeVec3, wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        let p = self.lobe_probabilities();
+        let mut pdf = if p[GLASS] > 0. {
+            p[GLASS] * self.glass().pdf(wo, wi)
+        } else {
+            0.
+        };
+        if o.z() <= 0. || i.z() <= 0. {
+            return pdf;
+        }
+        let h = unit(*o + *i);
+        pdf += p[DIFFUSE] * i.z() / PI;
+        let reflection = |alpha| Ggx { alpha }.visible_pdf(o, &h) / (4. * o.dot(&h));
+        pdf += p[SPECULAR] * reflection(self.alpha());
+        if p[CLEARCOAT] > 0. {
+            pdf += p[CLEARCOAT] * reflection(CLEARCOAT_ALPHA);
+        }
+        pdf
+    }
+}
+
+impl Bsdf for PrincipledBsdf {
+    fn sample(&self, wo: &UnitVec3) -> Option<BsdfSample> {
+        let frame = Frame::new(&self.normal);
+        let o = frame.to_local(wo);
+        if o.z() <= 0. {
+            return None;
+        }
+        let p = self.lobe_probabilities();
+        let u = random::random::<f64>();
+        let lobe = if u < p[DIFFUSE] {
+            DIFFUSE
+        } else if u < p[DIFFUSE] + p[SPECULAR] {
+            SPECULAR
+        } else if u < p[DIFFUSE] + p[SPECULAR] + p[GLASS] {
+            GLASS
+        } else {
+            CLEARCOAT
+        };
+
+        let direction = match lobe {
+            DIFFUSE => {
+                let d = FreeVec3::from(self.normal) + UnitVec3::random_unit_vector();
+                if d.length_squared() < 1e-12 {
+                    self.normal
+                } else {
+                    d.into()
+                }
+            }
+            GLASS => self.glass().sample(wo)?.direction,
+            _ => {
+                let alpha = if lobe == SPECULAR {
+                    self.alpha()
+                } else {
+                    CLEARCOAT_ALPHA
+                };
+                let m = Ggx { alpha }.sample_visible(&o);
+                let i = reflect(&o, &m);
+                if i.z() <= 0. {
+                    return None;
+                }
+                frame.to_world(&i)
+            }
+        };
+
+        let i = frame.to_local(&direction);
+        let pdf = self.lobe_pdf(&o, &i, wo, &direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            direction,
+            weight: self.lobe_values(&o, &i, wo, &direction) * (1. / pdf),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, wo: &UnitVec3, wi: &UnitVec3) -> Color {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        if o.z() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        self.lobe_values(&o, &i, wo, wi)
+    }
+
+    fn pdf(&self, wo: &UnitVec3, wi: &UnitVec3) -> f64 {
+        let frame = Frame::new(&self.normal);
+        let (o, i) = (frame.to_local(wo), frame.to_local(wi));
+        if o.z() <= 0. {
+            return 0.;
+        }
+        self.lobe_pdf(&o, &i, wo, wi)
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::chi_square::{assert_samples_match_pdf, Resolution};
+
+    fn normal() -> UnitVec3 {
+        FreeVec3::new(0.2, 1., -0.3).into()
+    }
+
+    fn bsdf(metallic: f64, transmission: f64) -> PrincipledBsdf {
+        rough_bsdf(metallic, 0.5, transmission)
+    }
+
+    fn rough_bsdf(metallic: f64, roughness: f64, transmission: f64) -> PrincipledBsdf {
+        PrincipledBsdf {
+            base_color: Color::new(0.8, 0.4, 0.2),
+            metallic,
+            roughness,
+            specular: 0.5,
+            sheen: 0.5,
+            clearcoat: 0.5,
+            transmission,
+            subsurface: 0.5,
+            relative_index: 1.5,
+            normal: normal(),
+        }
+    }
+
+    #[test]
+    fn samples_match_density() {
+        random::reseed(8, 0);
+        let grazing = FreeVec3::new(1., 0.3, 0.2).into();
+        let resolution = Resolution {
+            samples: 100_000,
+            ..Resolution::FINE
+        };
+        // Opaque, metallic, transmissive and blended, glossy and rough, so that every
+        // lobe takes part.
+        let bsdfs = [
+            rough_bsdf(0., 0.3, 0.),
+            rough_bsdf(1., 0.5, 0.),
+            rough_bsdf(0., 0.5, 1.),
+            rough_bsdf(0.3, 0.5, 0.6),
+        ];
+        for bsdf in &bsdfs {
+            for wo in &[normal(), grazing] {
+                assert_samples_match_pdf(bsdf, wo, &normal(), &resolution);
+                for _ in 0..100 {
+                    if let Some(sample) = bsdf.sample(wo) {
+                        let value = bsdf.eval(wo, &sample.direction);
+                        let expected = *(sample.weight * sample.pdf).r();
+                        assert!((expected - value.r()).abs() <= 1e-9 * value.r());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace_does_not_gain_energy() {
+        // Reflected energy of a white, rough, opaque dielectric lit uniformly.
+        random::reseed(9, 0);
+        let mut white = bsdf(0., 0.);
+        white.base_color = Color::new(1., 1., 1.);
+        white.sheen = 0.;
+        white.clearcoat = 0.;
+        let wo = FreeVec3::new(0.5, 1., 0.).into();
+        let n = 100000;
+        let albedo = (0..n)
+            .filter_map(|_| white.sample(&wo))
+            .map(|s| *s.weight.g())
+            .sum::<f64>()
+            / n as f64;
+        assert!(albedo > 0.8 && albedo < 1.05, "albedo {}", albedo);
+    }
+
+    #[test]
+    fn metal_reflects_base_color() {
+        let metal = bsdf(1., 0.);
+        let wo = normal();
+        let wi = normal();
+        let value = metal.eval(&wo, &wi);
+        // Only the specular lobe remains, tinted by the base color.
+        assert!(value.r() > value.g() && value.g() > value.b());
+        assert_eq!(metal.lobe_probabilities()[DIFFUSE], 0.);
+    }
+}
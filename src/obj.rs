@@ -0,0 +1,560 @@
+//! Import of Wavefront OBJ meshes and their MTL material libraries.
+use crate::color::Color;
+use crate::material::{Interior, Material};
+use crate::objects::mesh::{Face, VertexIndex};
+use crate::objects::{Bvh, TriangleMesh};
+use crate::spaces::{FreeVec3, Point};
+use crate::texture::{ImageTexture, Texture};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        file: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Loads the OBJ file at `path` together with the material libraries it references
+/// and returns its triangles in a hierarchy that can be placed into the world.
+pub fn load(path: &Path) -> Result<Bvh, ObjError> {
+    let mesh = Arc::new(load_mesh(path)?);
+    Ok(Bvh::new(mesh.triangles()))
+}
+
+pub fn load_mesh(path: &Path) -> Result<TriangleMesh, ObjError> {
+    let file = File::open(path).map_err(|error| ObjError::Io {
+        file: path.to_owned(),
+        error,
+    })?;
+    parse_obj(BufReader::new(file), path)
+}
+
+/// Material of faces that do not select any material from a library.
+fn default_material() -> Material {
+    Material::Lambertian(Color::new(0.8, 0.8, 0.8).into())
+}
+
+/// Line-oriented reader that keeps track of the position for error reports.
+struct Lines<'a, R> {
+    reader: R,
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a, R: BufRead> Lines<'a, R> {
+    fn new(reader: R, file: &'a Path) -> Self {
+        Lines {
+            reader,
+            file,
+            line: 0,
+        }
+    }
+
+    /// Returns the next line with comments stripped, or `None` at the end of input.
+    fn next_line(&mut self) -> Result<Option<String>, ObjError> {
+        let mut buffer = String::new();
+        if self.read_line(&mut buffer)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        // Backslash at the end of a line continues the statement on the next one.
+        while buffer.trim_end().ends_with('\\') {
+            let end = buffer.trim_end().len() - 1;
+            buffer.truncate(end);
+            buffer.push(' ');
+            if self.read_line(&mut buffer)? == 0 {
+                break;
+            }
+            self.line += 1;
+        }
+        if let Some(comment) = buffer.find('#') {
+            buffer.truncate(comment);
+        }
+        Ok(Some(buffer))
+    }
+
+    fn read_line(&mut self, buffer: &mut String) -> Result<usize, ObjError> {
+        self.reader.read_line(buffer).map_err(|error| ObjError::Io {
+            file: self.file.to_owned(),
+            error,
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_owned(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn floats<'s>(
+        &self,
+        args: impl Iterator<Item = &'s str>,
+        statement: &str,
+    ) -> Result<Vec<f64>, ObjError> {
+        args.map(|arg| {
+            arg.parse::<f64>()
+                .map_err(|_| self.error(format!("invalid number `{}` in `{}`", arg, statement)))
+        })
+        .collect()
+    }
+
+    fn exact_floats<'s>(
+        &self,
+        args: impl Iterator<Item = &'s str>,
+        statement: &str,
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f64>, ObjError> {
+        let values = self.floats(args, statement)?;
+        if values.len() < min || values.len() > max {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{} to {}", min, max)
+            };
+            return Err(self.error(format!(
+                "`{}` expects {} numbers, found {}",
+                statement,
+                expected,
+                values.len()
+            )));
+        }
+        Ok(values)
+    }
+}
+
+fn parse_obj<R: BufRead>(reader: R, path: &Path) -> Result<TriangleMesh, ObjError> {
+    let mut lines = Lines::new(reader, path);
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+
+    let mut library: HashMap<String, Material> = HashMap::new();
+    let mut materials: Vec<Material> = Vec::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material: Option<usize> = None;
+
+    while let Some(line) = lines.next_line()? {
+        let mut args = line.split_whitespace();
+        let statement = match args.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        match statement {
+            "v" => {
+                // An optional fourth component is the homogeneous weight.
+                let v = lines.exact_floats(args, statement, 3, 4)?;
+                let w = v.get(3).copied().unwrap_or(1.);
+                let p = [v[0] / w, v[1] / w, v[2] / w];
+                if w == 0. || v.iter().chain(&p).any(|x| !x.is_finite()) {
+                    return Err(lines.error(format!(
+                        "vertex needs finite coordinates and a non-zero weight, found {:?}",
+                        v
+                    )));
+                }
+                positions.push(Point::new(p[0], p[1], p[2]));
+            }
+            "vn" => {
+                let n = lines.exact_floats(args, statement, 3, 3)?;
+                let n = FreeVec3::new(n[0], n[1], n[2]);
+                if n == FreeVec3::new(0., 0., 0.) {
+                    return Err(lines.error("zero-length normal"));
+                }
+                normals.push(n.into());
+            }
+            "vt" => {
+                let t = lines.exact_floats(args, statement, 1, 3)?;
+                uvs.push((t[0], t.get(1).copied().unwrap_or(0.)));
+            }
+            "f" => {
+                let corners = args
+                    .map(|arg| {
+                        parse_vertex(arg, positions.len(), uvs.len(), normals.len())
+                            .map_err(|message| lines.error(message))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(lines.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+                let material = *current_material.get_or_insert_with(|| {
+                    materials.push(default_material());
+                    materials.len() - 1
+                });
+                // Polygons are split into a fan of triangles around the first vertex.
+                for i in 1..corners.len() - 1 {
+                    faces.push(Face {
+                        vertices: [corners[0], corners[i], corners[i + 1]],
+                        material,
+                    });
+                }
+            }
+            "mtllib" => {
+                let names: Vec<&str> = args.collect();
+                if names.is_empty() {
+                    return Err(lines.error("`mtllib` expects a file name"));
+                }
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                for name in names {
+                    library.extend(load_mtl(&directory.join(name))?);
+                }
+            }
+            "usemtl" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| lines.error("`usemtl` expects a material name"))?;
+                let index = match material_indices.get(name) {
+                    Some(&index) => index,
+                    None => {
+                        let material = library
+                            .get(name)
+                            .ok_or_else(|| lines.error(format!("unknown material `{}`", name)))?;
+                        materials.push(material.clone());
+                        material_indices.insert(name.to_owned(), materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+                current_material = Some(index);
+            }
+            // Grouping, smoothing and the rest of the statements do not affect rendering.
+            _ => {}
+        }
+    }
+
+    Ok(TriangleMesh::new(positions, normals, uvs, faces, materials))
+}
+
+/// Parses one face corner in any of the `v`, `v/vt`, `v//vn` and `v/vt/vn` forms.
+fn parse_vertex(
+    arg: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> Result<VertexIndex, String> {
+    let mut parts = arg.split('/');
+    let position = parts
+        .next()
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| format!("missing vertex index in `{}`", arg))?;
+    let uv = parts.next().filter(|p| !p.is_empty());
+    let normal = parts.next().filter(|p| !p.is_empty());
+    if parts.next().is_some() {
+        return Err(format!("malformed face vertex `{}`", arg));
+    }
+
+    Ok(VertexIndex {
+        position: resolve_index(position, positions, "vertex")?,
+        uv: uv
+            .map(|uv| resolve_index(uv, uvs, "texture coordinate"))
+            .transpose()?,
+        normal: normal
+            .map(|normal| resolve_index(normal, normals, "normal"))
+            .transpose()?,
+    })
+}
+
+/// Converts a one-based or negative relative OBJ index into a buffer index.
+fn resolve_index(index: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid {} index `{}`", kind, index))?;
+    let resolved = if value > 0 {
+        value - 1
+    } else {
+        count as i64 + value
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} out of range, {} defined so far",
+            kind, value, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Parameters of one `newmtl` block; unspecified ones keep the MTL defaults.
+struct MtlEntry {
+    diffuse: Color,
+    diffuse_map: Option<Arc<dyn Texture + Send + Sync>>,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illumination: u32,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        MtlEntry {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            specular: Color::new(0., 0., 0.),
+            emission: Color::new(0., 0., 0.),
+            shininess: 0.,
+            refraction_index: 1.,
+            dissolve: 1.,
+            illumination: 2,
+        }
+    }
+}
+
+impl MtlEntry {
+    /// Maps the Phong-style description onto the closest material of the renderer.
+    fn into_material(self) -> Material {
+        let transparent = self.dissolve < 1. || [4, 6, 7, 9].contains(&self.illumination);
+        let max = |c: Color| c.r().max(*c.g()).max(*c.b());
+        let specular = max(self.specular);
+        if max(self.emission) > 0. {
+            Material::DiffuseLight(self.emission)
+        } else if transparent {
+            let refraction_index = if self.refraction_index > 1. {
+                self.refraction_index
+            } else {
+                1.5
+            };
+            Material::Dielectric(Interior::new(refraction_index))
+        } else if self.illumination == 3 || (self.illumination >= 5 && specular > 0.) {
+            // Roughness of a Phong lobe with the given exponent.
+            let fuzziness = (2. / (self.shininess + 2.)).sqrt().min(1.);
+            Material::Metal {
+                color: self.specular.into(),
+                fuzziness,
+            }
+        } else {
+            let diffuse = self.diffuse;
+            Material::Lambertian(self.diffuse_map.unwrap_or_else(|| diffuse.into()))
+        }
+    }
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let file = File::open(path).map_err(|error| ObjError::Io {
+        file: path.to_owned(),
+        error,
+    })?;
+    parse_mtl(BufReader::new(file), path)
+}
+
+fn parse_mtl<R: BufRead>(reader: R, path: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut lines = Lines::new(reader, path);
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    while let Some(line) = lines.next_line()? {
+        let mut args = line.split_whitespace();
+        let statement = match args.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        if statement == "newmtl" {
+            let name = args
+                .next()
+                .ok_or_else(|| lines.error("`newmtl` expects a material name"))?;
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, entry.into_material());
+            }
+            current = Some((name.to_owned(), MtlEntry::default()));
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some((_, entry)) => entry,
+            None if [
+                "Kd", "Ks", "Ka", "Ke", "Ns", "Ni", "d", "Tr", "illum", "map_Kd",
+            ]
+            .contains(&statement) =>
+            {
+                return Err(lines.error(format!("`{}` before any `newmtl`", statement)))
+            }
+            None => continue,
+        };
+        match statement {
+            "Kd" | "Ks" | "Ke" => {
+                let c = lines.exact_floats(args, statement, 3, 3)?;
+                let color = Color::new(c[0], c[1], c[2]);
+                match statement {
+                    "Kd" => entry.diffuse = color,
+                    "Ks" => entry.specular = color,
+                    _ => entry.emission = color,
+                }
+            }
+            "map_Kd" => {
+                // Options may precede the file name, which comes last.
+                let name = args
+                    .last()
+                    .ok_or_else(|| lines.error("`map_Kd` expects a file name"))?;
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                let file = directory.join(name);
+                let texture = ImageTexture::load(&file).map_err(|error| {
+                    lines.error(format!("cannot load texture {}: {}", file.display(), error))
+                })?;
+                entry.diffuse_map = Some(Arc::new(texture));
+            }
+            "Ns" => entry.shininess = lines.exact_floats(args, statement, 1, 1)?[0],
+            "Ni" => entry.refraction_index = lines.exact_floats(args, statement, 1, 1)?[0],
+            "d" => entry.dissolve = lines.exact_floats(args, statement, 1, 1)?[0],
+            "Tr" => entry.dissolve = 1. - lines.exact_floats(args, statement, 1, 1)?[0],
+            "illum" => {
+                let arg = args.next().unwrap_or("");
+                entry.illumination = arg
+                    .parse()
+                    .map_err(|_| lines.error(format!("invalid illumination model `{}`", arg)))?;
+            }
+            // Ambient color, texture maps and other statements have no counterpart.
+            _ => {}
+        }
+    }
+    if let Some((name, entry)) = current {
+        materials.insert(name, entry.into_material());
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<TriangleMesh, ObjError> {
+        parse_obj(source.as_bytes(), Path::new("test.obj"))
+    }
+
+    fn error_line(result: Result<TriangleMesh, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("malformed file was accepted"),
+        }
+    }
+
+    #[test]
+    fn triangulates_polygons() {
+        let mesh = parse(
+            "# a unit square
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            f 1/1/1 2/2/1 3/3/1 4/4/1
+            f -4//-1 -3//-1 -2//-1",
+        )
+        .unwrap();
+        assert_eq!(mesh.len(), 3);
+        assert_eq!(mesh.faces()[1].vertices[2].position, 3);
+        assert_eq!(mesh.faces()[1].vertices[2].uv, Some(3));
+        assert_eq!(mesh.faces()[2].vertices[0].position, 0);
+        assert_eq!(mesh.faces()[2].vertices[0].uv, None);
+        assert_eq!(mesh.faces()[2].vertices[0].normal, Some(0));
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0\n")), 2);
+        assert_eq!(error_line(parse("v 0 0 0\n\nv 1 x 0\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nf 1 2\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n")), 4);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 0\n")), 4);
+        assert_eq!(
+            error_line(parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1/1 2 3\n")),
+            4
+        );
+        assert_eq!(error_line(parse("usemtl missing\n")), 1);
+        assert_eq!(error_line(parse("v 0 0 \\\n0\nvn 0 0 0\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 2 3 0\n")), 2);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 nan 3\n")), 2);
+        assert_eq!(error_line(parse("v inf 0 0\n")), 1);
+        assert_eq!(error_line(parse("v 1e300 0 0 1e-300\n")), 1);
+    }
+
+    #[test]
+    fn maps_mtl_materials() {
+        let materials = parse_mtl(
+            "newmtl matte
+            Kd 0.1 0.2 0.3
+            newmtl mirror
+            illum 3
+            Ks 0.9 0.9 0.9
+            Ns 1000
+            newmtl glass
+            illum 7
+            Ni 1.33
+            newmtl lamp
+            Kd 0 0 0
+            Ke 10 10 8"
+                .as_bytes(),
+            Path::new("test.mtl"),
+        )
+        .unwrap();
+        let p = Point::new(0., 0., 0.);
+        assert!(matches!(
+            &materials["matte"],
+            Material::Lambertian(t) if *t.value(0., 0., &p).g() == 0.2
+        ));
+        assert!(matches!(
+            materials["mirror"],
+            Material::Metal { fuzziness, .. } if fuzziness < 0.1
+        ));
+        assert!(
+            matches!(materials["glass"], Material::Dielectric(i) if i.refraction_index == 1.33)
+        );
+        assert!(matches!(materials["lamp"], Material::DiffuseLight(c) if *c.b() == 8.));
+    }
+
+    #[test]
+    fn reports_malformed_mtl_lines() {
+        let result = parse_mtl("Kd 1 1 1\n".as_bytes(), Path::new("test.mtl"));
+        assert!(matches!(result, Err(ObjError::Parse { line: 1, .. })));
+        let result = parse_mtl("newmtl a\nNs\n".as_bytes(), Path::new("test.mtl"));
+        assert!(matches!(result, Err(ObjError::Parse { line: 2, .. })));
+        let result = parse_mtl(
+            "newmtl a\n\nmap_Kd missing.png\n".as_bytes(),
+            Path::new("test.mtl"),
+        );
+        assert!(matches!(result, Err(ObjError::Parse { line: 3, .. })));
+    }
+}
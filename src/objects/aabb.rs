@@ -0,0 +1,100 @@
+use crate::ray::Ray;
+use crate::spaces::{Point, Vec3};
+
+/// Axis-aligned bounding box given by its minimal and maximal corners.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn new(min: Point, max: Point) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Smallest box containing both of the given points, in any order.
+    pub fn from_points(a: Point, b: Point) -> Self {
+        Aabb {
+            min: Point::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Self {
+        Aabb {
+            min: Point::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Point::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Point {
+        self.min + (self.max - self.min) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2. * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    /// Index of the axis along which the box is the longest.
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test of the ray against the box, restricted to the `(t_min, t_max)` interval.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    /// Part of the `(t_min, t_max)` interval of the ray that lies inside the box.
+    pub fn clip(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for axis in 0..3 {
+            let inv_d = 1. / component(&ray.direction, axis);
+            let origin = point_component(&ray.origin, axis);
+            let mut t0 = (point_component(&self.min, axis) - origin) * inv_d;
+            let mut t1 = (point_component(&self.max, axis) - origin) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so that a NaN produced by `0 * inf` keeps the previous bound.
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+pub fn point_component(p: &Point, axis: usize) -> f64 {
+    match axis {
+        0 => p.x(),
+        1 => p.y(),
+        _ => p.z(),
+    }
+}
+
+pub fn component<T: Vec3>(v: &T, axis: usize) -> f64 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
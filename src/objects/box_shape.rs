@@ -0,0 +1,75 @@
+use super::{Aabb, HitRecord, Object, ObjectList, Quad};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point};
+use std::sync::Arc;
+
+/// Axis-aligned box made of six quads facing outwards.
+pub struct BoxShape {
+    sides: ObjectList,
+    bbox: Aabb,
+}
+
+impl BoxShape {
+    /// Box with the given opposite corners.
+    pub fn new(a: Point, b: Point, material: Material) -> Self {
+        let bbox = Aabb::from_points(a, b);
+        let (min, max) = (bbox.min, bbox.max);
+        let dx = FreeVec3::new(max.x() - min.x(), 0., 0.);
+        let dy = FreeVec3::new(0., max.y() - min.y(), 0.);
+        let dz = FreeVec3::new(0., 0., max.z() - min.z());
+
+        let mut sides = ObjectList {
+            objects: Vec::with_capacity(6),
+        };
+        let mut side = |q, u, v| sides.add(Arc::new(Quad::new(q, u, v, material.clone())));
+        side(Point::new(min.x(), min.y(), max.z()), dx, dy); // front
+        side(Point::new(max.x(), min.y(), max.z()), -dz, dy); // right
+        side(Point::new(max.x(), min.y(), min.z()), -dx, dy); // back
+        side(Point::new(min.x(), min.y(), min.z()), dz, dy); // left
+        side(Point::new(min.x(), max.y(), max.z()), dx, -dz); // top
+        side(Point::new(min.x(), min.y(), min.z()), dx, dz); // bottom
+
+        BoxShape { sides, bbox }
+    }
+}
+
+impl Object for BoxShape {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::spaces::Vec3;
+
+    #[test]
+    fn normals_face_outwards() {
+        let shape = BoxShape::new(
+            Point::new(1., 2., 3.),
+            Point::new(-1., -2., -3.),
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        );
+        let directions = [
+            FreeVec3::new(1., 0., 0.),
+            FreeVec3::new(0., 1., 0.),
+            FreeVec3::new(0., 0., 1.),
+        ];
+        for d in directions.iter().flat_map(|&d| vec![d, -d]) {
+            // Rays coming from outside towards the center.
+            let ray = Ray::new(&(Point::new(0., 0., 0.) + d * 10.), &(-d).into(), 0.);
+            let hit = shape.hit(&ray, 0., f64::INFINITY).unwrap();
+            assert!(hit.front_face);
+            assert!((hit.normal.dot(&d) - 1.).abs() < 1e-12);
+            let extent = (d.x() * 1. + d.y() * 2. + d.z() * 3.).abs();
+            assert!((hit.t - (10. - extent)).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use super::aabb::{component, point_component};
+use super::{Aabb, HitRecord, Object, ObjectList};
+use crate::ray::Ray;
+use std::sync::Arc;
+
+/// Number of centroid buckets evaluated by the surface area heuristic.
+const BUCKET_COUNT: usize = 12;
+/// Leaves with at most this many objects are kept if splitting does not pay off.
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting an interior node relative to intersecting one object.
+const TRAVERSAL_COST: f64 = 0.125;
+/// Depth of the traversal stack. Nodes this deep are made leaves, which keeps the stack
+/// from overflowing even for degenerate inputs that would otherwise build chains.
+const STACK_SIZE: usize = 64;
+
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    // The first child directly follows its parent in the node array.
+    Interior { second_child: usize, axis: usize },
+}
+
+struct Node {
+    bbox: Aabb,
+    kind: NodeKind,
+}
+
+type Primitive = (Arc<dyn Object + Send + Sync>, Aabb);
+
+/// Bounding volume hierarchy built with the surface area heuristic and flattened into
+/// a linear array of nodes.
+///
+/// Objects without a bounding box cannot be placed in the hierarchy, so they are kept
+/// aside and tested against every ray.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<Arc<dyn Object + Send + Sync>>,
+    unbounded: Vec<Arc<dyn Object + Send + Sync>>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Arc<dyn Object + Send + Sync>>) -> Self {
+        let mut primitives = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box() {
+                Some(bbox) => primitives.push((object, bbox)),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            objects: Vec::with_capacity(primitives.len()),
+            unbounded,
+        };
+        if !primitives.is_empty() {
+            bvh.build(&mut primitives, 0);
+        }
+        bvh
+    }
+
+    /// Recursively builds the subtree over `primitives`, whose root is `depth` nodes
+    /// below the root of the hierarchy, and returns the index of its root.
+    fn build(&mut self, primitives: &mut [Primitive], depth: usize) -> usize {
+        let index = self.nodes.len();
+        let bbox = primitives[1..]
+            .iter()
+            .fold(primitives[0].1, |acc, (_, bbox)| acc.surrounding(bbox));
+        self.nodes.push(Node {
+            bbox,
+            kind: NodeKind::Leaf { first: 0, count: 0 },
+        });
+
+        // Traversal keeps a node on the stack for every interior node above the current one.
+        let split = if depth < STACK_SIZE {
+            split(primitives, &bbox)
+        } else {
+            None
+        };
+        match split {
+            Some((axis, mid)) => {
+                let (left, right) = primitives.split_at_mut(mid);
+                self.build(left, depth + 1);
+                let second_child = self.build(right, depth + 1);
+                self.nodes[index].kind = NodeKind::Interior { second_child, axis };
+            }
+            None => {
+                let first = self.objects.len();
+                self.objects
+                    .extend(primitives.iter().map(|(object, _)| object.clone()));
+                self.nodes[index].kind = NodeKind::Leaf {
+                    first,
+                    count: primitives.len(),
+                };
+            }
+        }
+        index
+    }
+}
+
+impl From<ObjectList> for Bvh {
+    fn from(list: ObjectList) -> Self {
+        Bvh::new(list.objects)
+    }
+}
+
+/// Reorders `primitives` around the cheapest split found by the surface area heuristic
+/// and returns the split axis with the index of the first primitive in the second half,
+/// or `None` when the primitives should stay together in a leaf.
+fn split(primitives: &mut [Primitive], bbox: &Aabb) -> Option<(usize, usize)> {
+    let count = primitives.len();
+    if count == 1 {
+        return None;
+    }
+
+    let centroid_bounds = primitives[1..].iter().fold(
+        Aabb::new(primitives[0].1.centroid(), primitives[0].1.centroid()),
+        |acc, (_, bbox)| acc.surrounding(&Aabb::new(bbox.centroid(), bbox.centroid())),
+    );
+    let axis = centroid_bounds.longest_axis();
+    let low = point_component(&centroid_bounds.min, axis);
+    let extent = point_component(&centroid_bounds.max, axis) - low;
+
+    if extent <= 0. {
+        // All centroids coincide, no split can separate them.
+        return if count <= MAX_LEAF_SIZE {
+            None
+        } else {
+            Some((axis, count / 2))
+        };
+    }
+
+    let bucket_of = |bbox: &Aabb| {
+        let offset = (point_component(&bbox.centroid(), axis) - low) / extent;
+        ((offset * BUCKET_COUNT as f64) as usize).min(BUCKET_COUNT - 1)
+    };
+
+    let mut buckets: [(usize, Option<Aabb>); BUCKET_COUNT] = [(0, None); BUCKET_COUNT];
+    for (_, bbox) in primitives.iter() {
+        let bucket = &mut buckets[bucket_of(bbox)];
+        bucket.0 += 1;
+        bucket.1 = Some(bucket.1.map_or(*bbox, |b| b.surrounding(bbox)));
+    }
+
+    // Sweep from the right to get the cost of every suffix, then from the left.
+    let mut right_area = [0.; BUCKET_COUNT];
+    let mut right_count = [0; BUCKET_COUNT];
+    let mut acc: Option<Aabb> = None;
+    let mut acc_count = 0;
+    for i in (1..BUCKET_COUNT).rev() {
+        acc_count += buckets[i].0;
+        if let Some(b) = buckets[i].1 {
+            acc = Some(acc.map_or(b, |a| a.surrounding(&b)));
+        }
+        right_count[i] = acc_count;
+        right_area[i] = acc.map_or(0., |a| a.surface_area());
+    }
+
+    let total_area = bbox.surface_area();
+    let mut best: Option<(usize, f64)> = None;
+    let mut acc: Option<Aabb> = None;
+    let mut acc_count = 0;
+    for i in 0..BUCKET_COUNT - 1 {
+        acc_count += buckets[i].0;
+        if let Some(b) = buckets[i].1 {
+            acc = Some(acc.map_or(b, |a| a.surrounding(&b)));
+        }
+        if acc_count == 0 || right_count[i + 1] == 0 {
+            continue;
+        }
+        let left_area = acc.map_or(0., |a| a.surface_area());
+        let cost = TRAVERSAL_COST
+            + if total_area > 0. {
+                (acc_count as f64 * left_area + right_count[i + 1] as f64 * right_area[i + 1])
+                    / total_area
+            } else {
+                count as f64 / 2.
+            };
+        if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+            best = Some((i, cost));
+        }
+    }
+
+    match best {
+        Some((_, cost)) if count <= MAX_LEAF_SIZE && cost >= count as f64 => None,
+        Some((bucket, _)) => {
+            let mut mid = 0;
+            for i in 0..count {
+                if bucket_of(&primitives[i].1) <= bucket {
+                    primitives.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            Some((axis, mid))
+        }
+        None if count <= MAX_LEAF_SIZE => None,
+        None => Some((axis, count / 2)),
+    }
+}
+
+impl Object for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest_so_far = t_max;
+        let mut closest_hit = None;
+
+        for object in &self.unbounded {
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                closest_hit = Some(hit);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return closest_hit;
+        }
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit(ray, t_min, closest_so_far) {
+                match node.kind {
+                    NodeKind::Leaf { first, count } => {
+                        for object in &self.objects[first..first + count] {
+                            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                                closest_so_far = hit.t;
+                                closest_hit = Some(hit);
+                            }
+                        }
+                    }
+                    NodeKind::Interior { second_child, axis } => {
+                        // Visit the child nearer to the ray origin first.
+                        if component(&ray.direction, axis) < 0. {
+                            stack[stack_len] = current + 1;
+                            current = second_child;
+                        } else {
+                            stack[stack_len] = second_child;
+                            current += 1;
+                        }
+                        stack_len += 1;
+                        continue;
+                    }
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.nodes.first().map(|node| node.bbox)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::objects::Sphere;
+    use crate::random;
+    use crate::spaces::{FreeVec3, Point};
+
+    fn random_spheres(count: usize) -> Vec<Arc<dyn Object + Send + Sync>> {
+        (0..count)
+            .map(|_| {
+                Arc::new(Sphere::new(
+                    Point::new(
+                        random::gen_range(-10., 10.),
+                        random::gen_range(-10., 10.),
+                        random::gen_range(-10., 10.),
+                    ),
+                    random::gen_range(0.05, 1.),
+                    Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+                )) as Arc<dyn Object + Send + Sync>
+            })
+            .collect()
+    }
+
+    fn assert_same_hits(objects: Vec<Arc<dyn Object + Send + Sync>>) {
+        let list = ObjectList {
+            objects: objects.clone(),
+        };
+        let bvh = Bvh::new(objects);
+        for _ in 0..2000 {
+            let ray = Ray::new(
+                &Point::new(
+                    random::gen_range(-15., 15.),
+                    random::gen_range(-15., 15.),
+                    random::gen_range(-15., 15.),
+                ),
+                &FreeVec3::new(
+                    random::gen_range(-1., 1.),
+                    random::gen_range(-1., 1.),
+                    random::gen_range(-1., 1.),
+                )
+                .into(),
+                0.,
+            );
+            let expected = list.hit(&ray, 0.001, f64::INFINITY);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(e), Some(a)) => {
+                    assert_eq!(e.t, a.t);
+                    assert_eq!(e.p, a.p);
+                }
+                (e, a) => panic!(
+                    "list hit at {:?}, BVH hit at {:?}",
+                    e.map(|h| h.t),
+                    a.map(|h| h.t)
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn matches_object_list() {
+        random::reseed(1, 0);
+        assert_same_hits(random_spheres(500));
+    }
+
+    #[test]
+    fn matches_object_list_with_coincident_objects() {
+        random::reseed(2, 0);
+        let sphere = random_spheres(1).pop().unwrap();
+        let mut objects = vec![sphere; 20];
+        objects.extend(random_spheres(3));
+        assert_same_hits(objects);
+    }
+
+    #[test]
+    fn matches_object_list_when_degenerate() {
+        random::reseed(3, 0);
+        // Every split peels off the farthest sphere, which would make a chain of nodes.
+        let objects = (0..600)
+            .map(|i| {
+                Arc::new(Sphere::new(
+                    Point::new(1.5f64.powi(i), 0., 0.),
+                    1.,
+                    Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+                )) as Arc<dyn Object + Send + Sync>
+            })
+            .collect();
+        assert_same_hits(objects);
+    }
+
+    #[test]
+    fn empty_hierarchy() {
+        let bvh = Bvh::new(Vec::new());
+        let ray = Ray::new(
+            &Point::new(0., 0., 0.),
+            &FreeVec3::new(1., 0., 0.).into(),
+            0.,
+        );
+        assert!(bvh.hit(&ray, 0., f64::INFINITY).is_none());
+        assert!(bvh.bounding_box().is_none());
+    }
+
+    #[test]
+    fn bounding_box_encloses_objects() {
+        random::reseed(4, 0);
+        let objects = random_spheres(50);
+        let expected = ObjectList {
+            objects: objects.clone(),
+        }
+        .bounding_box();
+        assert_eq!(Bvh::new(objects).bounding_box(), expected);
+    }
+}
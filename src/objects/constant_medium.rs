@@ -0,0 +1,129 @@
+use super::{Aabb, HitRecord, Object};
+use crate::material::Material;
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::FreeVec3;
+use std::sync::Arc;
+
+/// Homogeneous participating medium such as fog or smoke, filling a closed boundary
+/// object or, without a boundary, all of space.
+///
+/// Rays travelling through the medium scatter after a random free-flight distance that
+/// is exponentially distributed according to the density, and the hit then uses the
+/// phase function of the medium as its material.
+pub struct ConstantMedium {
+    boundary: Option<Arc<dyn Object + Send + Sync>>,
+    neg_inv_density: f64,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Object + Send + Sync>,
+        density: f64,
+        phase_function: Material,
+    ) -> Self {
+        ConstantMedium {
+            boundary: Some(boundary),
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+
+    /// Medium without boundary, surrounding the camera and every object of the scene.
+    pub fn everywhere(density: f64, phase_function: Material) -> Self {
+        ConstantMedium {
+            boundary: None,
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+}
+
+impl Object for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Part of the ray inside the medium.
+        let (enter, exit) = match &self.boundary {
+            Some(boundary) => {
+                let enter = boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+                let exit = boundary.hit(ray, enter.t + 0.0001, f64::INFINITY)?;
+                (enter.t.max(t_min), exit.t.min(t_max))
+            }
+            None => (t_min, t_max),
+        };
+        if enter >= exit {
+            return None;
+        }
+        let enter = enter.max(0.);
+
+        // Ray directions are unit vectors, so `t` measures distances.
+        let distance = self.neg_inv_density * (1. - random::random::<f64>()).ln();
+        if distance > exit - enter {
+            return None;
+        }
+        let t = enter + distance;
+        let mut hit = HitRecord::new(
+            ray,
+            ray.at(t),
+            FreeVec3::new(1., 0., 0.).into(),
+            t,
+            self.phase_function.clone(),
+        );
+        // Media have no surface, the normal and side are arbitrary.
+        hit.front_face = true;
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.as_ref().and_then(|b| b.bounding_box())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::objects::Sphere;
+    use crate::spaces::{Point, UnitVec3};
+
+    fn isotropic() -> Material {
+        Material::Isotropic(Color::new(0.5, 0.5, 0.5).into())
+    }
+
+    #[test]
+    fn transmittance_matches_beer_lambert() {
+        random::reseed(5, 0);
+        let sphere = Arc::new(Sphere::new(Point::new(0., 0., 0.), 1., isotropic()));
+        let medium = ConstantMedium::new(sphere, 0.7, isotropic());
+        let direction = UnitVec3::from(FreeVec3::new(0., 0., -1.));
+
+        // Rays through the center travel 2 units through the medium when coming from
+        // outside and 1 unit when starting at the center.
+        let n = 20000;
+        for &(z, length) in [(5., 2f64), (0., 1.)].iter() {
+            let ray = Ray::new(&Point::new(0., 0., z), &direction, 0.);
+            let passed = (0..n)
+                .filter(|_| medium.hit(&ray, 0.001, f64::INFINITY).is_none())
+                .count();
+            let expected = (-0.7 * length).exp();
+            assert!((passed as f64 / n as f64 - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn atmosphere_scatters_before_distant_surfaces() {
+        random::reseed(6, 0);
+        let medium = ConstantMedium::everywhere(0.1, isotropic());
+        let ray = Ray::new(
+            &Point::new(0., 0., 0.),
+            &FreeVec3::new(0., 1., 0.).into(),
+            0.,
+        );
+        let n = 20000;
+        let scattered = (0..n)
+            .filter(|_| medium.hit(&ray, 0.001, 10.).is_some())
+            .count();
+        assert!((scattered as f64 / n as f64 - (1. - (-1f64).exp())).abs() < 0.01);
+        assert!(medium.bounding_box().is_none());
+    }
+}
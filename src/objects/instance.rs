@@ -0,0 +1,249 @@
+use super::{Aabb, HitRecord, Object};
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Placement, Point, Transform, Vec3};
+use std::sync::Arc;
+
+/// Shared object placed in the world by an affine transformation.
+///
+/// Rays are moved into the space of the object instead of transforming the object, so
+/// any number of instances can refer to the same geometry.
+pub struct Instance {
+    object: Arc<dyn Object + Send + Sync>,
+    to_world: Transform,
+    to_object: Transform,
+    /// Replaces the fixed transformations for moving instances.
+    motion: Option<Motion>,
+    bbox: Option<Aabb>,
+}
+
+/// Placement interpolated between `start` at `time0` and `end` at `time1`, and kept
+/// outside of that interval.
+struct Motion {
+    start: Placement,
+    end: Placement,
+    time0: f64,
+    time1: f64,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Object + Send + Sync>, transform: Transform) -> Self {
+        let bbox = object
+            .bounding_box()
+            .map(|b| transformed_box(&b, &transform));
+        Instance {
+            object,
+            to_world: transform,
+            to_object: transform.inverse(),
+            motion: None,
+            bbox,
+        }
+    }
+
+    /// Instance moving from `start` at `time0` to `end` at `time1`, `None` if one of the
+    /// placements is degenerate or a scale factor changes its sign during the movement.
+    pub fn moving(
+        object: Arc<dyn Object + Send + Sync>,
+        start: Placement,
+        end: Placement,
+        time0: f64,
+        time1: f64,
+    ) -> Option<Self> {
+        let (first, last) = (start.transform()?, end.transform()?);
+        let (s, e) = (start.scale, end.scale);
+        if s.x() * e.x() < 0. || s.y() * e.y() < 0. || s.z() * e.z() < 0. {
+            return None;
+        }
+
+        let bbox = object.bounding_box().map(|b| {
+            if start.rotation == end.rotation {
+                // Corners move linearly when only scaling and translation change.
+                transformed_box(&b, &first).surrounding(&transformed_box(&b, &last))
+            } else {
+                // Otherwise bound the corners by their largest distance from the origin
+                // of the object, which rotations keep.
+                let largest_scale = [s.x(), s.y(), s.z(), e.x(), e.y(), e.z()]
+                    .iter()
+                    .fold(0., |m: f64, x| m.max(x.abs()));
+                let radius = largest_scale * corners(&b).map(|p| p.length()).fold(0., f64::max);
+                let r = FreeVec3::new(radius, radius, radius);
+                let origin = Point::new(0., 0., 0.);
+                let path = Aabb::from_points(origin + start.translation, origin + end.translation);
+                Aabb::new(path.min - r, path.max + r)
+            }
+        });
+        Some(Instance {
+            object,
+            to_world: first,
+            to_object: first.inverse(),
+            motion: Some(Motion {
+                start,
+                end,
+                time0,
+                time1,
+            }),
+            bbox,
+        })
+    }
+}
+
+/// Corners of the box as vectors from the origin.
+fn corners(b: &Aabb) -> impl Iterator<Item = FreeVec3> + '_ {
+    (0..8).map(move |i| {
+        FreeVec3::new(
+            if i & 1 == 0 { b.min.x() } else { b.max.x() },
+            if i & 2 == 0 { b.min.y() } else { b.max.y() },
+            if i & 4 == 0 { b.min.z() } else { b.max.z() },
+        )
+    })
+}
+
+fn transformed_box(b: &Aabb, transform: &Transform) -> Aabb {
+    corners(b)
+        .map(|c| transform.point(&(Point::new(0., 0., 0.) + c)))
+        .map(|p| Aabb::new(p, p))
+        .reduce(|a, b| a.surrounding(&b))
+        .unwrap()
+}
+
+impl Object for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (to_world, to_object) = match &self.motion {
+            None => (self.to_world, self.to_object),
+            Some(motion) => {
+                let fraction = if motion.time1 == motion.time0 {
+                    0.
+                } else {
+                    ((ray.time - motion.time0) / (motion.time1 - motion.time0)).clamp(0., 1.)
+                };
+                let to_world = motion.start.lerp(&motion.end, fraction).transform()?;
+                (to_world, to_world.inverse())
+            }
+        };
+
+        // Ray directions are unit vectors, so distances along the ray change by the
+        // length of the transformed direction.
+        let direction = to_object.vector(&ray.direction);
+        let scale = direction.length();
+        let local = Ray::new(&to_object.point(&ray.origin), &direction.into(), ray.time);
+
+        let mut hit = self.object.hit(&local, t_min * scale, t_max * scale)?;
+        hit.t /= scale;
+        hit.p = to_world.point(&hit.p);
+        // The inverse transpose keeps the normal on the side it was on, so the face
+        // orientation of the hit stays valid.
+        hit.normal = to_world.normal(&hit.normal);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Interior, Material};
+    use crate::objects::Sphere;
+    use crate::spaces::UnitVec3;
+
+    #[test]
+    fn hits_transformed_sphere() {
+        let sphere = Arc::new(Sphere::new(
+            Point::new(0., 0., 0.),
+            1.,
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        ));
+        let transform = Transform::translation(FreeVec3::new(0., 0., -10.))
+            * Transform::scaling(1., 1., 2.).unwrap();
+        let instance = Instance::new(sphere, transform);
+
+        let bbox = instance.bounding_box().unwrap();
+        assert!((bbox.min - Point::new(-1., -1., -12.)).length() < 1e-12);
+        assert!((bbox.max - Point::new(1., 1., -8.)).length() < 1e-12);
+
+        let ray = Ray::new(
+            &Point::new(0., 0., 0.),
+            &UnitVec3::from(FreeVec3::new(0., 0., -1.)),
+            0.,
+        );
+        let hit = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 8.).abs() < 1e-9);
+        assert!((hit.p - Point::new(0., 0., -8.)).length() < 1e-9);
+        assert!((FreeVec3::from(hit.normal) - FreeVec3::new(0., 0., 1.)).length() < 1e-9);
+        assert!(hit.front_face);
+        assert!(instance.hit(&ray, 0.001, 7.).is_none());
+
+        // Passing the stretched part, which the untransformed sphere does not reach.
+        let ray = Ray::new(
+            &Point::new(0., -5., -11.8),
+            &UnitVec3::from(FreeVec3::new(0., 1., 0.)),
+            0.,
+        );
+        assert!(instance.hit(&ray, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn moving_instance_stays_in_bounding_box() {
+        let cube = Arc::new(crate::objects::BoxShape::new(
+            Point::new(-1., -1., -1.),
+            Point::new(1., 2., 1.),
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        ));
+        let start = Placement::default();
+        let end = Placement {
+            scale: FreeVec3::new(2., 1., 1.),
+            rotation: FreeVec3::new(0., 90., 0.),
+            translation: FreeVec3::new(5., 0., 0.),
+        };
+        let instance = Instance::moving(cube, start, end, 0., 1.).unwrap();
+        let bbox = instance.bounding_box().unwrap();
+
+        // Rays from above hit the top face at its interpolated height.
+        for i in 0..=10 {
+            let time = i as f64 / 10.;
+            let x = 5. * time;
+            let ray = Ray::new(
+                &Point::new(x, 10., 0.),
+                &FreeVec3::new(0., -1., 0.).into(),
+                time,
+            );
+            let hit = instance.hit(&ray, 0., f64::INFINITY).unwrap();
+            assert!((hit.p.y() - 2.).abs() < 1e-9);
+            let p = hit.p;
+            assert!(p.x() >= bbox.min.x() && p.x() <= bbox.max.x());
+            assert!(p.z() >= bbox.min.z() && p.z() <= bbox.max.z());
+        }
+        // Past the end of the movement the cube stays where it ended.
+        let ray = Ray::new(
+            &Point::new(5., 10., 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            1.5,
+        );
+        let hit = instance.hit(&ray, 0., f64::INFINITY).unwrap();
+        assert!((hit.p - Point::new(5., 2., 0.)).length() < 1e-9);
+        // Instead of reaching 7.5, as extrapolating the movement would.
+        let beyond = Ray::new(
+            &Point::new(7.5, 10., 0.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            1.5,
+        );
+        assert!(instance.hit(&beyond, 0., f64::INFINITY).is_none());
+        assert!(Instance::moving(
+            Arc::new(Sphere::new(
+                Point::new(0., 0., 0.),
+                1.,
+                Material::Dielectric(Interior::new(1.5))
+            )),
+            start,
+            Placement {
+                scale: FreeVec3::new(-1., 1., 1.),
+                ..start
+            },
+            0.,
+            1.,
+        )
+        .is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use super::{HitRecord, Object};
+use super::{Aabb, HitRecord, Object};
 use crate::ray::Ray;
 use std::sync::Arc;
 
@@ -26,4 +26,12 @@ impl Object for ObjectList {
 
         closest_hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut objects = self.objects.iter();
+        let first = objects.next()?.bounding_box()?;
+        objects.try_fold(first, |acc, object| {
+            Some(acc.surrounding(&object.bounding_box()?))
+        })
+    }
 }
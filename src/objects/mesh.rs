@@ -0,0 +1,93 @@
+use super::{Object, Triangle};
+use crate::material::Material;
+use crate::spaces::{Point, UnitVec3};
+use std::sync::Arc;
+
+/// Indices of one face corner into the buffers of its mesh.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VertexIndex {
+    pub position: usize,
+    pub normal: Option<usize>,
+    pub uv: Option<usize>,
+}
+
+impl VertexIndex {
+    pub fn new(position: usize) -> Self {
+        VertexIndex {
+            position,
+            normal: None,
+            uv: None,
+        }
+    }
+}
+
+/// Triangle given by its corners in counter-clockwise order and an index into the
+/// materials of its mesh.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Face {
+    pub vertices: [VertexIndex; 3],
+    pub material: usize,
+}
+
+/// Triangles sharing buffers of vertex positions, normals and texture coordinates.
+pub struct TriangleMesh {
+    pub(super) positions: Vec<Point>,
+    pub(super) normals: Vec<UnitVec3>,
+    pub(super) uvs: Vec<(f64, f64)>,
+    pub(super) faces: Vec<Face>,
+    pub(super) materials: Vec<Material>,
+}
+
+impl TriangleMesh {
+    /// # Panics
+    ///
+    /// Panics if a face refers to a vertex attribute or a material that does not exist.
+    pub fn new(
+        positions: Vec<Point>,
+        normals: Vec<UnitVec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<Face>,
+        materials: Vec<Material>,
+    ) -> Self {
+        for face in &faces {
+            assert!(
+                face.material < materials.len(),
+                "face material out of range"
+            );
+            for vertex in &face.vertices {
+                assert!(vertex.position < positions.len(), "vertex out of range");
+                assert!(
+                    vertex.normal.is_none_or(|n| n < normals.len()),
+                    "normal out of range"
+                );
+                assert!(vertex.uv.is_none_or(|uv| uv < uvs.len()), "UV out of range");
+            }
+        }
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            faces,
+            materials,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
+
+    /// Every face of the mesh as a separate object, ready to be put into a `Bvh`.
+    pub fn triangles(self: &Arc<Self>) -> Vec<Arc<dyn Object + Send + Sync>> {
+        (0..self.faces.len())
+            .map(|face| Arc::new(Triangle::from_mesh(self.clone(), face)) as _)
+            .collect()
+    }
+}
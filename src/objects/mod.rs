@@ -1,8 +1,32 @@
+pub mod aabb;
+pub mod box_shape;
+pub mod bvh;
+pub mod constant_medium;
+pub mod instance;
 pub mod list;
+pub mod mesh;
+pub mod moving_sphere;
 pub mod object;
+pub mod plane;
+pub mod quad;
 pub mod sphere;
+pub mod tagged;
+pub mod triangle;
+pub mod voxel_volume;
 
+pub use aabb::Aabb;
+pub use box_shape::BoxShape;
+pub use bvh::Bvh;
+pub use constant_medium::ConstantMedium;
+pub use instance::Instance;
 pub use list::ObjectList;
+pub use mesh::TriangleMesh;
+pub use moving_sphere::MovingSphere;
 pub use object::HitRecord;
 pub use object::Object;
+pub use plane::Plane;
+pub use quad::Quad;
 pub use sphere::Sphere;
+pub use tagged::Tagged;
+pub use triangle::Triangle;
+pub use voxel_volume::VoxelVolume;
@@ -0,0 +1,106 @@
+use super::sphere::hit_sphere;
+use super::{Aabb, HitRecord, Object};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point};
+
+/// Sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`,
+/// resting at either end outside of that interval.
+pub struct MovingSphere {
+    center0: Point,
+    center1: Point,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Material,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point,
+        center1: Point,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Material,
+    ) -> Self {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let fraction = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        self.center0 + (self.center1 - self.center0) * fraction
+    }
+}
+
+impl Object for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let center = self.center(ray.time);
+        hit_sphere(center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    /// Box enclosing the sphere during the movement from `time0` to `time1`.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = FreeVec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::from_points(self.center0 - r, self.center0 + r);
+        let end = Aabb::from_points(self.center1 - r, self.center1 + r);
+        Some(start.surrounding(&end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::spaces::Vec3;
+
+    #[test]
+    fn moves_with_ray_time() {
+        let sphere = MovingSphere::new(
+            Point::new(0., 0., 0.),
+            Point::new(0., 4., 0.),
+            0.,
+            1.,
+            1.,
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        );
+        let ray = |time| {
+            Ray::new(
+                &Point::new(0., 2., 5.),
+                &FreeVec3::new(0., 0., -1.).into(),
+                time,
+            )
+        };
+        assert!(sphere.hit(&ray(0.), 0., f64::INFINITY).is_none());
+        let hit = sphere.hit(&ray(0.5), 0., f64::INFINITY).unwrap();
+        assert!((hit.p - Point::new(0., 2., 1.)).length() < 1e-12);
+        assert!(sphere.hit(&ray(1.), 0., f64::INFINITY).is_none());
+        // Outside the movement the sphere rests where it starts or ends, within its box.
+        let above = |time| {
+            Ray::new(
+                &Point::new(0., 4., 5.),
+                &FreeVec3::new(0., 0., -1.).into(),
+                time,
+            )
+        };
+        let hit = sphere.hit(&above(3.), 0., f64::INFINITY).unwrap();
+        assert!((hit.p - Point::new(0., 4., 1.)).length() < 1e-12);
+        assert!(sphere.hit(&above(-1.), 0., f64::INFINITY).is_none());
+        assert!(sphere.hit(&ray(-1.), 0., f64::INFINITY).is_none());
+        let bbox = sphere.bounding_box().unwrap();
+        assert_eq!(
+            bbox,
+            Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 5., 1.))
+        );
+    }
+}
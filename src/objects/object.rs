@@ -1,3 +1,4 @@
+use super::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::spaces::{Point, UnitVec3, Vec3};
@@ -8,6 +9,12 @@ pub struct HitRecord {
     pub t: f64,
     pub front_face: bool,
     pub material: Material,
+    /// Surface coordinates of the hit point, zero for objects without a parametrisation.
+    pub u: f64,
+    pub v: f64,
+    /// Identifiers of the object and its material, zero unless set by `Tagged` objects.
+    pub object_id: u32,
+    pub material_id: u32,
 }
 
 impl HitRecord {
@@ -24,10 +31,27 @@ impl HitRecord {
             t,
             front_face,
             material,
+            u: 0.,
+            v: 0.,
+            object_id: 0,
+            material_id: 0,
         }
     }
+
+    /// Replaces the normal used for shading while keeping the side determined from the
+    /// geometric normal.
+    pub fn set_shading_normal(&mut self, outward_normal: UnitVec3) {
+        self.normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+    }
 }
 
 pub trait Object {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Box enclosing the whole object, or `None` for objects without finite extent.
+    fn bounding_box(&self) -> Option<Aabb>;
 }
@@ -0,0 +1,92 @@
+use super::{Aabb, HitRecord, Object};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+
+/// Infinite plane through `point`, facing in the direction of `normal`.
+///
+/// The surface coordinates are distances from `point` along two perpendicular
+/// directions within the plane, so they are not limited to `[0, 1]`.
+pub struct Plane {
+    point: Point,
+    normal: UnitVec3,
+    tangent: UnitVec3,
+    bitangent: UnitVec3,
+    material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Point, normal: UnitVec3, material: Material) -> Self {
+        // Any axis that is not close to the normal gives a stable tangent.
+        let axis = if normal.x().abs() > 0.9 {
+            FreeVec3::new(0., 1., 0.)
+        } else {
+            FreeVec3::new(1., 0., 0.)
+        };
+        let tangent = UnitVec3::from(axis.cross(&normal));
+        let bitangent = UnitVec3::from(normal.cross(&tangent));
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+impl Object for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denominator = self.normal.dot(&ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.point - ray.origin)) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = ray.at(t);
+        let mut hit = HitRecord::new(ray, p, self.normal, t, self.material.clone());
+        hit.u = self.tangent.dot(&(p - self.point));
+        hit.v = self.bitangent.dot(&(p - self.point));
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn hits_from_both_sides() {
+        let plane = Plane::new(
+            Point::new(0., 1., 0.),
+            FreeVec3::new(0., 1., 0.).into(),
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        );
+        let ray = Ray::new(
+            &Point::new(3., 5., -7.),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        let hit = plane.hit(&ray, 0., f64::INFINITY).unwrap();
+        assert!(hit.front_face && (hit.t - 4.).abs() < 1e-12);
+        assert!((hit.u.abs() - 7.).abs() < 1e-12 && (hit.v.abs() - 3.).abs() < 1e-12);
+
+        let ray = Ray::new(
+            &Point::new(0., -1., 0.),
+            &FreeVec3::new(1., 1., 0.).into(),
+            0.,
+        );
+        let hit = plane.hit(&ray, 0., f64::INFINITY).unwrap();
+        assert!(!hit.front_face && hit.normal.y() < 0.);
+        assert!(plane.bounding_box().is_none());
+    }
+}
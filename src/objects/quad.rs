@@ -0,0 +1,114 @@
+use super::{Aabb, HitRecord, Object};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+
+/// Parallelogram spanned by the edges `u` and `v` from the corner `q`.
+///
+/// The outward normal is `u × v` and the surface coordinates run along the edges.
+pub struct Quad {
+    q: Point,
+    u: FreeVec3,
+    v: FreeVec3,
+    material: Material,
+    normal: UnitVec3,
+    // Scaled normal mapping points of the plane to their coordinates along the edges.
+    w: FreeVec3,
+}
+
+impl Quad {
+    pub fn new(q: Point, u: FreeVec3, v: FreeVec3, material: Material) -> Self {
+        let n = u.cross(&v);
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal: n.into(),
+            w: n / n.length_squared(),
+        }
+    }
+
+    pub fn corner(&self) -> Point {
+        self.q
+    }
+
+    pub fn edges(&self) -> (FreeVec3, FreeVec3) {
+        (self.u, self.v)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(&self.v).length()
+    }
+}
+
+impl Object for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denominator = self.normal.dot(&ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.q - ray.origin)) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+
+        let mut hit = HitRecord::new(ray, p, self.normal, t, self.material.clone());
+        hit.u = alpha;
+        hit.v = beta;
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let diagonal = Aabb::from_points(self.q, self.q + self.u + self.v);
+        let other = Aabb::from_points(self.q + self.u, self.q + self.v);
+        Some(diagonal.surrounding(&other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn hits_inside_with_edge_coordinates() {
+        let quad = Quad::new(
+            Point::new(0., 0., 0.),
+            FreeVec3::new(2., 0., 0.),
+            FreeVec3::new(0., 0., -1.),
+            Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+        );
+        let ray = Ray::new(
+            &Point::new(0.5, 1., -0.25),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        let hit = quad.hit(&ray, 0., f64::INFINITY).unwrap();
+        assert!(hit.front_face);
+        assert!((hit.normal.y() - 1.).abs() < 1e-12);
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.25).abs() < 1e-12);
+
+        let ray = Ray::new(
+            &Point::new(2.5, 1., -0.25),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        );
+        assert!(quad.hit(&ray, 0., f64::INFINITY).is_none());
+        let ray = Ray::new(
+            &Point::new(0.5, 1., -0.25),
+            &FreeVec3::new(1., 0., 0.).into(),
+            0.,
+        );
+        assert!(quad.hit(&ray, 0., f64::INFINITY).is_none());
+    }
+}
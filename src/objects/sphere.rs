@@ -1,7 +1,8 @@
-use super::{HitRecord, Object};
+use super::{Aabb, HitRecord, Object};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::spaces::{Point, Vec3};
+use crate::spaces::{FreeVec3, Point, Vec3};
+use std::f64::consts::PI;
 
 pub struct Sphere {
     center: Point,
@@ -19,30 +20,72 @@ impl Sphere {
     }
 }
 
-impl Object for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius.powi(2);
-        let discriminant = half_b.powi(2) - a * c;
-
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
-            for &t in [(-half_b - root) / a, (-half_b + root) / a].iter() {
-                if t < t_max && t > t_min {
-                    let p = ray.at(t);
-                    return Some(HitRecord::new(
-                        ray,
-                        p,
-                        ((p - self.center) / self.radius).into(),
-                        t,
-                        self.material.clone(),
-                    ));
-                }
+/// Surface coordinates of a point on the unit sphere: `u` is the angle around the y axis
+/// starting at -x and `v` the angle from -y, both scaled to `[0, 1]`.
+fn sphere_uv(p: &FreeVec3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1., 1.).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2. * PI), theta / PI)
+}
+
+/// Intersection of the ray with the sphere of the given center and radius.
+pub(super) fn hit_sphere(
+    center: Point,
+    radius: f64,
+    material: &Material,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    let oc = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.length_squared() - radius.powi(2);
+    let discriminant = half_b.powi(2) - a * c;
+
+    if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        for &t in [(-half_b - root) / a, (-half_b + root) / a].iter() {
+            if t < t_max && t > t_min {
+                let p = ray.at(t);
+                let outward_normal = (p - center) / radius;
+                let mut hit = HitRecord::new(ray, p, outward_normal.into(), t, material.clone());
+                let (u, v) = sphere_uv(&outward_normal);
+                hit.u = u;
+                hit.v = v;
+                return Some(hit);
             }
         }
+    }
+
+    None
+}
+
+impl Object for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = FreeVec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::from_points(self.center - r, self.center + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        None
+    #[test]
+    fn spherical_coordinates() {
+        let uv = |x, y, z| sphere_uv(&FreeVec3::new(x, y, z));
+        let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+            (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12
+        };
+        assert!(close(uv(1., 0., 0.), (0.5, 0.5)));
+        assert!(close(uv(0., 1., 0.), (0.5, 1.)));
+        assert!(close(uv(0., 0., 1.), (0.25, 0.5)));
+        assert!(close(uv(0., -1., 0.), (0.5, 0.)));
+        assert!(close(uv(0., 0., -1.), (0.75, 0.5)));
     }
 }
@@ -0,0 +1,45 @@
+use super::{Aabb, HitRecord, Object};
+use crate::ray::Ray;
+use std::sync::Arc;
+
+/// Object whose hits are labelled with identifiers of the object or of its material,
+/// which are written to the ID passes of renders.
+///
+/// Identifiers set by tags further out replace those set further in, so the object of
+/// the world containing a shape names it.
+pub struct Tagged {
+    object: Arc<dyn Object + Send + Sync>,
+    object_id: Option<u32>,
+    material_id: Option<u32>,
+}
+
+impl Tagged {
+    pub fn new(
+        object: Arc<dyn Object + Send + Sync>,
+        object_id: Option<u32>,
+        material_id: Option<u32>,
+    ) -> Self {
+        Tagged {
+            object,
+            object_id,
+            material_id,
+        }
+    }
+}
+
+impl Object for Tagged {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut hit = self.object.hit(ray, t_min, t_max)?;
+        if let Some(id) = self.object_id {
+            hit.object_id = id;
+        }
+        if let Some(id) = self.material_id {
+            hit.material_id = id;
+        }
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
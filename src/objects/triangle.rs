@@ -0,0 +1,285 @@
+use super::aabb::{component, point_component};
+use super::mesh::{Face, VertexIndex};
+use super::{Aabb, HitRecord, Object, TriangleMesh};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+use std::sync::Arc;
+
+/// Single face of a `TriangleMesh`.
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+}
+
+impl Triangle {
+    /// Stand-alone triangle with corners in counter-clockwise order.
+    pub fn new(a: Point, b: Point, c: Point, material: Material) -> Self {
+        let face = Face {
+            vertices: [
+                VertexIndex::new(0),
+                VertexIndex::new(1),
+                VertexIndex::new(2),
+            ],
+            material: 0,
+        };
+        let mesh = TriangleMesh::new(
+            vec![a, b, c],
+            Vec::new(),
+            Vec::new(),
+            vec![face],
+            vec![material],
+        );
+        Triangle::from_mesh(Arc::new(mesh), 0)
+    }
+
+    pub fn from_mesh(mesh: Arc<TriangleMesh>, face: usize) -> Self {
+        Triangle { mesh, face }
+    }
+
+    fn face(&self) -> &Face {
+        &self.mesh.faces[self.face]
+    }
+
+    fn corners(&self) -> [Point; 3] {
+        let vertices = &self.face().vertices;
+        [
+            self.mesh.positions[vertices[0].position],
+            self.mesh.positions[vertices[1].position],
+            self.mesh.positions[vertices[2].position],
+        ]
+    }
+}
+
+impl Object for Triangle {
+    /// Watertight ray/triangle intersection of Woop, Benthin and Wald (2013): rays
+    /// through an edge shared by two triangles never slip between them.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let corners = self.corners();
+
+        // Permute the axes so that the ray direction is largest along z.
+        let abs = |axis| component(&ray.direction, axis).abs();
+        let kz = if abs(0) > abs(1) && abs(0) > abs(2) {
+            0
+        } else if abs(1) > abs(2) {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if component(&ray.direction, kz) < 0. {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // Shear the triangle so that the ray points along +z from the origin.
+        let dz = component(&ray.direction, kz);
+        let sx = component(&ray.direction, kx) / dz;
+        let sy = component(&ray.direction, ky) / dz;
+        let sz = 1. / dz;
+        let sheared = |p: &Point| {
+            let rel = *p - ray.origin;
+            let z = component(&rel, kz);
+            (
+                component(&rel, kx) - sx * z,
+                component(&rel, ky) - sy * z,
+                sz * z,
+            )
+        };
+        let (ax, ay, az) = sheared(&corners[0]);
+        let (bx, by, bz) = sheared(&corners[1]);
+        let (cx, cy, cz) = sheared(&corners[2]);
+
+        // Scaled barycentric coordinates of the ray relative to the edges.
+        let e0 = cx * by - cy * bx;
+        let e1 = ax * cy - ay * cx;
+        let e2 = bx * ay - by * ax;
+        if (e0 < 0. || e1 < 0. || e2 < 0.) && (e0 > 0. || e1 > 0. || e2 > 0.) {
+            return None;
+        }
+        let det = e0 + e1 + e2;
+        if det == 0. {
+            return None;
+        }
+
+        let t = (e0 * az + e1 * bz + e2 * cz) / det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let (b0, b1, b2) = (e0 / det, e1 / det, e2 / det);
+
+        let face = self.face();
+        let geometric_normal =
+            UnitVec3::from((corners[1] - corners[0]).cross(&(corners[2] - corners[0])));
+        let mut hit = HitRecord::new(
+            ray,
+            ray.at(t),
+            geometric_normal,
+            t,
+            self.mesh.materials[face.material].clone(),
+        );
+
+        let [v0, v1, v2] = face.vertices;
+        if let (Some(n0), Some(n1), Some(n2)) = (v0.normal, v1.normal, v2.normal) {
+            let normals = &self.mesh.normals;
+            let shading_normal =
+                FreeVec3::from(normals[n0]) * b0 + normals[n1] * b1 + normals[n2] * b2;
+            hit.set_shading_normal(shading_normal.into());
+        }
+
+        let (u, v) = if let (Some(t0), Some(t1), Some(t2)) = (v0.uv, v1.uv, v2.uv) {
+            let uvs = &self.mesh.uvs;
+            (
+                b0 * uvs[t0].0 + b1 * uvs[t1].0 + b2 * uvs[t2].0,
+                b0 * uvs[t0].1 + b1 * uvs[t1].1 + b2 * uvs[t2].1,
+            )
+        } else {
+            (b1, b2)
+        };
+        hit.u = u;
+        hit.v = v;
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [a, b, c] = self.corners();
+        let coord = |f: fn(f64, f64) -> f64, axis| {
+            f(
+                point_component(&a, axis),
+                f(point_component(&b, axis), point_component(&c, axis)),
+            )
+        };
+        Some(Aabb::new(
+            Point::new(coord(f64::min, 0), coord(f64::min, 1), coord(f64::min, 2)),
+            Point::new(coord(f64::max, 0), coord(f64::max, 1), coord(f64::max, 2)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::random;
+
+    fn material() -> Material {
+        Material::Lambertian(Color::new(0.5, 0.5, 0.5).into())
+    }
+
+    fn down_ray(x: f64, z: f64) -> Ray {
+        Ray::new(
+            &Point::new(x, 1., z),
+            &FreeVec3::new(0., -1., 0.).into(),
+            0.,
+        )
+    }
+
+    #[test]
+    fn hits_inside_and_misses_outside() {
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(0., 0., 1.),
+            Point::new(1., 0., 0.),
+            material(),
+        );
+
+        let hit = triangle
+            .hit(&down_ray(0.25, 0.25), 0., f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 1.).abs() < 1e-12);
+        assert!(hit.front_face);
+        assert!((hit.normal.y() - 1.).abs() < 1e-12);
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.25).abs() < 1e-12);
+
+        assert!(triangle
+            .hit(&down_ray(0.75, 0.75), 0., f64::INFINITY)
+            .is_none());
+        assert!(triangle
+            .hit(&down_ray(-0.1, 0.5), 0., f64::INFINITY)
+            .is_none());
+        assert!(triangle.hit(&down_ray(0.25, 0.25), 0., 0.5).is_none());
+    }
+
+    #[test]
+    fn shared_edges_are_watertight() {
+        // Two triangles covering the unit square, split along its diagonal.
+        let positions = vec![
+            Point::new(0., 0., 0.),
+            Point::new(0., 0., 1.),
+            Point::new(1., 0., 1.),
+            Point::new(1., 0., 0.),
+        ];
+        let face = |a, b, c| Face {
+            vertices: [
+                VertexIndex::new(a),
+                VertexIndex::new(b),
+                VertexIndex::new(c),
+            ],
+            material: 0,
+        };
+        let mesh = Arc::new(TriangleMesh::new(
+            positions,
+            Vec::new(),
+            Vec::new(),
+            vec![face(0, 1, 2), face(0, 2, 3)],
+            vec![material()],
+        ));
+        let triangles = mesh.triangles();
+
+        random::reseed(4, 0);
+        for _ in 0..10_000 {
+            let s: f64 = random::gen_range(0.01, 0.99);
+            let origin = Point::new(s + random::gen_range(-0.5, 0.5), 1., s);
+            let ray = Ray::new(&origin, &(Point::new(s, 0., s) - origin).into(), 0.);
+            assert!(triangles
+                .iter()
+                .any(|t| t.hit(&ray, 0., f64::INFINITY).is_some()));
+        }
+    }
+
+    #[test]
+    fn interpolates_normals_and_uvs() {
+        let vertex = |i| VertexIndex {
+            position: i,
+            normal: Some(i),
+            uv: Some(i),
+        };
+        let mesh = Arc::new(TriangleMesh::new(
+            vec![
+                Point::new(0., 0., 0.),
+                Point::new(0., 0., 1.),
+                Point::new(1., 0., 0.),
+            ],
+            vec![
+                FreeVec3::new(0., 1., 0.).into(),
+                FreeVec3::new(0., 1., 1.).into(),
+                FreeVec3::new(1., 1., 0.).into(),
+            ],
+            vec![(0., 0.), (0., 1.), (1., 0.)],
+            vec![Face {
+                vertices: [vertex(0), vertex(1), vertex(2)],
+                material: 0,
+            }],
+            vec![material()],
+        ));
+        let triangle = Triangle::from_mesh(mesh, 0);
+
+        let hit = triangle.hit(&down_ray(0.5, 0.), 0., f64::INFINITY).unwrap();
+        let expected = UnitVec3::from(
+            FreeVec3::new(0., 0.5, 0.) + UnitVec3::from(FreeVec3::new(1., 1., 0.)) * 0.5,
+        );
+        assert!((hit.normal.dot(&expected) - 1.).abs() < 1e-12);
+        assert!((hit.u - 0.5).abs() < 1e-12 && hit.v.abs() < 1e-12);
+
+        // Hits from below keep the interpolated normal facing the ray.
+        let ray = Ray::new(
+            &Point::new(0.2, -1., 0.2),
+            &FreeVec3::new(0., 1., 0.).into(),
+            0.,
+        );
+        let hit = triangle.hit(&ray, 0., f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!(hit.normal.y() < 0.);
+    }
+}
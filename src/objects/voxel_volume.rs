@@ -0,0 +1,165 @@
+use super::{Aabb, HitRecord, Object};
+use crate::color::Color;
+use crate::grid::DensityGrid;
+use crate::material::Material;
+use crate::random;
+use crate::ray::Ray;
+use crate::spaces::{FreeVec3, Point};
+use crate::texture::Texture;
+use std::sync::Arc;
+
+/// Heterogeneous medium whose density is given by a voxel grid stretched over a box.
+///
+/// Collisions are sampled with delta tracking against the largest density of the grid,
+/// so that empty and thin regions are handled without bias by rejecting null
+/// collisions.
+pub struct VoxelVolume {
+    density: Arc<DensityGrid>,
+    bounds: Aabb,
+    density_scale: f64,
+    albedo: Arc<dyn Texture + Send + Sync>,
+    /// Grid scaling the emitted color at each collision.
+    emission: Option<(Arc<DensityGrid>, Color)>,
+    majorant: f64,
+}
+
+impl VoxelVolume {
+    /// Volume filling `bounds` whose density is the grid value times `density_scale`.
+    pub fn new(
+        density: Arc<DensityGrid>,
+        bounds: Aabb,
+        density_scale: f64,
+        albedo: Arc<dyn Texture + Send + Sync>,
+    ) -> Self {
+        VoxelVolume {
+            majorant: density.max() * density_scale,
+            density,
+            bounds,
+            density_scale,
+            albedo,
+            emission: None,
+        }
+    }
+
+    /// Makes the volume glow with `color` scaled by the values of `grid`, which is
+    /// stretched over the same box as the densities.
+    pub fn emission(self, grid: Arc<DensityGrid>, color: Color) -> Self {
+        VoxelVolume {
+            emission: Some((grid, color)),
+            ..self
+        }
+    }
+
+    /// Coordinates of the point in the unit cube the grids are defined on.
+    fn grid_coordinates(&self, p: &Point) -> (f64, f64, f64) {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        (
+            (p.x() - min.x()) / (max.x() - min.x()),
+            (p.y() - min.y()) / (max.y() - min.y()),
+            (p.z() - min.z()) / (max.z() - min.z()),
+        )
+    }
+
+    pub fn density(&self, p: &Point) -> f64 {
+        let (x, y, z) = self.grid_coordinates(p);
+        self.density.value(x, y, z) * self.density_scale
+    }
+}
+
+impl Object for VoxelVolume {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (mut t, exit) = self.bounds.clip(ray, t_min, t_max)?;
+        if self.majorant <= 0. {
+            return None;
+        }
+        // Ray directions are unit vectors, so `t` measures distances.
+        loop {
+            t -= (1. - random::random::<f64>()).ln() / self.majorant;
+            if t >= exit {
+                return None;
+            }
+            let p = ray.at(t);
+            if random::random::<f64>() * self.majorant < self.density(&p) {
+                let material = match &self.emission {
+                    None => Material::Isotropic(self.albedo.clone()),
+                    Some((grid, color)) => {
+                        let (x, y, z) = self.grid_coordinates(&p);
+                        Material::EmissiveIsotropic {
+                            albedo: self.albedo.clone(),
+                            emission: *color * grid.value(x, y, z),
+                        }
+                    }
+                };
+                let mut hit = HitRecord::new(ray, p, FreeVec3::new(1., 0., 0.).into(), t, material);
+                // Media have no surface, the normal and side are arbitrary.
+                hit.front_face = true;
+                return Some(hit);
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::emitted;
+
+    fn uniform(value: f32, scale: f64) -> VoxelVolume {
+        VoxelVolume::new(
+            Arc::new(DensityGrid::new([4, 3, 5], vec![value; 60])),
+            Aabb::new(Point::new(-1., -1., -1.), Point::new(1., 1., 1.)),
+            scale,
+            Color::new(0.8, 0.8, 0.8).into(),
+        )
+    }
+
+    fn ray(z: f64) -> Ray {
+        Ray::new(
+            &Point::new(0.3, -0.2, z),
+            &FreeVec3::new(0., 0., -1.).into(),
+            0.,
+        )
+    }
+
+    #[test]
+    fn transmittance_of_uniform_grid() {
+        random::reseed(12, 0);
+        let volume = uniform(0.25, 3.);
+        let n = 20000;
+        // Crossing the whole box, starting in its middle and stopping halfway through it.
+        for &(z, t_max, length) in [
+            (4., f64::INFINITY, 2f64),
+            (0., f64::INFINITY, 1.),
+            (4., 4., 1.),
+        ]
+        .iter()
+        {
+            let expected = (-0.75 * length).exp();
+            let passed = (0..n)
+                .filter(|_| volume.hit(&ray(z), 0., t_max).is_none())
+                .count();
+            let ratio = passed as f64 / n as f64;
+            assert!((ratio - expected).abs() < 0.01, "{} != {}", ratio, expected);
+        }
+    }
+
+    #[test]
+    fn empty_grid_is_transparent() {
+        let volume = uniform(0., 1.);
+        assert!(volume.hit(&ray(4.), 0., f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn emits_scaled_by_grid() {
+        random::reseed(13, 0);
+        let emission = Arc::new(DensityGrid::new([1, 1, 1], vec![0.5]));
+        let volume = uniform(1., 100.).emission(emission, Color::new(2., 4., 6.));
+        let hit = volume.hit(&ray(4.), 0., f64::INFINITY).unwrap();
+        assert!(hit.t > 3. && hit.t < 3.2);
+        assert_eq!(emitted(&hit), Color::new(1., 2., 3.));
+    }
+}
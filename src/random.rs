@@ -0,0 +1,35 @@
+//! Per-thread random number generator that can be reseeded for reproducible renders.
+//!
+//! Rendering threads reseed the generator before every unit of work with the render
+//! seed and the index of the work item, so the image does not depend on how the work
+//! is distributed between threads.
+use rand::distributions::{uniform::SampleUniform, Distribution, Standard};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+/// Restarts the generator of the current thread at the given seed and stream.
+pub fn reseed(seed: u64, stream: u64) {
+    // Spread consecutive streams over the whole seed space.
+    let mixed = seed ^ stream.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(mixed));
+}
+
+pub fn with_rng<T>(f: impl FnOnce(&mut SmallRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    with_rng(|rng| rng.gen())
+}
+
+pub fn gen_range<T: SampleUniform>(low: T, high: T) -> T {
+    with_rng(|rng| rng.gen_range(low, high))
+}
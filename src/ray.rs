@@ -1,15 +1,20 @@
 use crate::spaces::{Point, UnitVec3};
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Point,
     pub direction: UnitVec3,
+    /// Instant at which the ray is sent, moving objects are intersected at their
+    /// position at this time.
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: &Point, direction: &UnitVec3) -> Self {
+    pub fn new(origin: &Point, direction: &UnitVec3, time: f64) -> Self {
         Ray {
             origin: *origin,
             direction: *direction,
+            time,
         }
     }
 
@@ -0,0 +1,2154 @@
+//! Loading of scenes described in TOML files.
+//!
+//! A scene file consists of a `[render]` table with the image settings, a `[camera]`
+//! table, optional `[background]` and `[atmosphere]` tables, named `[textures.<name>]`,
+//! `[materials.<name>]` and `[shapes.<name>]` tables, an `[[objects]]` array referring
+//! to the materials and shapes by their names and an optional `[[lights]]` array. The
+//! format is described in `scenes/README.md`, next to example scenes.
+use crate::adaptive::AdaptiveSampling;
+use crate::aov::{Aov, AovSet};
+use crate::background::Background;
+use crate::camera::CameraSettings;
+use crate::color::Color;
+use crate::grid::{DensityGrid, GridError};
+use crate::light::{
+    sky, DirectionalLight, EnvironmentMap, Light, LightList, PhysicalSky, PointLight, QuadLight,
+    SphereLight,
+};
+use crate::material::{ComplexIor, Dispersion, Interior, Material, Principled};
+use crate::obj::{self, ObjError};
+use crate::objects::{
+    Aabb, BoxShape, ConstantMedium, Instance, MovingSphere, Object, ObjectList, Plane, Quad,
+    Sphere, Tagged, Triangle, VoxelVolume,
+};
+use crate::spaces::{FreeVec3, Placement, Point, Vec3};
+use crate::spectrum::{MAX_WAVELENGTH, MIN_WAVELENGTH};
+use crate::texture::{Checker, ImageTexture, Marble, Noise, Texture};
+use crate::tonemap::{Operator, ToneMapping};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Everything needed to render an image.
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub world: ObjectList,
+    /// Lights sampled directly, including the emitting spheres and quads of the world.
+    pub lights: LightList,
+    pub background: Background,
+    pub settings: RenderSettings,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    /// Whether to trace wavelengths rather than red, green and blue.
+    pub spectral: bool,
+    pub tone_mapping: ToneMapping,
+    /// Passes rendered alongside the radiance.
+    pub aovs: AovSet,
+    /// Whether to remove the noise of the radiance once rendered.
+    pub denoise: bool,
+    /// Rule for stopping the sampling of pixels early, which otherwise all take
+    /// `samples_per_pixel` samples.
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { file: PathBuf, error: io::Error },
+    Syntax(toml::de::Error),
+    Invalid { location: String, message: String },
+    Mesh(ObjError),
+    Grid(GridError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            SceneError::Syntax(error) => write!(f, "{}", error),
+            SceneError::Invalid { location, message } => write!(f, "{}: {}", location, message),
+            SceneError::Mesh(error) => write!(f, "{}", error),
+            SceneError::Grid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Syntax(error) => Some(error),
+            SceneError::Invalid { .. } => None,
+            SceneError::Mesh(error) => Some(error),
+            SceneError::Grid(error) => Some(error),
+        }
+    }
+}
+
+fn invalid(location: impl Into<String>, message: impl Into<String>) -> SceneError {
+    SceneError::Invalid {
+        location: location.into(),
+        message: message.into(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    render: RenderDesc,
+    camera: CameraDesc,
+    #[serde(default)]
+    background: BackgroundDesc,
+    atmosphere: Option<AtmosphereDesc>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    shapes: HashMap<String, ObjectDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    width: i64,
+    height: Option<i64>,
+    #[serde(default = "default_samples_per_pixel")]
+    samples_per_pixel: i64,
+    #[serde(default = "default_max_depth")]
+    max_depth: i64,
+    #[serde(default)]
+    spectral: bool,
+    #[serde(default)]
+    exposure: f64,
+    tone_map: Option<String>,
+    #[serde(default)]
+    dither: bool,
+    #[serde(default)]
+    aovs: Vec<String>,
+    #[serde(default)]
+    denoise: bool,
+    adaptive: Option<AdaptiveDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdaptiveDesc {
+    threshold: f64,
+    #[serde(default = "default_min_samples")]
+    min_samples: i64,
+}
+
+fn default_min_samples() -> i64 {
+    AdaptiveSampling::DEFAULT_MIN_SAMPLES as i64
+}
+
+fn default_samples_per_pixel() -> i64 {
+    100
+}
+
+fn default_max_depth() -> i64 {
+    50
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_up")]
+    up: [f64; 3],
+    vertical_fov: f64,
+    /// Determines the image height when the render settings do not specify it.
+    aspect_ratio: Option<f64>,
+    #[serde(default)]
+    aperture: f64,
+    focus_distance: Option<f64>,
+    /// Interval during which the shutter is open, moving objects get blurred over it.
+    #[serde(default)]
+    shutter_open: f64,
+    #[serde(default = "default_time1")]
+    shutter_close: f64,
+}
+
+fn default_time1() -> f64 {
+    1.
+}
+
+fn default_up() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+    },
+    Black,
+    /// Equirectangular `.hdr` or `.exr` image whose path is relative to the scene file,
+    /// rotated by `rotation` degrees around the vertical axis.
+    Environment {
+        file: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// Daylight sky and sun, with angles in degrees.
+    Sky {
+        sun_elevation: f64,
+        #[serde(default)]
+        sun_azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.
+}
+
+fn default_turbidity() -> f64 {
+    3.
+}
+
+impl Default for BackgroundDesc {
+    fn default() -> Self {
+        BackgroundDesc::Gradient {
+            bottom: [1., 1., 1.],
+            top: [0.5, 0.7, 1.],
+        }
+    }
+}
+
+/// Fog filling the sphere of the given radius around the camera, or all of space without
+/// a radius, which keeps the light of the background from reaching the camera.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtmosphereDesc {
+    density: f64,
+    radius: Option<f64>,
+    #[serde(default = "default_albedo")]
+    color: [f64; 3],
+}
+
+fn default_albedo() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+/// Color of a material given either directly or by the name of a texture.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Named(String),
+}
+
+/// Scalar parameter of a material given either directly or by the name of a texture,
+/// whose luminance is used.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum ScalarRef {
+    Value(f64),
+    Named(String),
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        even: TextureRef,
+        odd: TextureRef,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    /// Image file whose path is relative to the scene file.
+    Image {
+        file: PathBuf,
+    },
+    Noise {
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    Marble {
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+}
+
+fn default_scale() -> f64 {
+    1.
+}
+
+fn default_base_color() -> TextureRef {
+    TextureRef::Color([0.8, 0.8, 0.8])
+}
+
+fn zero() -> ScalarRef {
+    ScalarRef::Value(0.)
+}
+
+fn half() -> ScalarRef {
+    ScalarRef::Value(0.5)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f64; 3],
+    c: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        color: TextureRef,
+    },
+    Metal {
+        color: TextureRef,
+        fuzziness: f64,
+    },
+    /// Glass or liquid absorbing light per unit of distance inside it. Where volumes
+    /// overlap, the one of the highest `priority` wins. The refraction index is either
+    /// constant or given by the coefficients of Cauchy's `[a, b]` or Sellmeier's formula,
+    /// for wavelengths in micrometres.
+    Dielectric {
+        refraction_index: Option<f64>,
+        cauchy: Option<[f64; 2]>,
+        sellmeier: Option<SellmeierDesc>,
+        #[serde(default)]
+        absorption: [f64; 3],
+        #[serde(default)]
+        priority: u32,
+    },
+    /// GGX microfacet metal, either a named preset (`gold`, `copper` or `aluminium`) or
+    /// given by the real and imaginary parts `eta` and `k` of its refraction index.
+    Conductor {
+        metal: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+    },
+    /// GGX microfacet glass.
+    RoughDielectric {
+        refraction_index: f64,
+        roughness: f64,
+    },
+    /// Uber material whose parameters, except for the color, are between 0 and 1.
+    Principled {
+        #[serde(default = "default_base_color")]
+        base_color: TextureRef,
+        #[serde(default = "zero")]
+        metallic: ScalarRef,
+        #[serde(default = "half")]
+        roughness: ScalarRef,
+        #[serde(default = "half")]
+        specular: ScalarRef,
+        #[serde(default = "zero")]
+        sheen: ScalarRef,
+        #[serde(default = "zero")]
+        clearcoat: ScalarRef,
+        #[serde(default = "zero")]
+        transmission: ScalarRef,
+        #[serde(default = "zero")]
+        subsurface: ScalarRef,
+    },
+    DiffuseLight {
+        emission: [f64; 3],
+    },
+    /// Phase function for objects of type `constant_medium`.
+    Isotropic {
+        color: TextureRef,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    /// Sphere moving with constant velocity from `center0` at `time0` to `center1` at
+    /// `time1`.
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    /// Parallelogram spanned by the edges `u` and `v`, facing in the direction of `u × v`.
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    /// Axis-aligned box given by two opposite corners.
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+    },
+    /// Wavefront OBJ file whose path is relative to the scene file.
+    Mesh { file: PathBuf },
+    /// Heterogeneous medium with the densities of a `.npy` or raw voxel grid file
+    /// stretched over the box from `min` to `max`. Raw files need the `dimensions` of
+    /// the grid. Collisions glow with the `emission` color scaled by the values of the
+    /// `emission_file` grid, or by the densities if it is not given.
+    VoxelGrid {
+        file: PathBuf,
+        dimensions: Option<[usize; 3]>,
+        min: [f64; 3],
+        max: [f64; 3],
+        #[serde(default = "default_scale")]
+        density_scale: f64,
+        #[serde(default = "default_albedo")]
+        albedo: [f64; 3],
+        emission: Option<[f64; 3]>,
+        emission_file: Option<PathBuf>,
+    },
+    /// Fog or smoke filling the named closed shape, scattering with an isotropic
+    /// material.
+    ConstantMedium {
+        boundary: String,
+        density: f64,
+        material: String,
+    },
+    /// Copy of a named shape, scaled first, then rotated and translated last.
+    Instance {
+        shape: String,
+        #[serde(default = "default_scaling")]
+        scale: [f64; 3],
+        #[serde(default)]
+        rotate: [f64; 3],
+        #[serde(default)]
+        translate: [f64; 3],
+        motion: Option<MotionDesc>,
+    },
+}
+
+/// Placement an instance moves to, parts that are not given stay as they are.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MotionDesc {
+    scale: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    translate: Option<[f64; 3]>,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_time1")]
+    time1: f64,
+}
+
+fn default_scaling() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+/// Lights without a surface, which only light the scene by being sampled.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    /// Point emitting `intensity` per solid angle in all directions.
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    /// Parallel light travelling in `direction`, like sunlight.
+    Directional {
+        direction: [f64; 3],
+        irradiance: [f64; 3],
+    },
+}
+
+fn point(p: [f64; 3]) -> Point {
+    Point::new(p[0], p[1], p[2])
+}
+
+fn vector(v: [f64; 3]) -> FreeVec3 {
+    FreeVec3::new(v[0], v[1], v[2])
+}
+
+fn is_positive(x: f64) -> bool {
+    x > 0. && x.is_finite()
+}
+
+fn color(location: &str, c: [f64; 3]) -> Result<Color, SceneError> {
+    if c.iter().any(|&x| x < 0. || !x.is_finite()) {
+        return Err(invalid(
+            location,
+            format!("color components must be non-negative, found {:?}", c),
+        ));
+    }
+    Ok(Color::new(c[0], c[1], c[2]))
+}
+
+impl Scene {
+    /// Reads and validates the scene file at `path`.
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+            file: path.to_owned(),
+            error,
+        })?;
+        Scene::parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Builds a scene from the contents of a scene file, resolving referenced files
+    /// relative to `directory`.
+    pub fn parse(source: &str, directory: &Path) -> Result<Scene, SceneError> {
+        let desc: SceneDesc = toml::from_str(source).map_err(SceneError::Syntax)?;
+        let settings = desc.render.build(desc.camera.aspect_ratio)?;
+        let camera = desc.camera.build()?;
+        let background = desc.background.build(directory)?;
+
+        // Build every texture, so that even unused ones get validated.
+        let names: Vec<String> = desc.textures.keys().cloned().collect();
+        let mut textures = Textures {
+            descs: desc.textures,
+            built: HashMap::new(),
+            directory,
+        };
+        for name in names {
+            textures.named(&name, "textures", &mut Vec::new())?;
+        }
+
+        let mut materials = HashMap::new();
+        for (name, material) in desc.materials {
+            let location = format!("materials.{}", name);
+            materials.insert(name, material.build(&location, &mut textures)?);
+        }
+
+        let mut material_names: Vec<&String> = materials.keys().collect();
+        material_names.sort();
+        let material_ids = (1..)
+            .zip(material_names)
+            .map(|(id, name)| (name.clone(), id))
+            .collect();
+        let names: Vec<String> = desc.shapes.keys().cloned().collect();
+        let mut shapes = Shapes {
+            descs: desc.shapes,
+            built: HashMap::new(),
+            visiting: Vec::new(),
+            materials: &materials,
+            material_ids,
+            directory,
+        };
+        for name in names {
+            shapes.named(&name, "shapes")?;
+        }
+
+        let mut world = ObjectList {
+            objects: Vec::new(),
+        };
+        if let Some(atmosphere) = desc.atmosphere {
+            let density = positive_density("atmosphere", atmosphere.density)?;
+            let albedo = color("atmosphere", atmosphere.color)?;
+            let phase_function = Material::Isotropic(albedo.into());
+            world.add(Arc::new(match atmosphere.radius {
+                Some(radius) if is_positive(radius) => {
+                    let extent = Sphere::new(camera.lookfrom, radius, phase_function.clone());
+                    ConstantMedium::new(Arc::new(extent), density, phase_function)
+                }
+                Some(radius) => {
+                    return Err(invalid(
+                        "atmosphere.radius",
+                        format!("must be positive, found {}", radius),
+                    ))
+                }
+                None => ConstantMedium::everywhere(density, phase_function),
+            }));
+        }
+        let mut lights = LightList::default();
+        for (i, object) in desc.objects.into_iter().enumerate() {
+            let location = format!("objects[{}]", i);
+            if let Some(light) = object.light(&materials) {
+                lights.add(light);
+            }
+            let object = object.build(&location, &mut shapes)?;
+            world.add(Arc::new(Tagged::new(object, Some(i as u32 + 1), None)));
+        }
+        for (i, light) in desc.lights.into_iter().enumerate() {
+            lights.add(light.build(&format!("lights[{}]", i))?);
+        }
+        if let Some(light) = background.light() {
+            lights.add(light);
+        }
+
+        Ok(Scene {
+            camera,
+            world,
+            lights,
+            background,
+            settings,
+        })
+    }
+
+    /// Replaces the background, along with the light sampling it.
+    pub fn set_background(&mut self, background: Background) {
+        if let Some(old) = self.background.light() {
+            self.lights.lights.retain(|light| !Arc::ptr_eq(light, &old));
+        }
+        if let Some(light) = background.light() {
+            self.lights.add(light);
+        }
+        self.background = background;
+    }
+}
+
+impl RenderDesc {
+    fn build(&self, aspect_ratio: Option<f64>) -> Result<RenderSettings, SceneError> {
+        let positive = |name: &str, value: i64| {
+            if value > 0 {
+                Ok(value)
+            } else {
+                Err(invalid(
+                    format!("render.{}", name),
+                    format!("must be positive, found {}", value),
+                ))
+            }
+        };
+        // Counts of samples and bounces are kept in 32 bits.
+        let count = |name: &str, value: i64| {
+            i32::try_from(positive(name, value)?).map_err(|_| {
+                invalid(
+                    format!("render.{}", name),
+                    format!("must be at most {}, found {}", i32::MAX, value),
+                )
+            })
+        };
+        let image_width = positive("width", self.width)? as usize;
+        let aspect_ratio = match aspect_ratio {
+            Some(ratio) if is_positive(ratio) => Some(ratio),
+            Some(ratio) => {
+                return Err(invalid(
+                    "camera.aspect_ratio",
+                    format!("must be positive, found {}", ratio),
+                ))
+            }
+            None => None,
+        };
+        let image_height = match (self.height, aspect_ratio) {
+            (Some(height), _) => positive("height", height)? as usize,
+            (None, Some(aspect_ratio)) => ((image_width as f64 / aspect_ratio) as usize).max(1),
+            (None, None) => {
+                return Err(invalid(
+                    "render.height",
+                    "either the image height or the camera aspect ratio must be given",
+                ))
+            }
+        };
+        if !self.exposure.is_finite() {
+            return Err(invalid(
+                "render.exposure",
+                format!("must be finite, found {}", self.exposure),
+            ));
+        }
+        let operator = match &self.tone_map {
+            Some(name) => Operator::named(name).ok_or_else(|| {
+                invalid(
+                    "render.tone_map",
+                    format!("unknown operator `{}`, expected {}", name, Operator::NAMES),
+                )
+            })?,
+            None => Operator::Clamp,
+        };
+        let aovs = self
+            .aovs
+            .iter()
+            .map(|name| {
+                Aov::named(name).ok_or_else(|| {
+                    invalid(
+                        "render.aovs",
+                        format!("unknown pass `{}`, expected {}", name, Aov::NAMES),
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let adaptive = match &self.adaptive {
+            Some(adaptive) => {
+                if !is_positive(adaptive.threshold) {
+                    return Err(invalid(
+                        "render.adaptive.threshold",
+                        format!("must be positive, found {}", adaptive.threshold),
+                    ));
+                }
+                Some(AdaptiveSampling {
+                    min_samples: count("adaptive.min_samples", adaptive.min_samples)?,
+                    threshold: adaptive.threshold,
+                })
+            }
+            None => None,
+        };
+        Ok(RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel: count("samples_per_pixel", self.samples_per_pixel)?,
+            max_depth: count("max_depth", self.max_depth)?,
+            spectral: self.spectral,
+            tone_mapping: ToneMapping {
+                operator,
+                exposure: self.exposure,
+                dither: self.dither,
+            },
+            aovs,
+            denoise: self.denoise,
+            adaptive,
+        })
+    }
+}
+
+impl CameraDesc {
+    fn build(&self) -> Result<CameraSettings, SceneError> {
+        if !(self.vertical_fov > 0. && self.vertical_fov < 180.) {
+            return Err(invalid(
+                "camera.vertical_fov",
+                format!(
+                    "must be between 0 and 180 degrees, found {}",
+                    self.vertical_fov
+                ),
+            ));
+        }
+        if self.aperture < 0. {
+            return Err(invalid(
+                "camera.aperture",
+                format!("must not be negative, found {}", self.aperture),
+            ));
+        }
+
+        let look_from = point(self.look_from);
+        let look_at = point(self.look_at);
+        let view = look_at - look_from;
+        if view.length() == 0. {
+            return Err(invalid(
+                "camera.look_at",
+                "must differ from camera.look_from",
+            ));
+        }
+        let up = vector(self.up);
+        if up.cross(&view).length() == 0. {
+            return Err(invalid(
+                "camera.up",
+                "must not be parallel to the viewing direction",
+            ));
+        }
+        if self.shutter_close < self.shutter_open {
+            return Err(invalid(
+                "camera.shutter_close",
+                format!(
+                    "must not be before camera.shutter_open, found {} < {}",
+                    self.shutter_close, self.shutter_open
+                ),
+            ));
+        }
+        let focus_distance = match self.focus_distance {
+            Some(distance) if is_positive(distance) => distance,
+            Some(distance) => {
+                return Err(invalid(
+                    "camera.focus_distance",
+                    format!("must be positive, found {}", distance),
+                ))
+            }
+            None => view.length(),
+        };
+
+        Ok(CameraSettings {
+            lookfrom: look_from,
+            lookat: vector(self.look_at),
+            vup: up,
+            vfov: self.vertical_fov,
+            aperture: self.aperture,
+            focus_dist: focus_distance,
+            shutter: (self.shutter_open, self.shutter_close),
+        })
+    }
+}
+
+impl BackgroundDesc {
+    fn build(self, directory: &Path) -> Result<Background, SceneError> {
+        Ok(match self {
+            BackgroundDesc::Solid { color: c } => Background::Solid(color("background", c)?),
+            BackgroundDesc::Gradient { bottom, top } => Background::Gradient {
+                bottom: color("background", bottom)?,
+                top: color("background", top)?,
+            },
+            BackgroundDesc::Black => Background::Black,
+            BackgroundDesc::Environment {
+                file,
+                rotation,
+                intensity,
+            } => {
+                if !rotation.is_finite() {
+                    return Err(invalid(
+                        "background",
+                        format!("rotation must be finite, found {}", rotation),
+                    ));
+                }
+                if !(intensity >= 0. && intensity.is_finite()) {
+                    return Err(invalid(
+                        "background",
+                        format!("intensity must be non-negative, found {}", intensity),
+                    ));
+                }
+                let file = directory.join(file);
+                let map = EnvironmentMap::load(&file, rotation, intensity).map_err(|error| {
+                    invalid(
+                        "background",
+                        format!("cannot load {}: {}", file.display(), error),
+                    )
+                })?;
+                Background::Environment(Arc::new(map))
+            }
+            BackgroundDesc::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                intensity,
+            } => {
+                if !sky::ELEVATIONS.contains(&sun_elevation) {
+                    return Err(invalid(
+                        "background",
+                        format!(
+                            "sun_elevation must be between 0 and 90, found {}",
+                            sun_elevation
+                        ),
+                    ));
+                }
+                if !sun_azimuth.is_finite() {
+                    return Err(invalid(
+                        "background",
+                        format!("sun_azimuth must be finite, found {}", sun_azimuth),
+                    ));
+                }
+                if !sky::TURBIDITIES.contains(&turbidity) {
+                    return Err(invalid(
+                        "background",
+                        format!("turbidity must be between 1.7 and 10, found {}", turbidity),
+                    ));
+                }
+                if !(intensity >= 0. && intensity.is_finite()) {
+                    return Err(invalid(
+                        "background",
+                        format!("intensity must be non-negative, found {}", intensity),
+                    ));
+                }
+                let sky = PhysicalSky::new(sun_elevation, sun_azimuth, turbidity, intensity);
+                Background::Sky(Arc::new(sky))
+            }
+        })
+    }
+}
+
+type TextureArc = Arc<dyn Texture + Send + Sync>;
+
+/// Textures of the scene file, built on first use so that they can refer to each other
+/// regardless of their order.
+struct Textures<'a> {
+    descs: HashMap<String, TextureDesc>,
+    built: HashMap<String, TextureArc>,
+    directory: &'a Path,
+}
+
+impl Textures<'_> {
+    fn get(&mut self, reference: &TextureRef, location: &str) -> Result<TextureArc, SceneError> {
+        self.resolve(reference, location, &mut Vec::new())
+    }
+
+    /// Texture of a scalar parameter, whose values must be between 0 and 1.
+    fn scalar(&mut self, reference: &ScalarRef, location: &str) -> Result<TextureArc, SceneError> {
+        match reference {
+            ScalarRef::Value(x) if (0. ..=1.).contains(x) => Ok(Color::new(*x, *x, *x).into()),
+            ScalarRef::Value(x) => Err(invalid(
+                location,
+                format!("value must be between 0 and 1, found {}", x),
+            )),
+            ScalarRef::Named(name) => self.named(name, location, &mut Vec::new()),
+        }
+    }
+
+    fn resolve(
+        &mut self,
+        reference: &TextureRef,
+        location: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<TextureArc, SceneError> {
+        match reference {
+            TextureRef::Color(c) => Ok(color(location, *c)?.into()),
+            TextureRef::Named(name) => self.named(name, location, visiting),
+        }
+    }
+
+    /// Returns the texture of the given name, `visiting` holds the textures whose
+    /// construction is in progress.
+    fn named(
+        &mut self,
+        name: &str,
+        location: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<TextureArc, SceneError> {
+        if let Some(texture) = self.built.get(name) {
+            return Ok(texture.clone());
+        }
+        let desc = self
+            .descs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| invalid(location, format!("unknown texture `{}`", name)))?;
+        let location = format!("textures.{}", name);
+        if visiting.iter().any(|n| n == name) {
+            return Err(invalid(location, "texture refers to itself"));
+        }
+
+        visiting.push(name.to_owned());
+        let positive_scale = |scale: f64| {
+            if is_positive(scale) {
+                Ok(scale)
+            } else {
+                Err(invalid(
+                    &location,
+                    format!("scale must be positive, found {}", scale),
+                ))
+            }
+        };
+        let texture: TextureArc = match desc {
+            TextureDesc::Solid { color: c } => color(&location, c)?.into(),
+            TextureDesc::Checker { even, odd, scale } => Arc::new(Checker::new(
+                self.resolve(&even, &location, visiting)?,
+                self.resolve(&odd, &location, visiting)?,
+                positive_scale(scale)?,
+            )),
+            TextureDesc::Image { file } => {
+                let file = self.directory.join(file);
+                Arc::new(ImageTexture::load(&file).map_err(|error| {
+                    invalid(
+                        &location,
+                        format!("cannot load {}: {}", file.display(), error),
+                    )
+                })?)
+            }
+            TextureDesc::Noise { scale } => Arc::new(Noise::new(positive_scale(scale)?)),
+            TextureDesc::Marble { scale } => Arc::new(Marble::new(positive_scale(scale)?)),
+        };
+        visiting.pop();
+
+        self.built.insert(name.to_owned(), texture.clone());
+        Ok(texture)
+    }
+}
+
+impl MaterialDesc {
+    fn build(self, location: &str, textures: &mut Textures) -> Result<Material, SceneError> {
+        Ok(match self {
+            MaterialDesc::Lambertian { color } => {
+                Material::Lambertian(textures.get(&color, location)?)
+            }
+            MaterialDesc::Metal { color, fuzziness } => {
+                if !(0. ..=1.).contains(&fuzziness) {
+                    return Err(invalid(
+                        location,
+                        format!("fuzziness must be between 0 and 1, found {}", fuzziness),
+                    ));
+                }
+                Material::Metal {
+                    color: textures.get(&color, location)?,
+                    fuzziness,
+                }
+            }
+            MaterialDesc::Dielectric {
+                refraction_index,
+                cauchy,
+                sellmeier,
+                absorption,
+                priority,
+            } => {
+                let interior = match (refraction_index, cauchy, sellmeier) {
+                    (Some(refraction_index), None, None) => {
+                        Interior::new(refraction_index_of(location, refraction_index)?)
+                    }
+                    (None, Some([a, b]), None) => {
+                        Interior::dispersive(dispersion_of(location, Dispersion::Cauchy { a, b })?)
+                    }
+                    (None, None, Some(SellmeierDesc { b, c })) => Interior::dispersive(
+                        dispersion_of(location, Dispersion::Sellmeier { b, c })?,
+                    ),
+                    _ => {
+                        return Err(invalid(
+                            location,
+                            "exactly one of refraction_index, cauchy and sellmeier must be given",
+                        ))
+                    }
+                };
+                Material::Dielectric(
+                    interior
+                        .absorption(color(location, absorption)?)
+                        .priority(priority),
+                )
+            }
+            MaterialDesc::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                let ior = match (metal, eta, k) {
+                    (Some(metal), None, None) => ComplexIor::named(&metal).ok_or_else(|| {
+                        invalid(
+                            location,
+                            format!(
+                                "unknown metal `{}`, expected gold, copper or aluminium",
+                                metal
+                            ),
+                        )
+                    })?,
+                    (None, Some(eta), Some(k)) => ComplexIor {
+                        eta: color(location, eta)?,
+                        k: color(location, k)?,
+                    },
+                    _ => {
+                        return Err(invalid(
+                            location,
+                            "conductor needs either a metal or both eta and k",
+                        ))
+                    }
+                };
+                Material::Conductor {
+                    ior,
+                    roughness: roughness_of(location, roughness)?,
+                }
+            }
+            MaterialDesc::RoughDielectric {
+                refraction_index,
+                roughness,
+            } => Material::RoughDielectric {
+                refraction_index: refraction_index_of(location, refraction_index)?,
+                roughness: roughness_of(location, roughness)?,
+            },
+            MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                sheen,
+                clearcoat,
+                transmission,
+                subsurface,
+            } => {
+                let mut scalar = |reference: &ScalarRef, parameter: &str| {
+                    textures.scalar(reference, &format!("{}.{}", location, parameter))
+                };
+                Material::Principled(Arc::new(Principled {
+                    metallic: scalar(&metallic, "metallic")?,
+                    roughness: scalar(&roughness, "roughness")?,
+                    specular: scalar(&specular, "specular")?,
+                    sheen: scalar(&sheen, "sheen")?,
+                    clearcoat: scalar(&clearcoat, "clearcoat")?,
+                    transmission: scalar(&transmission, "transmission")?,
+                    subsurface: scalar(&subsurface, "subsurface")?,
+                    base_color: textures.get(&base_color, location)?,
+                }))
+            }
+            MaterialDesc::DiffuseLight { emission } => {
+                Material::DiffuseLight(color(location, emission)?)
+            }
+            MaterialDesc::Isotropic { color } => {
+                Material::Isotropic(textures.get(&color, location)?)
+            }
+        })
+    }
+}
+
+/// Checks that the refraction index is positive over the range of sampled wavelengths.
+fn dispersion_of(location: &str, dispersion: Dispersion) -> Result<Dispersion, SceneError> {
+    let steps = ((MAX_WAVELENGTH - MIN_WAVELENGTH) / 10.) as usize;
+    for step in 0..=steps {
+        let wavelength = MIN_WAVELENGTH + 10. * step as f64;
+        let refraction_index = dispersion.refraction_index(wavelength);
+        if !is_positive(refraction_index) {
+            return Err(invalid(
+                location,
+                format!(
+                    "refraction index must be positive, found {} at {} nm",
+                    refraction_index, wavelength
+                ),
+            ));
+        }
+    }
+    Ok(dispersion)
+}
+
+fn refraction_index_of(location: &str, refraction_index: f64) -> Result<f64, SceneError> {
+    if !is_positive(refraction_index) {
+        return Err(invalid(
+            location,
+            format!(
+                "refraction index must be positive, found {}",
+                refraction_index
+            ),
+        ));
+    }
+    Ok(refraction_index)
+}
+
+fn roughness_of(location: &str, roughness: f64) -> Result<f64, SceneError> {
+    if !(0. ..=1.).contains(&roughness) {
+        return Err(invalid(
+            location,
+            format!("roughness must be between 0 and 1, found {}", roughness),
+        ));
+    }
+    Ok(roughness)
+}
+
+fn find_material(
+    location: &str,
+    materials: &HashMap<String, Material>,
+    name: &str,
+) -> Result<Material, SceneError> {
+    materials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| invalid(location, format!("unknown material `{}`", name)))
+}
+
+type ObjectArc = Arc<dyn Object + Send + Sync>;
+
+/// Checks that a movement does not end before it starts.
+fn check_times(location: &str, time0: f64, time1: f64) -> Result<(), SceneError> {
+    if time0 <= time1 {
+        Ok(())
+    } else {
+        Err(invalid(
+            location,
+            format!(
+                "time0 must not be after time1, found {} and {}",
+                time0, time1
+            ),
+        ))
+    }
+}
+
+fn positive_density(location: &str, density: f64) -> Result<f64, SceneError> {
+    if is_positive(density) {
+        Ok(density)
+    } else {
+        Err(invalid(
+            location,
+            format!("density must be positive, found {}", density),
+        ))
+    }
+}
+
+fn phase_function(location: &str, material: Material) -> Result<Material, SceneError> {
+    match material {
+        Material::Isotropic(_) => Ok(material),
+        _ => Err(invalid(
+            location,
+            "media need a material of type `isotropic`",
+        )),
+    }
+}
+
+/// Named shapes of the scene file, built on first use like the textures so that they can
+/// be instances or media of other shapes.
+struct Shapes<'a> {
+    descs: HashMap<String, ObjectDesc>,
+    built: HashMap<String, ObjectArc>,
+    /// Shapes whose construction is in progress.
+    visiting: Vec<String>,
+    materials: &'a HashMap<String, Material>,
+    /// Identifiers of the materials for the ID passes.
+    material_ids: HashMap<String, u32>,
+    directory: &'a Path,
+}
+
+impl Shapes<'_> {
+    fn named(&mut self, name: &str, location: &str) -> Result<ObjectArc, SceneError> {
+        if let Some(shape) = self.built.get(name) {
+            return Ok(shape.clone());
+        }
+        let shape_location = format!("shapes.{}", name);
+        if self.visiting.iter().any(|n| n == name) {
+            return Err(invalid(shape_location, "shape refers to itself"));
+        }
+        let desc = self
+            .descs
+            .remove(name)
+            .ok_or_else(|| invalid(location, format!("unknown shape `{}`", name)))?;
+
+        self.visiting.push(name.to_owned());
+        let shape = desc.build(&shape_location, self)?;
+        self.visiting.pop();
+
+        self.built.insert(name.to_owned(), shape.clone());
+        Ok(shape)
+    }
+
+    fn material(&self, location: &str, name: &str) -> Result<Material, SceneError> {
+        find_material(location, self.materials, name)
+    }
+
+    /// Loads a voxel grid, whose format is chosen by the extension of the file.
+    fn grid(
+        &self,
+        location: &str,
+        file: &Path,
+        dimensions: Option<[usize; 3]>,
+    ) -> Result<DensityGrid, SceneError> {
+        let path = self.directory.join(file);
+        let npy = path.extension().is_some_and(|e| e == "npy");
+        match (npy, dimensions) {
+            (true, None) => DensityGrid::load_npy(&path),
+            (false, Some(dimensions)) => DensityGrid::load_raw(&path, dimensions),
+            (true, Some(_)) => {
+                return Err(invalid(
+                    location,
+                    ".npy files contain their dimensions, they must not be given",
+                ))
+            }
+            (false, None) => {
+                return Err(invalid(
+                    location,
+                    "the dimensions of raw grid files must be given",
+                ))
+            }
+        }
+        .map_err(SceneError::Grid)
+    }
+}
+
+impl LightDesc {
+    fn build(self, location: &str) -> Result<Arc<dyn Light + Send + Sync>, SceneError> {
+        Ok(match self {
+            LightDesc::Point {
+                position,
+                intensity,
+            } => Arc::new(PointLight::new(
+                point(position),
+                color(location, intensity)?,
+            )),
+            LightDesc::Directional {
+                direction,
+                irradiance,
+            } => {
+                if vector(direction).length() == 0. {
+                    return Err(invalid(location, "light direction must not be zero"));
+                }
+                Arc::new(DirectionalLight::new(
+                    vector(direction).into(),
+                    color(location, irradiance)?,
+                ))
+            }
+        })
+    }
+}
+
+impl ObjectDesc {
+    /// Light sampling the object if it is an emitting sphere or quad. Invalid objects are
+    /// reported when they are built.
+    fn light(&self, materials: &HashMap<String, Material>) -> Option<Arc<dyn Light + Send + Sync>> {
+        let emission = |name: &String| match materials.get(name) {
+            Some(&Material::DiffuseLight(emission)) => Some(emission),
+            _ => None,
+        };
+        match self {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } if is_positive(*radius) => Some(Arc::new(SphereLight::new(
+                point(*center),
+                *radius,
+                emission(material)?,
+            ))),
+            ObjectDesc::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } if vector(*u).cross(&vector(*v)).length() > 0. => Some(Arc::new(QuadLight::new(
+                point(*corner),
+                vector(*u),
+                vector(*v),
+                emission(material)?,
+            ))),
+            _ => None,
+        }
+    }
+
+    /// Name of the material of the object, if it has a single one.
+    fn material(&self) -> Option<&str> {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::MovingSphere { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Quad { material, .. }
+            | ObjectDesc::Box { material, .. }
+            | ObjectDesc::Plane { material, .. }
+            | ObjectDesc::ConstantMedium { material, .. } => Some(material),
+            ObjectDesc::Mesh { .. }
+            | ObjectDesc::VoxelGrid { .. }
+            | ObjectDesc::Instance { .. } => None,
+        }
+    }
+
+    fn build(self, location: &str, shapes: &mut Shapes) -> Result<ObjectArc, SceneError> {
+        let material_id = self
+            .material()
+            .and_then(|name| shapes.material_ids.get(name).copied());
+        let object: ObjectArc = match self {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                if !is_positive(radius) {
+                    return Err(invalid(
+                        location,
+                        format!("sphere radius must be positive, found {}", radius),
+                    ));
+                }
+                let material = shapes.material(location, &material)?;
+                Arc::new(Sphere::new(point(center), radius, material))
+            }
+            ObjectDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                if !is_positive(radius) {
+                    return Err(invalid(
+                        location,
+                        format!("sphere radius must be positive, found {}", radius),
+                    ));
+                }
+                check_times(location, time0, time1)?;
+                let material = shapes.material(location, &material)?;
+                Arc::new(MovingSphere::new(
+                    point(center0),
+                    point(center1),
+                    time0,
+                    time1,
+                    radius,
+                    material,
+                ))
+            }
+            ObjectDesc::Triangle { vertices, material } => {
+                let [a, b, c] = vertices;
+                if (point(b) - point(a)).cross(&(point(c) - point(a))).length() == 0. {
+                    return Err(invalid(location, "triangle is degenerate"));
+                }
+                let material = shapes.material(location, &material)?;
+                Arc::new(Triangle::new(point(a), point(b), point(c), material))
+            }
+            ObjectDesc::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                if vector(u).cross(&vector(v)).length() == 0. {
+                    return Err(invalid(location, "quad edges must not be parallel"));
+                }
+                let material = shapes.material(location, &material)?;
+                Arc::new(Quad::new(point(corner), vector(u), vector(v), material))
+            }
+            ObjectDesc::Box { min, max, material } => {
+                if (0..3).any(|i| min[i] >= max[i]) {
+                    return Err(invalid(
+                        location,
+                        format!("box minimum {:?} must be below its maximum {:?}", min, max),
+                    ));
+                }
+                let material = shapes.material(location, &material)?;
+                Arc::new(BoxShape::new(point(min), point(max), material))
+            }
+            ObjectDesc::Plane {
+                point: p,
+                normal,
+                material,
+            } => {
+                if vector(normal).length() == 0. {
+                    return Err(invalid(location, "plane normal must not be zero"));
+                }
+                let material = shapes.material(location, &material)?;
+                Arc::new(Plane::new(point(p), vector(normal).into(), material))
+            }
+            ObjectDesc::Mesh { file } => {
+                Arc::new(obj::load(&shapes.directory.join(file)).map_err(SceneError::Mesh)?)
+            }
+            ObjectDesc::VoxelGrid {
+                file,
+                dimensions,
+                min,
+                max,
+                density_scale,
+                albedo,
+                emission,
+                emission_file,
+            } => {
+                if (0..3).any(|i| min[i] >= max[i]) {
+                    return Err(invalid(
+                        location,
+                        format!("grid minimum {:?} must be below its maximum {:?}", min, max),
+                    ));
+                }
+                if !is_positive(density_scale) {
+                    return Err(invalid(
+                        location,
+                        format!("density scale must be positive, found {}", density_scale),
+                    ));
+                }
+                let density = Arc::new(shapes.grid(location, &file, dimensions)?);
+                let mut volume = VoxelVolume::new(
+                    density.clone(),
+                    Aabb::new(point(min), point(max)),
+                    density_scale,
+                    color(location, albedo)?.into(),
+                );
+                if let Some(emission) = emission {
+                    let grid = match emission_file {
+                        Some(file) => Arc::new(shapes.grid(location, &file, dimensions)?),
+                        None => density,
+                    };
+                    volume = volume.emission(grid, color(location, emission)?);
+                } else if emission_file.is_some() {
+                    return Err(invalid(location, "emission_file needs an emission color"));
+                }
+                Arc::new(volume)
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                density,
+                material,
+            } => {
+                let boundary = shapes.named(&boundary, location)?;
+                let material = shapes.material(location, &material)?;
+                Arc::new(ConstantMedium::new(
+                    boundary,
+                    positive_density(location, density)?,
+                    phase_function(location, material)?,
+                ))
+            }
+            ObjectDesc::Instance {
+                shape,
+                scale,
+                rotate,
+                translate,
+                motion,
+            } => {
+                let shape = shapes.named(&shape, location)?;
+                let start = Placement {
+                    scale: vector(scale),
+                    rotation: vector(rotate),
+                    translation: vector(translate),
+                };
+                let transform = start.transform().ok_or_else(|| {
+                    invalid(
+                        location,
+                        format!("scale factors must not be zero, found {:?}", scale),
+                    )
+                })?;
+                match motion {
+                    None => Arc::new(Instance::new(shape, transform)),
+                    Some(motion) => {
+                        check_times(location, motion.time0, motion.time1)?;
+                        let end = Placement {
+                            scale: motion.scale.map_or(start.scale, vector),
+                            rotation: motion.rotate.map_or(start.rotation, vector),
+                            translation: motion.translate.map_or(start.translation, vector),
+                        };
+                        Arc::new(
+                            Instance::moving(shape, start, end, motion.time0, motion.time1)
+                                .ok_or_else(|| {
+                                    invalid(
+                                        location,
+                                        "scale factors of a moving instance must not \
+                                         become zero",
+                                    )
+                                })?,
+                        )
+                    }
+                }
+            }
+        };
+        Ok(match material_id {
+            Some(id) => Arc::new(Tagged::new(object, None, Some(id))),
+            None => object,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::emitted;
+    use crate::ray::Ray;
+
+    const SCENE: &str = r#"
+        [render]
+        width = 300
+        samples_per_pixel = 10
+
+        [camera]
+        look_from = [13, 2, 3]
+        look_at = [0, 0, 0]
+        vertical_fov = 20
+        aspect_ratio = 1.5
+
+        [materials.ground]
+        type = "lambertian"
+        color = [0.5, 0.5, 0.5]
+
+        [materials.glass]
+        type = "dielectric"
+        refraction_index = 1.5
+
+        [[objects]]
+        type = "sphere"
+        center = [0, -1000, 0]
+        radius = 1000
+        material = "ground"
+
+        [[objects]]
+        type = "sphere"
+        center = [0, 1, 0]
+        radius = 1
+        material = "glass"
+    "#;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse(source, Path::new(""))
+    }
+
+    fn invalid_location(source: &str) -> String {
+        match parse(source) {
+            Err(SceneError::Invalid { location, .. }) => location,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("invalid scene was accepted"),
+        }
+    }
+
+    #[test]
+    fn loads_scene() {
+        let scene = parse(SCENE).unwrap();
+        assert_eq!(
+            scene.settings,
+            RenderSettings {
+                image_width: 300,
+                image_height: 200,
+                samples_per_pixel: 10,
+                max_depth: 50,
+                spectral: false,
+                tone_mapping: ToneMapping::default(),
+                aovs: AovSet::default(),
+                denoise: false,
+                adaptive: None,
+            }
+        );
+
+        let source = SCENE.replace(
+            "samples_per_pixel = 10",
+            "samples_per_pixel = 10\nexposure = -1.5\ntone_map = \"agx\"\ndither = true",
+        );
+        let expected = ToneMapping {
+            operator: Operator::Agx,
+            exposure: -1.5,
+            dither: true,
+        };
+        assert_eq!(parse(&source).unwrap().settings.tone_mapping, expected);
+        let unknown = source.replace("agx", "filmic");
+        assert_eq!(invalid_location(&unknown), "render.tone_map");
+
+        let source = SCENE.replace(
+            "samples_per_pixel = 10",
+            "samples_per_pixel = 10\nadaptive = { threshold = 0.01 }",
+        );
+        let expected = AdaptiveSampling {
+            min_samples: 16,
+            threshold: 0.01,
+        };
+        assert_eq!(parse(&source).unwrap().settings.adaptive, Some(expected));
+        let negative = source.replace("0.01", "-0.01");
+        assert_eq!(invalid_location(&negative), "render.adaptive.threshold");
+        let none = source.replace("}", ", min_samples = 0 }");
+        assert_eq!(invalid_location(&none), "render.adaptive.min_samples");
+        let many = source.replace("}", ", min_samples = 4294967312 }");
+        assert_eq!(invalid_location(&many), "render.adaptive.min_samples");
+        let many = SCENE.replace("samples_per_pixel = 10", "samples_per_pixel = 4294967306");
+        assert_eq!(invalid_location(&many), "render.samples_per_pixel");
+        let deep = SCENE.replace("samples_per_pixel = 10", "max_depth = 2147483648");
+        assert_eq!(invalid_location(&deep), "render.max_depth");
+    }
+
+    #[test]
+    fn numbers_objects_and_materials() {
+        let source = SCENE.replace(
+            "samples_per_pixel = 10",
+            "samples_per_pixel = 10\naovs = [\"object_id\", \"depth\"]",
+        );
+        let scene = parse(&source).unwrap();
+        let aovs: Vec<Aov> = scene.settings.aovs.iter().collect();
+        assert_eq!(aovs, [Aov::Depth, Aov::ObjectId]);
+        // Materials are numbered alphabetically, so glass comes before the ground.
+        let ids = |x: f64| {
+            let down = FreeVec3::new(0., -1., 0.).into();
+            let ray = Ray::new(&Point::new(x, 5., 0.), &down, 0.);
+            let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            (hit.object_id, hit.material_id)
+        };
+        assert_eq!(ids(0.), (2, 1));
+        assert_eq!(ids(5.), (1, 2));
+
+        let unknown = source.replace("depth", "beauty");
+        assert_eq!(invalid_location(&unknown), "render.aovs");
+        assert_eq!(scene.world.objects.len(), 2);
+    }
+
+    #[test]
+    fn loads_example_scenes() {
+        for entry in fs::read_dir("scenes").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "toml") {
+                if let Err(error) = Scene::load(&path) {
+                    panic!("{}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_material() {
+        let source = SCENE.replace("material = \"glass\"", "material = \"steel\"");
+        assert_eq!(invalid_location(&source), "objects[1]");
+    }
+
+    #[test]
+    fn rejects_negative_radius() {
+        let source = SCENE.replace("radius = 1\n", "radius = -1\n");
+        assert_eq!(invalid_location(&source), "objects[1]");
+    }
+
+    #[test]
+    fn rejects_zero_aspect_ratio() {
+        let source = SCENE.replace("aspect_ratio = 1.5", "aspect_ratio = 0");
+        assert_eq!(invalid_location(&source), "camera.aspect_ratio");
+        let source = source.replace("width = 300", "width = 300\nheight = 200");
+        assert_eq!(invalid_location(&source), "camera.aspect_ratio");
+    }
+
+    #[test]
+    fn collects_lights() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [materials.lamp]
+            type = "diffuse_light"
+            emission = [4, 4, 4]
+
+            [[objects]]
+            type = "quad"
+            corner = [0, 5, 0]
+            u = [0, 0, 1]
+            v = [1, 0, 0]
+            material = "lamp"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 5, 3]
+            radius = 0.5
+            material = "lamp"
+
+            [[lights]]
+            type = "point"
+            position = [0, 3, 0]
+            intensity = [1, 1, 1]
+
+            [[lights]]
+            type = "directional"
+            direction = [0, -1, 0]
+            irradiance = [2, 2, 2]
+            "#
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        assert_eq!(scene.lights.lights.len(), 4);
+        assert!(parse(SCENE).unwrap().lights.is_empty());
+
+        let source = source.replace("direction = [0, -1, 0]", "direction = [0, 0, 0]");
+        assert_eq!(invalid_location(&source), "lights[1]");
+    }
+
+    #[test]
+    fn builds_backgrounds_and_lights() {
+        // The default is the white-to-blue gradient.
+        match parse(SCENE).unwrap().background {
+            Background::Gradient { bottom, top } => {
+                assert_eq!(bottom, Color::new(1., 1., 1.));
+                assert_eq!(top, Color::new(0.5, 0.7, 1.));
+            }
+            _ => panic!("expected a gradient"),
+        }
+        let with = |extra: &str| format!("{}{}", SCENE, extra);
+        let solid = with(
+            r#"
+            [background]
+            type = "solid"
+            color = [0.1, 0.2, 0.3]
+            "#,
+        );
+        match parse(&solid).unwrap().background {
+            Background::Solid(color) => assert_eq!(color, Color::new(0.1, 0.2, 0.3)),
+            _ => panic!("expected a solid color"),
+        }
+        let gradient = with(
+            r#"
+            [background]
+            type = "gradient"
+            bottom = [0, 0, 0]
+            top = [1, 2, 3]
+            "#,
+        );
+        match parse(&gradient).unwrap().background {
+            Background::Gradient { bottom, top } => {
+                assert_eq!(bottom, Color::new(0., 0., 0.));
+                assert_eq!(top, Color::new(1., 2., 3.));
+            }
+            _ => panic!("expected a gradient"),
+        }
+        let black = with("[background]\ntype = \"black\"\n");
+        assert!(matches!(
+            parse(&black).unwrap().background,
+            Background::Black
+        ));
+        let negative = solid.replace("0.2", "-0.2");
+        assert_eq!(invalid_location(&negative), "background");
+        let negative = gradient.replace("[1, 2, 3]", "[1, -2, 3]");
+        assert_eq!(invalid_location(&negative), "background");
+
+        let lamp = with(
+            r#"
+            [materials.lamp]
+            type = "diffuse_light"
+            emission = [4, 3, 2]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 5, 0]
+            radius = 0.5
+            material = "lamp"
+            "#,
+        );
+        let scene = parse(&lamp).unwrap();
+        let down = FreeVec3::new(0., -1., 0.).into();
+        let ray = Ray::new(&Point::new(0., 10., 0.), &down, 0.);
+        let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(emitted(&hit), Color::new(4., 3., 2.));
+        let negative = lamp.replace("[4, 3, 2]", "[4, -3, 2]");
+        assert_eq!(invalid_location(&negative), "materials.lamp");
+    }
+
+    #[test]
+    fn samples_environment_map() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [background]
+            type = "environment"
+            file = "scenes/sky.hdr"
+            rotation = 90
+            intensity = 2
+            "#
+        );
+        let scene = parse(&source).unwrap();
+        assert!(matches!(scene.background, Background::Environment(_)));
+        assert_eq!(scene.lights.lights.len(), 1);
+
+        let negative = source.replace("intensity = 2", "intensity = -2");
+        assert_eq!(invalid_location(&negative), "background");
+        let missing = source.replace("sky.hdr", "missing.hdr");
+        assert_eq!(invalid_location(&missing), "background");
+    }
+
+    #[test]
+    fn builds_physical_sky() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [background]
+            type = "sky"
+            sun_elevation = 30
+            sun_azimuth = 120
+            "#
+        );
+        let mut scene = parse(&source).unwrap();
+        match &scene.background {
+            Background::Sky(sky) => {
+                assert_eq!((sky.elevation(), sky.azimuth()), (30., 120.));
+                assert_eq!((sky.turbidity(), sky.intensity()), (3., 1.));
+            }
+            _ => panic!("expected a sky"),
+        }
+        assert_eq!(scene.lights.lights.len(), 1);
+        // Replacing the background replaces its light.
+        scene.set_background(Background::Sky(Arc::new(PhysicalSky::new(60., 0., 2., 1.))));
+        assert_eq!(scene.lights.lights.len(), 1);
+        scene.set_background(Background::Black);
+        assert!(scene.lights.is_empty());
+
+        let low = source.replace("sun_elevation = 30", "sun_elevation = -5");
+        assert_eq!(invalid_location(&low), "background");
+        let hazy = source.replace("sun_azimuth = 120", "turbidity = 20");
+        assert_eq!(invalid_location(&hazy), "background");
+    }
+
+    #[test]
+    fn builds_media() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [atmosphere]
+            density = 0.01
+            radius = 100
+
+            [materials.smoke]
+            type = "isotropic"
+            color = [0.2, 0.2, 0.2]
+
+            [shapes.cloud]
+            type = "box"
+            min = [0, 0, 0]
+            max = [1, 1, 1]
+            material = "smoke"
+
+            [[objects]]
+            type = "constant_medium"
+            boundary = "cloud"
+            density = 2
+            material = "smoke"
+            "#
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        let glass = source.replace(
+            "density = 2\n            material = \"smoke\"",
+            "density = 2\n            material = \"glass\"",
+        );
+        assert_eq!(invalid_location(&glass), "objects[2]");
+        let negative = source.replace("density = 0.01", "density = -0.01");
+        assert_eq!(invalid_location(&negative), "atmosphere");
+        let inside_out = source.replace("radius = 100", "radius = -100");
+        assert_eq!(invalid_location(&inside_out), "atmosphere.radius");
+    }
+
+    #[test]
+    fn checks_voxel_grid_dimensions() {
+        let grid = |file: &str, dimensions: &str| {
+            format!(
+                "{}[[objects]]\ntype = \"voxel_grid\"\nfile = \"{}\"\n{}\n\
+                 min = [0, 0, 0]\nmax = [1, 1, 1]",
+                SCENE, file, dimensions
+            )
+        };
+        assert_eq!(invalid_location(&grid("smoke.raw", "")), "objects[2]");
+        let npy = grid("smoke.npy", "dimensions = [2, 2, 2]");
+        assert_eq!(invalid_location(&npy), "objects[2]");
+        assert!(matches!(
+            parse(&grid("missing.raw", "dimensions = [2, 2, 2]")),
+            Err(SceneError::Grid(GridError::Io { .. }))
+        ));
+    }
+
+    #[test]
+    fn rejects_reversed_shutter() {
+        let source = SCENE.replace(
+            "aspect_ratio = 1.5",
+            "aspect_ratio = 1.5\nshutter_open = 1\nshutter_close = 0.5",
+        );
+        assert_eq!(invalid_location(&source), "camera.shutter_close");
+    }
+
+    #[test]
+    fn rejects_degenerate_shapes() {
+        let quad = "[[objects]]\ntype = \"quad\"\ncorner = [0, 0, 0]\n\
+                    u = [1, 0, 0]\nv = [2, 0, 0]\nmaterial = \"ground\"";
+        assert_eq!(
+            invalid_location(&format!("{}{}", SCENE, quad)),
+            "objects[2]"
+        );
+        let shape = "[[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\n\
+                     max = [1, 0, 1]\nmaterial = \"ground\"";
+        assert_eq!(
+            invalid_location(&format!("{}{}", SCENE, shape)),
+            "objects[2]"
+        );
+    }
+
+    #[test]
+    fn instantiates_shapes() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [shapes.ball]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "glass"
+
+            [[objects]]
+            type = "instance"
+            shape = "ball"
+            translate = [0, 5, 0]
+
+            [[objects]]
+            type = "instance"
+            shape = "ball"
+            scale = [2, 2, 2]
+            "#
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        let bbox = scene.world.objects[2].bounding_box().unwrap();
+        assert!((bbox.min - Point::new(-1., 4., -1.)).length() < 1e-12);
+
+        let unknown = source.replace("shape = \"ball\"", "shape = \"cube\"");
+        assert_eq!(invalid_location(&unknown), "objects[2]");
+        let flat = source.replace("scale = [2, 2, 2]", "scale = [2, 0, 2]");
+        assert_eq!(invalid_location(&flat), "objects[3]");
+        let cycle = "[shapes.loop]\ntype = \"instance\"\nshape = \"loop\"";
+        assert_eq!(
+            invalid_location(&format!("{}{}", source, cycle)),
+            "shapes.loop"
+        );
+    }
+
+    #[test]
+    fn rejects_movements_ending_before_they_start() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+            [shapes.ball]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "glass"
+
+            [[objects]]
+            type = "moving_sphere"
+            center0 = [0, 0, 0]
+            center1 = [0, 1, 0]
+            time0 = 0.5
+            radius = 1
+            material = "glass"
+
+            [[objects]]
+            type = "instance"
+            shape = "ball"
+
+            [objects.motion]
+            translate = [1, 0, 0]
+            time1 = 0.5
+            "#
+        );
+        assert_eq!(parse(&source).unwrap().world.objects.len(), 4);
+        let moving_sphere = source.replace("time0 = 0.5", "time0 = 2");
+        assert_eq!(invalid_location(&moving_sphere), "objects[2]");
+        let instance = source.replace("time1 = 0.5", "time1 = -1");
+        assert_eq!(invalid_location(&instance), "objects[3]");
+    }
+
+    #[test]
+    fn rejects_invalid_material_parameters() {
+        let source = SCENE.replace("refraction_index = 1.5", "refraction_index = -1");
+        assert_eq!(invalid_location(&source), "materials.glass");
+        let source = SCENE.replace("color = [0.5, 0.5, 0.5]", "color = [0.5, -0.5, 0.5]");
+        assert_eq!(invalid_location(&source), "materials.ground");
+    }
+
+    #[test]
+    fn builds_principled_material() {
+        let source = SCENE.replace(
+            "type = \"lambertian\"\n        color = [0.5, 0.5, 0.5]",
+            r#"type = "principled"
+            base_color = "checker"
+            metallic = 1
+            roughness = "noise"
+
+            [textures.checker]
+            type = "checker"
+            even = [0.9, 0.9, 0.9]
+            odd = [0.1, 0.1, 0.1]
+
+            [textures.noise]
+            type = "noise""#,
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+
+        let out_of_range = source.replace("metallic = 1", "metallic = 2");
+        assert_eq!(invalid_location(&out_of_range), "materials.ground.metallic");
+        let unknown = source.replace("roughness = \"noise\"", "roughness = \"missing\"");
+        assert_eq!(invalid_location(&unknown), "materials.ground.roughness");
+    }
+
+    #[test]
+    fn builds_microfacet_materials() {
+        let glass = r#"type = "rough_dielectric"
+        refraction_index = 1.5
+        roughness = 0.3"#;
+        let source = SCENE.replace(
+            "type = \"lambertian\"\n        color = [0.5, 0.5, 0.5]",
+            "type = \"conductor\"\n        metal = \"gold\"\n        roughness = 0.2",
+        );
+        let source = source.replace(
+            "type = \"dielectric\"\n        refraction_index = 1.5",
+            glass,
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+
+        let rough = source.replace("roughness = 0.3", "roughness = 1.5");
+        assert_eq!(invalid_location(&rough), "materials.glass");
+        let unknown = source.replace("\"gold\"", "\"brass\"");
+        assert_eq!(invalid_location(&unknown), "materials.ground");
+        let both = source.replace(
+            "metal = \"gold\"",
+            "metal = \"gold\"\n        k = [1, 1, 1]",
+        );
+        assert_eq!(invalid_location(&both), "materials.ground");
+        let custom = source.replace(
+            "metal = \"gold\"",
+            "eta = [0.2, 0.9, 1.1]\n        k = [3.9, 2.5, 2.1]",
+        );
+        assert!(parse(&custom).is_ok());
+    }
+
+    #[test]
+    fn builds_nested_dielectrics() {
+        let source = SCENE.replace(
+            "refraction_index = 1.5",
+            "refraction_index = 1.5\n        absorption = [0.1, 0.2, 0]\n        priority = 2",
+        );
+        assert!(parse(&source).is_ok());
+        let negative = source.replace("[0.1, 0.2, 0]", "[0.1, -0.2, 0]");
+        assert_eq!(invalid_location(&negative), "materials.glass");
+
+        let desc: MaterialDesc = toml::from_str(
+            "type = \"dielectric\"\nrefraction_index = 1.33\nabsorption = [0.5, 0.1, 0]",
+        )
+        .unwrap();
+        let mut textures = Textures {
+            descs: HashMap::new(),
+            built: HashMap::new(),
+            directory: Path::new("."),
+        };
+        let material = desc.build("materials.water", &mut textures).unwrap();
+        assert!(matches!(
+            material,
+            Material::Dielectric(i) if *i.absorption.g() == 0.1 && i.priority == 0
+        ));
+    }
+
+    #[test]
+    fn builds_dispersive_dielectrics() {
+        let source = SCENE.replace(
+            "samples_per_pixel = 10",
+            "samples_per_pixel = 10\nspectral = true",
+        );
+        let cauchy = source.replace("refraction_index = 1.5", "cauchy = [1.5, 0.005]");
+        assert!(parse(&cauchy).unwrap().settings.spectral);
+        let sellmeier = source.replace(
+            "refraction_index = 1.5",
+            "sellmeier = { b = [1.04, 0.23, 1.01], c = [0.006, 0.02, 103.6] }",
+        );
+        assert!(parse(&sellmeier).is_ok());
+
+        let both = source.replace(
+            "refraction_index = 1.5",
+            "refraction_index = 1.5\ncauchy = [1.5, 0]",
+        );
+        assert_eq!(invalid_location(&both), "materials.glass");
+        let negative = source.replace("refraction_index = 1.5", "cauchy = [1.5, -0.5]");
+        assert_eq!(invalid_location(&negative), "materials.glass");
+    }
+
+    #[test]
+    fn resolves_textures() {
+        let source = SCENE.replace(
+            "color = [0.5, 0.5, 0.5]",
+            "color = \"checker\"\n[textures.checker]\ntype = \"checker\"\n\
+             even = \"white\"\nodd = [0.1, 0.1, 0.1]\n\
+             [textures.white]\ntype = \"solid\"\ncolor = [1, 1, 1]",
+        );
+        assert!(parse(&source).is_ok());
+
+        let missing = source.replace("even = \"white\"", "even = \"black\"");
+        assert_eq!(invalid_location(&missing), "textures.checker");
+        let cyclic = source.replace("even = \"white\"", "even = \"checker\"");
+        assert_eq!(invalid_location(&cyclic), "textures.checker");
+        let unknown = SCENE.replace("color = [0.5, 0.5, 0.5]", "color = \"wood\"");
+        assert_eq!(invalid_location(&unknown), "materials.ground");
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let source = SCENE.replace("radius = 1000", "radius = \"big\"");
+        assert!(matches!(parse(&source), Err(SceneError::Syntax(_))));
+        let source = SCENE.replace("radius = 1000", "radius = 1000\ncolour = 1");
+        assert!(matches!(parse(&source), Err(SceneError::Syntax(_))));
+    }
+}
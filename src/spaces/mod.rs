@@ -1,8 +1,10 @@
 /// [TODO] Find a better name than `spaces` for this module
 pub mod point;
+pub mod transform;
 pub mod vec3;
 
 pub use point::Point;
+pub use transform::{Placement, Transform};
 pub use vec3::{FreeVec3, UnitVec3, Vec3};
 
 #[cfg(test)]
@@ -0,0 +1,295 @@
+use super::{FreeVec3, Point, UnitVec3, Vec3};
+use std::ops::Mul;
+
+/// Row-major 4x4 matrix acting on column vectors in homogeneous coordinates.
+pub type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Invertible affine transformation, stored together with its inverse.
+///
+/// Products compose like matrices: `a * b` applies `b` first and `a` second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    /// Transformation given by its matrix, or `None` if the matrix is singular or not
+    /// affine, i.e. its last row differs from `[0, 0, 0, 1]`.
+    pub fn new(matrix: Matrix) -> Option<Self> {
+        if matrix[3] != [0., 0., 0., 1.] {
+            return None;
+        }
+        let m = |i: usize, j: usize| matrix[i][j];
+        // Cofactors of the linear part.
+        let c = [
+            [
+                m(1, 1) * m(2, 2) - m(1, 2) * m(2, 1),
+                m(0, 2) * m(2, 1) - m(0, 1) * m(2, 2),
+                m(0, 1) * m(1, 2) - m(0, 2) * m(1, 1),
+            ],
+            [
+                m(1, 2) * m(2, 0) - m(1, 0) * m(2, 2),
+                m(0, 0) * m(2, 2) - m(0, 2) * m(2, 0),
+                m(0, 2) * m(1, 0) - m(0, 0) * m(1, 2),
+            ],
+            [
+                m(1, 0) * m(2, 1) - m(1, 1) * m(2, 0),
+                m(0, 1) * m(2, 0) - m(0, 0) * m(2, 1),
+                m(0, 0) * m(1, 1) - m(0, 1) * m(1, 0),
+            ],
+        ];
+        let determinant = m(0, 0) * c[0][0] + m(0, 1) * c[1][0] + m(0, 2) * c[2][0];
+        if determinant == 0. || !determinant.is_finite() {
+            return None;
+        }
+
+        let mut inverse = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                inverse[i][j] = c[i][j] / determinant;
+            }
+        }
+        for row in inverse.iter_mut().take(3) {
+            row[3] = -(0..3).map(|j| row[j] * m(j, 3)).sum::<f64>();
+        }
+        Some(Transform { matrix, inverse })
+    }
+
+    pub fn translation(offset: FreeVec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (i, x) in [offset.x(), offset.y(), offset.z()].iter().enumerate() {
+            matrix[i][3] = *x;
+            inverse[i][3] = -x;
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// Scaling along the coordinate axes, `None` if one of the factors is zero.
+    pub fn scaling(x: f64, y: f64, z: f64) -> Option<Self> {
+        if x == 0. || y == 0. || z == 0. {
+            return None;
+        }
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (i, s) in [x, y, z].iter().enumerate() {
+            matrix[i][i] = *s;
+            inverse[i][i] = 1. / s;
+        }
+        Some(Transform { matrix, inverse })
+    }
+
+    /// Counterclockwise rotation by `degrees` around `axis` when looking against it.
+    pub fn rotation(axis: UnitVec3, degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (x, y, z) = (axis.x(), axis.y(), axis.z());
+        let t = 1. - cos;
+        let matrix = [
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ];
+        // The inverse of a rotation is its transpose.
+        Transform {
+            matrix,
+            inverse: transpose(&matrix),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        let m = &self.matrix;
+        let row = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        Point::new(row(0), row(1), row(2))
+    }
+
+    pub fn vector<T: Vec3>(&self, v: &T) -> FreeVec3 {
+        linear(&self.matrix, v)
+    }
+
+    /// Transforms a surface normal, which has to be done with the inverse transpose so
+    /// that it stays perpendicular to the transformed surface.
+    pub fn normal<T: Vec3>(&self, n: &T) -> UnitVec3 {
+        linear(&transpose(&self.inverse), n).into()
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Transform) -> Self::Output {
+        Transform {
+            matrix: product(&self.matrix, &rhs.matrix),
+            inverse: product(&rhs.inverse, &self.inverse),
+        }
+    }
+}
+
+/// Transformation made of a scaling, rotations around the x, y and z axes (in degrees)
+/// and a translation, applied in this order.
+///
+/// Unlike matrices, placements can be interpolated, which is used for objects moving
+/// while the shutter is open.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub scale: FreeVec3,
+    pub rotation: FreeVec3,
+    pub translation: FreeVec3,
+}
+
+impl Placement {
+    /// The transformation, or `None` if one of the scale factors is zero.
+    pub fn transform(&self) -> Option<Transform> {
+        let scaling = Transform::scaling(self.scale.x(), self.scale.y(), self.scale.z())?;
+        let axis = |x, y, z| FreeVec3::new(x, y, z).into();
+        Some(
+            Transform::translation(self.translation)
+                * Transform::rotation(axis(0., 0., 1.), self.rotation.z())
+                * Transform::rotation(axis(0., 1., 0.), self.rotation.y())
+                * Transform::rotation(axis(1., 0., 0.), self.rotation.x())
+                * scaling,
+        )
+    }
+
+    /// Linear interpolation of all components, `fraction` 0 gives `self`.
+    pub fn lerp(&self, other: &Placement, fraction: f64) -> Placement {
+        let mix = |a: FreeVec3, b: FreeVec3| a + (b - a) * fraction;
+        Placement {
+            scale: mix(self.scale, other.scale),
+            rotation: mix(self.rotation, other.rotation),
+            translation: mix(self.translation, other.translation),
+        }
+    }
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            scale: FreeVec3::new(1., 1., 1.),
+            rotation: FreeVec3::new(0., 0., 0.),
+            translation: FreeVec3::new(0., 0., 0.),
+        }
+    }
+}
+
+fn linear<T: Vec3>(m: &Matrix, v: &T) -> FreeVec3 {
+    let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+    FreeVec3::new(row(0), row(1), row(2))
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut t = *m;
+    for (i, row) in t.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = m[j][i];
+        }
+    }
+    t
+}
+
+fn product(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut p = [[0.; 4]; 4];
+    for (i, row) in p.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Matrix, b: &Matrix) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a[i][j] - b[i][j]).abs() < 1e-12, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_of_composition() {
+        let t = Transform::translation(FreeVec3::new(1., -2., 3.))
+            * Transform::rotation(FreeVec3::new(1., 2., 2.).into(), 37.)
+            * Transform::scaling(2., 0.5, -3.).unwrap();
+        assert_close(&product(&t.matrix, &t.inverse), &IDENTITY);
+
+        let general = Transform::new(t.matrix).unwrap();
+        assert_close(&general.inverse, &t.inverse);
+        assert!(
+            Transform::new([[1., 2., 3., 0.], [2., 4., 6., 0.], IDENTITY[2], IDENTITY[3]])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn transforms_points_vectors_and_normals() {
+        let t = Transform::translation(FreeVec3::new(1., 0., 0.))
+            * Transform::rotation(FreeVec3::new(0., 0., 1.).into(), 90.);
+        let p = t.point(&Point::new(1., 0., 5.));
+        assert!((p - Point::new(1., 1., 5.)).length() < 1e-12);
+        let v = t.vector(&FreeVec3::new(1., 0., 0.));
+        assert!((v - FreeVec3::new(0., 1., 0.)).length() < 1e-12);
+
+        // A plane sheared along x keeps normals perpendicular to its tangents.
+        let shear = Transform::new([
+            [1., 1., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ])
+        .unwrap();
+        let tangent = shear.vector(&FreeVec3::new(1., 1., 0.));
+        let normal = shear.normal(&FreeVec3::new(1., -1., 0.));
+        assert!(tangent.dot(&normal).abs() < 1e-12);
+    }
+}
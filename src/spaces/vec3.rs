@@ -1,4 +1,4 @@
-use rand::Rng;
+use crate::random;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 pub trait Vec3 {
@@ -130,9 +130,8 @@ impl FreeVec3 {
     }
 
     pub fn random_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
         loop {
-            let p = FreeVec3::new(rng.gen_range(-1., 1.), rng.gen_range(-1., 1.), 0.);
+            let p = FreeVec3::new(random::gen_range(-1., 1.), random::gen_range(-1., 1.), 0.);
             if p.length_squared() < 1. {
                 return p;
             }
@@ -164,6 +163,19 @@ pub fn refraction<T: Vec3 + Into<FreeVec3>, S: Vec3 + Into<FreeVec3>>(
     r_out_perp + r_out_parallel
 }
 
+/// Two unit vectors completing `w` to a right-handed orthonormal basis.
+pub fn orthonormal_basis(w: &UnitVec3) -> (FreeVec3, FreeVec3) {
+    // Building the basis without branches of Duff et al., "Building an Orthonormal Basis,
+    // Revisited".
+    let sign = 1f64.copysign(w.z);
+    let a = -1. / (sign + w.z);
+    let b = w.x * w.y * a;
+    (
+        FreeVec3::new(1. + sign * w.x * w.x * a, sign * b, -sign * w.x),
+        FreeVec3::new(b, sign + w.y * w.y * a, -w.y),
+    )
+}
+
 #[derive(Copy, Clone)]
 pub struct UnitVec3 {
     x: f64,
@@ -187,9 +199,8 @@ impl Vec3 for UnitVec3 {
 
 impl UnitVec3 {
     pub fn random_unit_vector() -> Self {
-        let mut rng = rand::thread_rng();
-        let a = rng.gen_range(0., 2. * std::f64::consts::PI);
-        let z: f64 = rng.gen_range(-1., 1.);
+        let a = random::gen_range(0., 2. * std::f64::consts::PI);
+        let z: f64 = random::gen_range(-1., 1.);
         let r = (1. - z * z).sqrt();
         FreeVec3::new(r * a.cos(), r * a.sin(), z).into()
     }
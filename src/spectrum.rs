@@ -0,0 +1,311 @@
+//! Spectral rendering with hero wavelengths, after Wilkie et al., "Hero Wavelength
+//! Spectral Sampling" (2014).
+//!
+//! Every path carries a few wavelengths spread over the visible range, in the channels of
+//! the colors it traces. RGB albedos are upsampled to reflectance spectra with
+//! the method of Smits, "An RGB-to-Spectrum Conversion for Reflectances" (1999), light
+//! sources emit the upsampled spectrum under the CIE illuminant D65, and the radiance
+//! found for the wavelengths is converted back to RGB with the CIE 1931 color matching
+//! functions.
+use crate::color::Color;
+use crate::random;
+use std::sync::OnceLock;
+
+/// Bounds of the sampled wavelengths in nanometres.
+pub const MIN_WAVELENGTH: f64 = 360.;
+pub const MAX_WAVELENGTH: f64 = 830.;
+/// Wavelength of the sodium D line in nanometres, at which refraction indices of
+/// materials are usually given.
+pub const SODIUM_D: f64 = 589.3;
+
+/// What the three channels of the colors traced along a path stand for.
+#[derive(Copy, Clone, Debug)]
+pub enum Channels {
+    /// Red, green and blue.
+    Rgb,
+    /// Values at the given wavelengths.
+    Spectral(Wavelengths),
+}
+
+impl Channels {
+    /// Wavelengths of the channels, the sodium D line for all of them in RGB.
+    pub fn wavelengths(&self) -> [f64; 3] {
+        match self {
+            Channels::Rgb => [SODIUM_D; 3],
+            Channels::Spectral(wavelengths) => wavelengths.0,
+        }
+    }
+
+    /// Values of the reflectance spectrum of an RGB albedo, or of any other RGB quantity
+    /// that scales light, like absorption coefficients.
+    pub fn reflectance(&self, color: Color) -> Color {
+        match self {
+            Channels::Rgb => color,
+            Channels::Spectral(wavelengths) => wavelengths.map(|w| upsample(&color, w)),
+        }
+    }
+
+    /// Values of the spectrum emitted by a light source of RGB radiance.
+    pub fn illuminant(&self, color: Color) -> Color {
+        match self {
+            Channels::Rgb => color,
+            Channels::Spectral(wavelengths) => {
+                wavelengths.map(|w| upsample(&color, w) * d65(w) / 100.)
+            }
+        }
+    }
+
+    /// Estimate of the RGB color of the radiance traced in the channels.
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        let wavelengths = match self {
+            Channels::Rgb => return radiance,
+            Channels::Spectral(wavelengths) => wavelengths.0,
+        };
+        let values = [*radiance.r(), *radiance.g(), *radiance.b()];
+        let mut xyz = [0.; 3];
+        for (&wavelength, value) in wavelengths.iter().zip(values) {
+            let weight = value / (3. * visible_pdf(wavelength));
+            for (x, m) in xyz.iter_mut().zip(color_matching(wavelength)) {
+                *x += weight * m;
+            }
+        }
+        let balance = white_balance();
+        let rgb = xyz_to_rgb(xyz);
+        Color::new(
+            rgb[0] * balance[0],
+            rgb[1] * balance[1],
+            rgb[2] * balance[2],
+        )
+    }
+}
+
+/// Hero wavelength and two more at equal distances from it in the space of the samples,
+/// wrapping around the bounds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wavelengths([f64; 3]);
+
+impl Wavelengths {
+    pub fn sample() -> Self {
+        Self::rotated(random::random())
+    }
+
+    /// Wavelengths for a hero placed at `u` in the space of the samples.
+    fn rotated(u: f64) -> Self {
+        let at = |offset: f64| sample_visible((u + offset).fract());
+        Wavelengths([at(0.), at(1. / 3.), at(2. / 3.)])
+    }
+
+    fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.0[0]), f(self.0[1]), f(self.0[2]))
+    }
+}
+
+/// Wavelength distributed roughly like the sensitivity of the eye for `u` uniform in
+/// `[0, 1)`, as proposed in Radziszewski et al., "An Improved Technique for Full Spectral
+/// Rendering" (2009), with the constants of pbrt for the range of the samples.
+fn sample_visible(u: f64) -> f64 {
+    538. - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_pdf(wavelength: f64) -> f64 {
+    0.0039398042 / (0.0072 * (wavelength - 538.)).cosh().powi(2)
+}
+
+/// Basis spectra of Smits, sampled evenly from 380 to 720 nm.
+const SMITS_WHITE: [f64; 10] = [1., 1., 0.9999, 0.9993, 0.9992, 0.9998, 1., 1., 1., 1.];
+const SMITS_CYAN: [f64; 10] = [
+    0.971, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0., 0., 0.,
+];
+const SMITS_MAGENTA: [f64; 10] = [1., 1., 0.9685, 0.2229, 0., 0.0458, 0.8369, 1., 1., 0.9959];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0., 0.1088, 0.6651, 1., 1., 0.9996, 0.9586, 0.9685, 0.984,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0., 0., 0., 0., 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [0., 0., 0.0273, 0.7937, 1., 0.9418, 0.1719, 0., 0., 0.0025];
+const SMITS_BLUE: [f64; 10] = [
+    1., 1., 0.8916, 0.3323, 0., 0., 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at `wavelength` of the smooth spectrum of Smits for an RGB color, which is
+/// white plus as little of the other basis spectra as needed.
+fn upsample(color: &Color, wavelength: f64) -> f64 {
+    let (r, g, b) = (*color.r(), *color.g(), *color.b());
+    let basis = |spectrum: &[f64; 10]| interpolate(spectrum, 380., 720., wavelength);
+    let white = basis(&SMITS_WHITE);
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        };
+        r * white + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        };
+        g * white + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        };
+        b * white + rest
+    }
+}
+
+/// Relative spectral power of the CIE standard illuminant D65 from 360 to 830 nm in steps
+/// of 10 nm.
+const D65: [f64; 48] = [
+    46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+    117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.,
+    96.3342, 95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146,
+    82.2778, 78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+];
+
+fn d65(wavelength: f64) -> f64 {
+    interpolate(&D65, MIN_WAVELENGTH, MAX_WAVELENGTH, wavelength)
+}
+
+/// Linear interpolation of samples evenly spaced from `min` to `max`, both included,
+/// clamped beyond them.
+fn interpolate(samples: &[f64], min: f64, max: f64, wavelength: f64) -> f64 {
+    let last = samples.len() - 1;
+    let x = ((wavelength - min) / (max - min) * last as f64).clamp(0., last as f64);
+    let i = (x as usize).min(last - 1);
+    let t = x - i as f64;
+    samples[i] * (1. - t) + samples[i + 1] * t
+}
+
+/// CIE 1931 color matching functions, in the multi-lobe fit of Wyman et al., "Simple
+/// Analytic Approximations to the CIE XYZ Color Matching Functions" (2013).
+fn color_matching(wavelength: f64) -> [f64; 3] {
+    let lobe = |mean: f64, left: f64, right: f64| {
+        let t = (wavelength - mean) / if wavelength < mean { left } else { right };
+        (-0.5 * t * t).exp()
+    };
+    [
+        1.056 * lobe(599.8, 37.9, 31.) + 0.362 * lobe(442., 16., 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437., 11.8, 36.) + 0.681 * lobe(459., 26., 13.8),
+    ]
+}
+
+/// Linear sRGB color of CIE XYZ coordinates, for the D65 white point.
+fn xyz_to_rgb([x, y, z]: [f64; 3]) -> [f64; 3] {
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.969266 * x + 1.8760108 * y + 0.041556 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+}
+
+/// Factors that make white light of the illuminant D65 exactly white in RGB, making up for
+/// the imprecision of the tables.
+fn white_balance() -> [f64; 3] {
+    static BALANCE: OnceLock<[f64; 3]> = OnceLock::new();
+    *BALANCE.get_or_init(|| {
+        let white = Color::new(1., 1., 1.);
+        let mut xyz = [0.; 3];
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        for i in 0..steps {
+            let wavelength = MIN_WAVELENGTH + i as f64 + 0.5;
+            let power = upsample(&white, wavelength) * d65(wavelength) / 100.;
+            for (x, m) in xyz.iter_mut().zip(color_matching(wavelength)) {
+                *x += power * m;
+            }
+        }
+        xyz_to_rgb(xyz).map(|c| 1. / c)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGB color of the spectrum of a reflectance under white light, integrated finely.
+    fn reflected(color: Color) -> Color {
+        let n = 470;
+        let channels = (0..n).map(|i| {
+            let u = (i as f64 + 0.5) / n as f64;
+            let spectral = Channels::Spectral(Wavelengths::rotated(u));
+            spectral.to_rgb(spectral.reflectance(color) * spectral.illuminant(white()))
+        });
+        let sum = channels.fold(Color::new(0., 0., 0.), |sum, c| sum + c);
+        sum * (1. / n as f64)
+    }
+
+    fn white() -> Color {
+        Color::new(1., 1., 1.)
+    }
+
+    #[test]
+    fn d65_is_the_white_point() {
+        let mut xyz = [0.; 3];
+        for i in 360..830 {
+            let wavelength = i as f64 + 0.5;
+            for (x, m) in xyz.iter_mut().zip(color_matching(wavelength)) {
+                *x += d65(wavelength) * m;
+            }
+        }
+        let sum: f64 = xyz.iter().sum();
+        assert!((xyz[0] / sum - 0.3127).abs() < 0.002);
+        assert!((xyz[1] / sum - 0.329).abs() < 0.002);
+    }
+
+    #[test]
+    fn samples_visible_wavelengths() {
+        assert!((sample_visible(0.) - MIN_WAVELENGTH).abs() < 0.1);
+        assert!((sample_visible(1.) - MAX_WAVELENGTH).abs() < 0.1);
+        // The density integrates to one and is the derivative of the sampled wavelength.
+        let n = 10000;
+        let mut integral = 0.;
+        for i in 0..n {
+            let u = (i as f64 + 0.5) / n as f64;
+            let wavelength = sample_visible(u);
+            let step = (sample_visible(u + 1e-6) - sample_visible(u - 1e-6)) / 2e-6;
+            assert!((visible_pdf(wavelength) * step - 1.).abs() < 1e-4);
+            integral += visible_pdf(MIN_WAVELENGTH + (i as f64 + 0.5) * 0.047) * 0.047;
+        }
+        assert!((integral - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn round_trips_colors() {
+        let white = reflected(white());
+        for c in [white.r(), white.g(), white.b()] {
+            assert!((c - 1.).abs() < 1e-3, "white became {:?}", white);
+        }
+        for &(r, g, b) in &[
+            (0.8, 0.1, 0.1),
+            (0.1, 0.6, 0.2),
+            (0.2, 0.3, 0.9),
+            (0.5, 0.4, 0.3),
+        ] {
+            let color = reflected(Color::new(r, g, b));
+            let error = [color.r() - r, color.g() - g, color.b() - b];
+            assert!(
+                error.iter().all(|e| e.abs() < 0.08),
+                "({}, {}, {}) became {:?}",
+                r,
+                g,
+                b,
+                color
+            );
+        }
+    }
+
+    #[test]
+    fn rgb_channels_are_unchanged() {
+        let color = Color::new(0.1, 0.2, 0.3);
+        assert_eq!(Channels::Rgb.reflectance(color), color);
+        assert_eq!(Channels::Rgb.to_rgb(Channels::Rgb.illuminant(color)), color);
+        assert_eq!(Channels::Rgb.wavelengths(), [SODIUM_D; 3]);
+    }
+}
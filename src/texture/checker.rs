@@ -0,0 +1,50 @@
+use super::Texture;
+use crate::color::Color;
+use crate::spaces::Point;
+use std::sync::Arc;
+
+/// Three-dimensional checkerboard of cubes with the given edge length, alternating
+/// between two textures.
+pub struct Checker {
+    even: Arc<dyn Texture + Send + Sync>,
+    odd: Arc<dyn Texture + Send + Sync>,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn new(
+        even: Arc<dyn Texture + Send + Sync>,
+        odd: Arc<dyn Texture + Send + Sync>,
+        scale: f64,
+    ) -> Self {
+        Checker { even, odd, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternates_between_cells() {
+        let black = Color::new(0., 0., 0.);
+        let white = Color::new(1., 1., 1.);
+        let checker = Checker::new(white.into(), black.into(), 0.5);
+        let at = |x, y, z| checker.value(0., 0., &Point::new(x, y, z));
+        assert_eq!(at(0.1, 0.1, 0.1), white);
+        assert_eq!(at(0.6, 0.1, 0.1), black);
+        assert_eq!(at(0.6, 0.6, 0.1), white);
+        assert_eq!(at(-0.1, 0.1, 0.1), black);
+    }
+}
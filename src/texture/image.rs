@@ -0,0 +1,81 @@
+use super::Texture;
+use crate::color::{srgb_to_linear, Color};
+use crate::spaces::Point;
+use image::ImageResult;
+use std::path::Path;
+
+/// Picture mapped onto the surface coordinates, with `(0, 0)` in its lower left corner.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // Linear colors of the pixels, row by row from the top.
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// Loads an 8-bit image in any of the formats supported by the `image` crate and
+    /// converts its sRGB values to linear colors.
+    pub fn load(path: &Path) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb8();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                let channel = |c: u8| srgb_to_linear(c as f64 / 255.);
+                Color::new(channel(p[0]), channel(p[1]), channel(p[2]))
+            })
+            .collect();
+        Ok(ImageTexture {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        if self.pixels.is_empty() {
+            // Cyan makes missing texture data obvious.
+            return Color::new(0., 1., 1.);
+        }
+        let u = u.clamp(0., 1.);
+        let v = 1. - v.clamp(0., 1.);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_corners_to_linear_colors() {
+        // Rows from the top: red and green, then blue and gray.
+        let mut picture = image::RgbImage::new(2, 2);
+        picture.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        picture.put_pixel(1, 0, image::Rgb([0, 188, 0]));
+        picture.put_pixel(0, 1, image::Rgb([0, 0, 255]));
+        picture.put_pixel(1, 1, image::Rgb([188, 188, 188]));
+        let path = std::env::temp_dir().join(format!("manta-{}-texture.png", std::process::id()));
+        picture.save(&path).unwrap();
+        let texture = ImageTexture::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let texture = texture.unwrap();
+
+        let p = Point::new(0., 0., 0.);
+        let gray = srgb_to_linear(188. / 255.);
+        assert!((gray - 0.5).abs() < 0.01);
+        // (0, 0) is the lower left corner.
+        assert_eq!(texture.value(0.1, 0.1, &p), Color::new(0., 0., 1.));
+        assert_eq!(texture.value(0.9, 0.1, &p), Color::new(gray, gray, gray));
+        assert_eq!(texture.value(0.1, 0.9, &p), Color::new(1., 0., 0.));
+        assert_eq!(texture.value(0.9, 0.9, &p), Color::new(0., gray, 0.));
+        // Coordinates beyond the edges take the nearest pixels.
+        assert_eq!(texture.value(-1., 2., &p), Color::new(1., 0., 0.));
+        assert_eq!(texture.value(1., 0., &p), Color::new(gray, gray, gray));
+        assert_eq!(texture.value(5., -3., &p), Color::new(gray, gray, gray));
+    }
+}
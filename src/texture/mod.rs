@@ -0,0 +1,25 @@
+pub mod checker;
+pub mod image;
+pub mod noise;
+pub mod solid;
+
+pub use self::image::ImageTexture;
+pub use checker::Checker;
+pub use noise::{Marble, Noise, Perlin};
+pub use solid::SolidColor;
+
+use crate::color::Color;
+use crate::spaces::Point;
+use std::sync::Arc;
+
+/// Color varying over a surface, given by the surface coordinates and the position of
+/// a point.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color;
+}
+
+impl From<Color> for Arc<dyn Texture + Send + Sync> {
+    fn from(color: Color) -> Self {
+        Arc::new(SolidColor(color))
+    }
+}
@@ -0,0 +1,143 @@
+use super::Texture;
+use crate::color::Color;
+use crate::random;
+use crate::spaces::{FreeVec3, Point, UnitVec3, Vec3};
+use rand::seq::SliceRandom;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise of Ken Perlin with random gradients at the lattice points.
+pub struct Perlin {
+    gradients: Vec<UnitVec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| UnitVec3::random_unit_vector())
+            .collect();
+        let permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            random::with_rng(|rng| p.shuffle(rng));
+            p
+        };
+        Perlin {
+            gradients,
+            permutations: [permutation(), permutation(), permutation()],
+        }
+    }
+
+    /// Smooth noise with values in `[-1, 1]`.
+    pub fn noise(&self, p: &Point) -> f64 {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let (u, v, w) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (i, j, k) = (x.floor() as i64, y.floor() as i64, z.floor() as i64);
+
+        // Hermite smoothing of the interpolation weights.
+        let smooth = |t: f64| t * t * (3. - 2. * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut accum = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = |axis: usize, c: i64| {
+                        self.permutations[axis][(c & (POINT_COUNT as i64 - 1)) as usize]
+                    };
+                    let gradient =
+                        self.gradients[index(0, i + di) ^ index(1, j + dj) ^ index(2, k + dk)];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = FreeVec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1. - fi) * (1. - uu))
+                        * (fj * vv + (1. - fj) * (1. - vv))
+                        * (fk * ww + (1. - fk) * (1. - ww))
+                        * gradient.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sum of `depth` octaves of noise with halving amplitudes.
+    pub fn turbulence(&self, p: &Point, depth: u32) -> f64 {
+        let mut accum = 0.;
+        let mut p = *p;
+        let mut weight = 1.;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = Point::new(p.x() * 2., p.y() * 2., p.z() * 2.);
+        }
+        accum.abs()
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
+
+fn scaled(p: &Point, scale: f64) -> Point {
+    Point::new(p.x() * scale, p.y() * scale, p.z() * scale)
+}
+
+/// Grey Perlin noise of the given frequency.
+pub struct Noise {
+    perlin: Perlin,
+    scale: f64,
+}
+
+impl Noise {
+    pub fn new(scale: f64) -> Self {
+        Noise {
+            perlin: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        Color::new(1., 1., 1.) * (0.5 * (1. + self.perlin.noise(&scaled(p, self.scale))))
+    }
+}
+
+/// Marble-like veins along the z axis, perturbed by turbulence.
+pub struct Marble {
+    perlin: Perlin,
+    scale: f64,
+}
+
+impl Marble {
+    pub fn new(scale: f64) -> Self {
+        Marble {
+            perlin: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let phase = self.scale * p.z() + 10. * self.perlin.turbulence(p, 7);
+        Color::new(0.5, 0.5, 0.5) * (1. + phase.sin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_bounded_and_vanishes_on_lattice() {
+        let perlin = Perlin::new();
+        for i in 0..1000 {
+            let x = i as f64 * 0.137;
+            let p = Point::new(x, x * 0.71 - 3., 5. - x * 1.3);
+            assert!(perlin.noise(&p).abs() <= 1.);
+        }
+        assert_eq!(perlin.noise(&Point::new(3., -2., 7.)), 0.);
+    }
+}
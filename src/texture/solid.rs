@@ -0,0 +1,11 @@
+use super::Texture;
+use crate::color::Color;
+use crate::spaces::Point;
+
+pub struct SolidColor(pub Color);
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        self.0
+    }
+}
@@ -0,0 +1,220 @@
+//! Mapping of the radiance of rendered images to the range of displays.
+use crate::color::{linear_to_srgb, Color};
+
+/// Curves compressing radiance into the range of displays.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// Cuts off radiance above 1, which blows out highlights.
+    Clamp,
+    /// Reinhard's operator on the luminance, which keeps the hues.
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX in the polynomial approximation of Benjamin Wrensch, which
+    /// desaturates bright colors towards white.
+    Agx,
+}
+
+impl Operator {
+    pub const NAMES: &'static str = "clamp, reinhard, aces or agx";
+
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(Operator::Clamp),
+            "reinhard" => Some(Operator::Reinhard),
+            "aces" => Some(Operator::Aces),
+            "agx" => Some(Operator::Agx),
+            _ => None,
+        }
+    }
+
+    /// Maps linear radiance to display-linear values between 0 and 1.
+    pub fn apply(&self, c: Color) -> Color {
+        let c = Color::new(c.r().max(0.), c.g().max(0.), c.b().max(0.));
+        let mapped = match self {
+            Operator::Clamp => c,
+            Operator::Reinhard => c * (1. / (1. + c.luminance())),
+            Operator::Aces => {
+                let fit = |v: f64| {
+                    (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081)
+                };
+                let c = transform(&ACES_INPUT, c);
+                let c = Color::new(fit(*c.r()), fit(*c.g()), fit(*c.b()));
+                transform(&ACES_OUTPUT, c)
+            }
+            Operator::Agx => {
+                // Log-encoded exposures between these stops are mapped by a sigmoid.
+                let (min, max) = (-12.47393, 4.026069);
+                let contrast = |v: f64| {
+                    let v = (v.max(1e-10).log2().clamp(min, max) - min) / (max - min);
+                    let (v2, v4) = (v * v, v * v * v * v);
+                    15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v
+                        + 0.4298 * v2
+                        + 0.1191 * v
+                        - 0.00232
+                };
+                let c = transform(&AGX_INSET, c);
+                let c = Color::new(contrast(*c.r()), contrast(*c.g()), contrast(*c.b()));
+                let c = transform(&AGX_OUTSET, c);
+                // The sigmoid gives values encoded for a display of gamma 2.2.
+                let decode = |v: f64| v.max(0.).powf(2.2);
+                Color::new(decode(*c.r()), decode(*c.g()), decode(*c.b()))
+            }
+        };
+        let clamp = |v: f64| v.clamp(0., 1.);
+        Color::new(clamp(*mapped.r()), clamp(*mapped.g()), clamp(*mapped.b()))
+    }
+}
+
+/// From linear sRGB to the color space of the ACES fit, including the exposure it
+/// expects.
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.076, 0.90834, 0.01566],
+    [0.0284, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+fn transform(m: &[[f64; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+/// Conversion of rendered radiance to 8-bit sRGB images.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: Operator,
+    /// Exposure in stops, each doubling the radiance.
+    pub exposure: f64,
+    /// Whether to break up banding of smooth gradients by ordered dithering.
+    pub dither: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: Operator::Clamp,
+            exposure: 0.,
+            dither: false,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Display-linear values between 0 and 1 of `radiance`.
+    pub fn map(&self, radiance: Color) -> Color {
+        self.operator.apply(radiance * self.exposure.exp2())
+    }
+
+    /// 8-bit sRGB values of `radiance` seen in the pixel in column `x` and row `y`.
+    pub fn encode(&self, radiance: Color, x: usize, y: usize) -> [u8; 3] {
+        let threshold = if self.dither { bayer(x, y) } else { 0.5 };
+        let c = self.map(radiance);
+        [c.r(), c.g(), c.b()].map(|v| (255. * linear_to_srgb(*v) + threshold).floor() as u8)
+    }
+}
+
+/// Threshold between 0 and 1 of the pixel in an 8×8 Bayer matrix, which spreads
+/// consecutive thresholds as far apart as possible.
+fn bayer(x: usize, y: usize) -> f64 {
+    let mut index = 0;
+    for bit in 0..3 {
+        let (xb, yb) = ((x >> bit) & 1, (y >> bit) & 1);
+        index |= (((xb ^ yb) << 1) | yb) << (2 * (2 - bit));
+    }
+    (index as f64 + 0.5) / 64.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_compress_highlights() {
+        let operators = [
+            Operator::Clamp,
+            Operator::Reinhard,
+            Operator::Aces,
+            Operator::Agx,
+        ];
+        for &operator in &operators {
+            let grey = |v: f64| operator.apply(Color::new(v, v, v));
+            assert_eq!(
+                Operator::named(&format!("{:?}", operator).to_lowercase()),
+                Some(operator)
+            );
+            assert_eq!(grey(0.), Color::new(0., 0., 0.));
+            // Monotonic, neutral and within the range of displays.
+            let mut previous = -1.;
+            for i in 0..100 {
+                let c = grey(1e-3 * 1.15f64.powi(i));
+                assert!(*c.r() >= previous && *c.r() <= 1., "{:?}", operator);
+                assert!((c.r() - c.g()).abs() < 2e-3 && (c.r() - c.b()).abs() < 2e-3);
+                previous = *c.r();
+            }
+            // Middle grey stays within a stop of itself.
+            let middle = *grey(0.18).r();
+            assert!(middle > 0.07 && middle < 0.3, "{:?}: {}", operator, middle);
+        }
+        // Unlike clamping, the curves keep highlights apart.
+        for &operator in &operators[1..] {
+            assert!(
+                operator.apply(Color::new(4., 4., 4.)).r()
+                    < operator.apply(Color::new(8., 8., 8.)).r()
+            );
+        }
+        assert_eq!(Operator::named("filmic"), None);
+    }
+
+    #[test]
+    fn encodes_srgb() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.,
+            ..ToneMapping::default()
+        };
+        let encode = |v: f64| tone_mapping.encode(Color::new(v, v / 2., 0.), 0, 0);
+        assert_eq!(encode(0.5), [255, 188, 0]);
+        assert_eq!(encode(0.0005), [3, 2, 0]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_mean() {
+        let mut thresholds: Vec<f64> = (0..64).map(|i| bayer(i % 8, i / 8)).collect();
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (i, t) in thresholds.iter().enumerate() {
+            assert_eq!(*t, (i as f64 + 0.5) / 64.);
+        }
+
+        let tone_mapping = ToneMapping {
+            dither: true,
+            ..ToneMapping::default()
+        };
+        // A level between two 8-bit values is reached on average over the matrix.
+        let radiance = 0.2;
+        let level = 255. * linear_to_srgb(radiance);
+        let mut mean = 0.;
+        for y in 0..8 {
+            for x in 0..8 {
+                mean += tone_mapping.encode(Color::new(radiance, 0., 0.), x, y)[0] as f64 / 64.;
+            }
+        }
+        assert!((mean - level).abs() < 1. / 64., "{} {}", mean, level);
+    }
+}